@@ -87,7 +87,7 @@ impl Api {
 // not compile).
 #[hsr::async_trait::async_trait(?Send)]
 impl PetstoreApi for Api {
-    async fn get_all_pets(&self, limit: i64, filter: Option<String>) -> api::GetAllPets {
+    async fn get_all_pets(&self, limit: i32, filter: Option<String>) -> api::GetAllPets {
         let regex = if let Some(filter) = filter {
             match Regex::new(&filter) {
                 Ok(re) => re,
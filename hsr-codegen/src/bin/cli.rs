@@ -2,19 +2,39 @@ use std::path::PathBuf;
 
 use structopt::StructOpt;
 
-use hsr_codegen::generate_from_yaml_file;
+use hsr_codegen::{generate_from_spec_with_options, Options, Spec, SpecFormat};
 
 #[derive(Clone, Debug, StructOpt)]
 struct Args {
     #[structopt(parse(from_os_str))]
     spec: PathBuf,
+    /// Format of the spec ('json' or 'yaml'). Detected automatically if not given
+    #[structopt(long)]
+    format: Option<SpecFormat>,
+    /// Skip or degrade unsupported features, rather than failing
+    #[structopt(long)]
+    lenient: bool,
+    /// Reject data which matches more than one alternative of a oneOf
+    #[structopt(long)]
+    strict_one_of: bool,
+    /// Embed the object schemas referred to by an allOf as flattened fields
+    #[structopt(long)]
+    flatten_all_of: bool,
 }
 
 fn main() {
     let args = Args::from_args();
     println!("{:?}", args);
 
-    let gen = generate_from_yaml_file(args.spec).unwrap();
+    let spec = Spec::load_as(&args.spec, args.format).unwrap();
+    let options = Options::default()
+        .lenient(args.lenient)
+        .strict_one_of(args.strict_one_of)
+        .flatten_all_of(args.flatten_all_of);
+    let (gen, report) = generate_from_spec_with_options(&spec, &options).unwrap();
+    if !report.is_empty() {
+        eprintln!("{}", report);
+    }
 
     println!("{}", gen);
 }
@@ -0,0 +1,336 @@
+//! Vendor extensions (`x-rust-*`) which control code generation.
+//!
+//! openapiv3 drops extensions when it parses a spec, so we gather them from the
+//! raw document instead, keyed by their location in the spec (e.g.
+//! `components.schemas.Pet.properties.age`). Parameters are located by name
+//! rather than by position, as in `paths./pets.get.parameters.limit`.
+//!
+//! openapiv3 also drops the `enum` of a boolean, and of a schema whose values don't
+//! all fit its type, so these are gathered in the same way. Likewise the `explode`
+//! of a query parameter, and an operation's `security: []`, which openapiv3 reads
+//! the same as a missing list.
+
+use openapiv3::{Parameter, QueryStyle, ReferenceOr, Schema};
+use serde_yaml::{Mapping, Value};
+
+use crate::{component_name, ApiPath, Error, Map, Result};
+
+/// Overrides the name of the enum variant generated for a oneOf/anyOf alternative
+pub(crate) const RUST_VARIANT: &str = "x-rust-variant";
+/// Generate an unsigned type for an integer schema with a `minimum` of 0 or more
+pub(crate) const RUST_UNSIGNED: &str = "x-rust-unsigned";
+
+const PREFIX: &str = "x-rust-";
+const ENUM: &str = "enum";
+const EXPLODE: &str = "explode";
+const SECURITY: &str = "security";
+
+/// The `x-rust-*` extensions (and other dropped fields) found in a spec
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Extensions {
+    // everything gathered, by location in the spec
+    located: Map<Vec<String>, Mapping>,
+}
+
+impl Extensions {
+    /// Gather the extensions of every schema and parameter in a (raw) spec
+    pub(crate) fn gather(root: &Value) -> Self {
+        let mut extensions = Self::default();
+        extensions.visit(root, &mut Vec::new());
+        extensions
+    }
+
+    fn visit(&mut self, value: &Value, location: &mut Vec<String>) {
+        match value {
+            Value::Mapping(mapping) => {
+                let mut found = gathered(mapping);
+                let explode = mapping.get(&EXPLODE.into());
+                if let (Some(explode), true) = (explode, is_query_param(mapping)) {
+                    found.insert(EXPLODE.into(), explode.clone());
+                }
+                let security = mapping.get(&SECURITY.into());
+                if let (Some(Value::Sequence(reqs)), true) = (security, is_operation(mapping)) {
+                    if reqs.is_empty() {
+                        found.insert(SECURITY.into(), Value::Sequence(Vec::new()));
+                    }
+                }
+                if !found.is_empty() {
+                    self.located.insert(location.clone(), found);
+                }
+                for (key, value) in mapping {
+                    location.push(key_to_string(key));
+                    match (key.as_str(), value) {
+                        (Some("parameters"), Value::Sequence(params)) => {
+                            for (ix, param) in params.iter().enumerate() {
+                                let name = param.get("name").and_then(Value::as_str);
+                                location.push(name.map_or_else(|| ix.to_string(), String::from));
+                                self.visit(param, location);
+                                location.pop();
+                            }
+                        }
+                        _ => self.visit(value, location),
+                    }
+                    location.pop();
+                }
+            }
+            Value::Sequence(values) => {
+                for (ix, value) in values.iter().enumerate() {
+                    location.push(ix.to_string());
+                    self.visit(value, location);
+                    location.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Look up an extension of the schema at `path`
+    pub(crate) fn get(&self, path: &ApiPath, name: &str) -> Option<&Value> {
+        self.located
+            .get(&path.location)
+            .and_then(|found| found.get(&Value::String(name.into())))
+    }
+
+    /// Look up the `enum` of the schema at `path`, if openapiv3 dropped it
+    pub(crate) fn get_enum(&self, path: &ApiPath) -> Option<&Vec<Value>> {
+        match self.get(path, ENUM) {
+            Some(Value::Sequence(values)) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Look up the `explode` of the query parameter at `path`, which defaults
+    /// to true for the `form` style
+    pub(crate) fn get_explode(&self, param: &Parameter, path: &ApiPath) -> Result<bool> {
+        let default = matches!(
+            param,
+            Parameter::Query {
+                style: QueryStyle::Form,
+                ..
+            }
+        );
+        Ok(as_bool(EXPLODE, self.get(path, EXPLODE))?.unwrap_or(default))
+    }
+
+    /// Whether the operation at `path` allows anonymous access with `security: []`,
+    /// overriding the top-level security
+    pub(crate) fn is_anonymous(&self, path: &ApiPath) -> bool {
+        self.get(path, SECURITY).is_some()
+    }
+
+    /// Look up an extension of the oneOf/anyOf alternative at `path`.
+    /// An extension on the alternative itself takes precedence over one on the
+    /// schema it refers to
+    pub(crate) fn get_alternative(
+        &self,
+        path: &ApiPath,
+        alternative: &ReferenceOr<Schema>,
+        name: &str,
+    ) -> Option<&Value> {
+        self.get(path, name).or_else(|| match alternative {
+            ReferenceOr::Reference { reference } => {
+                let component = ApiPath::default()
+                    .push("components")
+                    .push("schemas")
+                    .push(component_name(reference)?);
+                self.get(&component, name)
+            }
+            ReferenceOr::Item(_) => None,
+        })
+    }
+}
+
+fn is_operation(mapping: &Mapping) -> bool {
+    mapping.contains_key(&"responses".into())
+}
+
+fn is_query_param(mapping: &Mapping) -> bool {
+    mapping.get(&"in".into()) == Some(&"query".into()) && mapping.contains_key(&"name".into())
+}
+
+fn key_to_string(key: &Value) -> String {
+    match key {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Expect the value of an extension to be a string
+pub(crate) fn as_str<'a>(name: &str, value: Option<&'a Value>) -> Result<Option<&'a str>> {
+    match value {
+        None => Ok(None),
+        Some(Value::String(val)) => Ok(Some(val)),
+        Some(other) => Err(Error::Validation(format!(
+            "Extension '{}' must be a string, found {:?}",
+            name, other
+        ))),
+    }
+}
+
+/// Expect the value of an extension to be a bool
+pub(crate) fn as_bool(name: &str, value: Option<&Value>) -> Result<Option<bool>> {
+    match value {
+        None => Ok(None),
+        Some(Value::Bool(val)) => Ok(Some(*val)),
+        Some(other) => Err(Error::Validation(format!(
+            "Extension '{}' must be a bool, found {:?}",
+            name, other
+        ))),
+    }
+}
+
+/// The entries of a mapping which we gather: extensions, and an `enum` which
+/// openapiv3 would drop
+fn gathered(mapping: &Mapping) -> Mapping {
+    let mut found = rust_extensions(mapping);
+    let key = Value::String(ENUM.into());
+    if let Some(Value::Sequence(values)) = mapping.get(&key) {
+        let fits: fn(&Value) -> bool = match mapping.get(&"type".into()).and_then(Value::as_str) {
+            Some("string") => Value::is_string,
+            Some("integer") => Value::is_i64,
+            Some("number") => Value::is_number,
+            _ => |_| false,
+        };
+        if !values.iter().all(fits) {
+            found.insert(key, Value::Sequence(values.clone()));
+        }
+    }
+    found
+}
+
+/// The entries of a mapping which are extensions for us
+fn rust_extensions(mapping: &Mapping) -> Mapping {
+    mapping
+        .iter()
+        .filter(|(key, _)| matches!(key.as_str(), Some(key) if key.starts_with(PREFIX)))
+        .map(|(key, val)| (key.clone(), val.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(segments: &[&str]) -> ApiPath {
+        segments
+            .iter()
+            .fold(ApiPath::default(), |path, segment| path.push(*segment))
+    }
+
+    #[test]
+    fn test_gather_extensions() {
+        let yaml = r#"
+paths: {}
+components:
+  schemas:
+    Cat:
+      type: object
+      x-rust-variant: Kitty
+    Pet:
+      oneOf:
+        - $ref: '#/components/schemas/Cat'
+        - $ref: '#/components/schemas/Cat'
+          x-rust-variant: OtherCat
+        - type: string
+          x-rust-variant: Name
+          x-other: ignored
+        - type: string
+"#;
+        let root: Value = serde_yaml::from_str(yaml).unwrap();
+        let extensions = Extensions::gather(&root);
+        let pet: Schema =
+            serde_yaml::from_value(root["components"]["schemas"]["Pet"].clone()).unwrap();
+        let alternatives = match &pet.schema_kind {
+            openapiv3::SchemaKind::OneOf { one_of } => one_of,
+            _ => unreachable!(),
+        };
+        let alternative =
+            |ix: usize| location(&["components", "schemas", "Pet", "oneOf", &ix.to_string()]);
+        let found: Vec<_> = alternatives
+            .iter()
+            .enumerate()
+            .map(|(ix, alt)| {
+                as_str(
+                    RUST_VARIANT,
+                    extensions.get_alternative(&alternative(ix), alt, RUST_VARIANT),
+                )
+                .unwrap()
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![Some("Kitty"), Some("OtherCat"), Some("Name"), None]
+        );
+        assert_eq!(
+            extensions.get_alternative(&alternative(2), &alternatives[2], "x-other"),
+            None
+        );
+
+        let bad = yaml.replace("x-rust-variant: Name", "x-rust-variant: [Name]");
+        let extensions = Extensions::gather(&serde_yaml::from_str(&bad).unwrap());
+        let found = extensions.get_alternative(&alternative(2), &alternatives[2], RUST_VARIANT);
+        assert!(as_str(RUST_VARIANT, found).is_err());
+    }
+
+    #[test]
+    fn test_gather_explode() {
+        let yaml = r#"
+paths:
+  /a:
+    parameters:
+      - name: tags
+        in: query
+        explode: false
+        schema:
+          type: array
+    get:
+      parameters:
+        - name: ids
+          in: query
+          schema:
+            type: array
+  /b:
+    get:
+      parameters:
+        - name: ids
+          in: query
+          explode: false
+          schema:
+            type: array
+        - $ref: '#/components/parameters/Labels'
+        - name: bad
+          in: query
+          explode: "no"
+          schema:
+            type: array
+components:
+  parameters:
+    Labels:
+      name: labels
+      in: query
+      explode: false
+      schema:
+        type: array
+"#;
+        let root: Value = serde_yaml::from_str(yaml).unwrap();
+        let extensions = Extensions::gather(&root);
+        let explode = |param: &Value, segments: &[&str]| {
+            let param: Parameter = serde_yaml::from_value(param.clone()).unwrap();
+            extensions.get_explode(&param, &location(segments))
+        };
+        let (a, b) = (&root["paths"]["/a"], &root["paths"]["/b"]["get"]);
+        let labels = &root["components"]["parameters"]["Labels"];
+        // parameters are located by name
+        assert!(!explode(&a["parameters"][0], &["paths", "/a", "parameters", "tags"]).unwrap());
+        assert!(!explode(labels, &["components", "parameters", "Labels"]).unwrap());
+        // the same parameter is exploded in one place but not the other
+        let ids = &a["get"]["parameters"][0];
+        assert!(explode(ids, &["paths", "/a", "get", "parameters", "ids"]).unwrap());
+        let ids = &b["parameters"][0];
+        assert!(!explode(ids, &["paths", "/b", "get", "parameters", "ids"]).unwrap());
+        let bad = &b["parameters"][2];
+        assert!(explode(bad, &["paths", "/b", "get", "parameters", "bad"]).is_err());
+    }
+}
@@ -4,7 +4,7 @@
 use std::convert::TryFrom;
 use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use actix_http::http::StatusCode;
@@ -20,6 +20,7 @@ use openapiv3::{
 use proc_macro2::{Ident as QIdent, TokenStream};
 use quote::quote;
 use regex::Regex;
+use serde::de::DeserializeOwned;
 use thiserror::Error;
 
 macro_rules! invalid {
@@ -28,9 +29,14 @@ macro_rules! invalid {
     );
 }
 
+mod extensions;
+mod load;
 mod route;
+mod security;
 mod walk;
 
+use extensions::Extensions;
+pub use load::Spec;
 use route::Route;
 
 const SWAGGER_UI_TEMPLATE: &'static str = include_str!("../ui-template.html");
@@ -47,16 +53,191 @@ pub enum Error {
     Io(#[from] std::io::Error),
     #[error("Yaml Error: {}", _0)]
     Yaml(#[from] serde_yaml::Error),
+    #[error("Json Error: {}", _0)]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to parse {} as {}: {}", spec_location(path), format, source)]
+    Parse {
+        // absent for a spec which was not read from a file
+        path: Option<PathBuf>,
+        format: SpecFormat,
+        source: Box<Error>,
+    },
     #[error("Codegen failed: {}", _0)]
     BadCodegen(String),
     #[error("Bad reference: {}", _0)]
     BadReference(String),
+    #[error("Cyclic reference: {}", _0)]
+    CyclicReference(String),
     #[error("OpenAPI validation failed: {}", _0)]
     Validation(String),
+    #[error("{} not supported (location: '{}')", construct, path)]
+    Unsupported { path: ApiPath, construct: String },
+    #[error("{} errors found:\n{}", _0.len(), display_errors(_0))]
+    Multiple(Vec<Error>),
+}
+
+impl Error {
+    /// Turn a list of errors into a single error, if there are any
+    fn collect(mut errors: Vec<Error>) -> Result<()> {
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(Error::Multiple(errors)),
+        }
+    }
+}
+
+fn spec_location(path: &Option<PathBuf>) -> String {
+    match path {
+        Some(path) => format!("'{}'", path.display()),
+        None => "spec".into(),
+    }
+}
+
+fn display_errors(errors: &[Error]) -> String {
+    let lines: Vec<_> = errors.iter().map(|e| format!("  - {}", e)).collect();
+    lines.join("\n")
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Options controlling code generation
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    lenient: bool,
+    strict_one_of: bool,
+    flatten_all_of: bool,
+}
+
+impl Options {
+    /// In lenient mode, unsupported features do not cause generation to fail.
+    /// Schemas which can't be represented fall back to `JsonValue`,
+    /// and operations which can't be represented are left out.
+    /// Everything which was degraded or skipped is listed in the `Report`.
+    pub fn lenient(self, lenient: bool) -> Self {
+        Self { lenient, ..self }
+    }
+
+    /// In strict oneOf mode, data which fits more than one alternative of a
+    /// (non-discriminated) oneOf is rejected, as the spec requires.
+    /// Otherwise the first alternative which fits is taken
+    pub fn strict_one_of(self, strict_one_of: bool) -> Self {
+        Self {
+            strict_one_of,
+            ..self
+        }
+    }
+
+    /// By default, the properties of every part of an allOf are copied into one struct.
+    /// With this option, parts which refer to other object schemas are embedded instead,
+    /// as a `#[serde(flatten)]` field named after the referenced schema
+    pub fn flatten_all_of(self, flatten_all_of: bool) -> Self {
+        Self {
+            flatten_all_of,
+            ..self
+        }
+    }
+}
+
+/// Lists everything which could not be generated faithfully. Apart from unknown
+/// string formats, which fall back to `String`, these are errors unless in lenient mode
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Unsupported constructs which were ignored, or replaced with `JsonValue` or `String`
+    pub degraded: Vec<Error>,
+    /// Operations which were left out, along with the reasons why
+    pub skipped: Vec<(String, Vec<Error>)>,
+}
+
+impl Report {
+    pub fn is_empty(&self) -> bool {
+        self.degraded.is_empty() && self.skipped.is_empty()
+    }
+
+    /// Print the report as warnings which cargo will show to the user.
+    /// Intended to be called from `build.rs`.
+    pub fn emit_cargo_warnings(&self) {
+        for line in self.to_string().lines() {
+            println!("cargo:warning={}", line);
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "Everything was generated");
+        }
+        writeln!(
+            f,
+            "{} constructs degraded, {} operations skipped",
+            self.degraded.len(),
+            self.skipped.len()
+        )?;
+        for err in &self.degraded {
+            writeln!(f, "  degraded: {}", err)?;
+        }
+        for (operation, errs) in &self.skipped {
+            writeln!(f, "  skipped '{}':", operation)?;
+            for err in errs {
+                writeln!(f, "    {}", err)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The serialization format of an OpenAPI document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum SpecFormat {
+    #[display(fmt = "YAML")]
+    Yaml,
+    #[display(fmt = "JSON")]
+    Json,
+}
+
+impl SpecFormat {
+    /// Guess the format from the file extension, if it is a known one
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let ext = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "json" => Some(SpecFormat::Json),
+            "yaml" | "yml" => Some(SpecFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    /// Guess the format by looking at the document itself.
+    /// An OpenAPI document is always an object, so a JSON
+    /// document must begin with a '{'. Anything else we treat as YAML.
+    pub fn detect(source: &str) -> Self {
+        if source.trim_start().starts_with('{') {
+            SpecFormat::Json
+        } else {
+            SpecFormat::Yaml
+        }
+    }
+
+    fn parse<T: DeserializeOwned>(self, source: &str) -> Result<T> {
+        let api = match self {
+            SpecFormat::Yaml => serde_yaml::from_str(source)?,
+            SpecFormat::Json => serde_json::from_str(source)?,
+        };
+        Ok(api)
+    }
+}
+
+impl FromStr for SpecFormat {
+    type Err = Error;
+    fn from_str(val: &str) -> Result<Self> {
+        match val.to_lowercase().as_str() {
+            "json" => Ok(SpecFormat::Json),
+            "yaml" | "yml" => Ok(SpecFormat::Yaml),
+            _ => invalid!("Unknown spec format '{}' (expected 'json' or 'yaml')", val),
+        }
+    }
+}
+
 /// Unwrap the reference, or fail
 /// TODO get rid of this
 fn unwrap_ref<T>(item: &ReferenceOr<T>) -> Result<&T> {
@@ -68,15 +249,57 @@ fn unwrap_ref<T>(item: &ReferenceOr<T>) -> Result<&T> {
 
 /// Fetch reference target via a lookup
 fn dereference<'a, T>(
-    refr: &'a ReferenceOr<T>,
+    mut refr: &'a ReferenceOr<T>,
     lookup: &'a Map<String, ReferenceOr<T>>,
 ) -> Result<&'a T> {
-    match refr {
-        ReferenceOr::Reference { reference } => lookup
-            .get(reference)
-            .ok_or_else(|| Error::BadReference(reference.to_string()))
-            .and_then(|refr| dereference(refr, lookup)),
-        ReferenceOr::Item(item) => Ok(item),
+    let mut chain: Vec<&str> = Vec::new();
+    loop {
+        match refr {
+            ReferenceOr::Reference { reference } => {
+                if chain.contains(&reference.as_str()) {
+                    chain.push(reference);
+                    return Err(Error::CyclicReference(chain.join(" -> ")));
+                }
+                chain.push(reference);
+                refr = component_name(reference)
+                    .and_then(|name| lookup.get(name))
+                    .ok_or_else(|| Error::BadReference(reference.to_string()))?;
+            }
+            ReferenceOr::Item(item) => return Ok(item),
+        }
+    }
+}
+
+/// Fetch reference target via a lookup, along with the location of its definition:
+/// `path` for an inline item, or `components.<section>.<name>` for a reference
+fn dereference_at<'a, T>(
+    refr: &'a ReferenceOr<T>,
+    lookup: &'a Map<String, ReferenceOr<T>>,
+    section: &str,
+    path: ApiPath,
+) -> Result<(&'a T, ApiPath)> {
+    let item = dereference(refr, lookup)?;
+    let mut path = path;
+    let mut refr = refr;
+    // the chain has already been checked by `dereference`
+    while let ReferenceOr::Reference { reference } = refr {
+        let name = component_name(reference).unwrap();
+        let component = ApiPath::default()
+            .push("components")
+            .push(section)
+            .push(name);
+        path = path.relocate(&component);
+        refr = &lookup[name];
+    }
+    Ok((item, path))
+}
+
+/// Extract the name from a reference of the form `#/components/<kind>/<name>`
+fn component_name(reference: &str) -> Option<&str> {
+    let parts: Vec<&str> = reference.splitn(4, '/').collect();
+    match &parts[..] {
+        ["#", "components", _kind, name] => Some(name),
+        _ => None,
     }
 }
 
@@ -156,6 +379,13 @@ impl Method {
         Ok(meth)
     }
 
+    fn body_type_mut(&mut self) -> Option<&mut TypePath> {
+        match self {
+            Method::WithoutBody(_) => None,
+            Method::WithBody { body_type, .. } => body_type.as_mut(),
+        }
+    }
+
     fn body_type(&self) -> Option<&TypePath> {
         match self {
             Method::WithoutBody(_)
@@ -230,9 +460,47 @@ impl FromStr for Ident {
     }
 }
 
+impl Ident {
+    /// Convert a property name into a snake_case field name. Anything other than
+    /// ASCII letters and digits separates words, and a leading digit is prefixed
+    fn from_property(name: &str) -> Self {
+        let snake = name.to_snake_case();
+        let words: Vec<&str> = snake
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect();
+        let mut field = words.join("_");
+        if field.is_empty() {
+            field = "field".into();
+        } else if field.starts_with(|c: char| c.is_ascii_digit()) {
+            field = format!("field_{}", field);
+        }
+        if UNRAW_KEYWORDS.contains(&field.as_str()) {
+            field.push('_');
+        }
+        Ident(field)
+    }
+}
+
+/// Keywords are written as raw identifiers, e.g. `r#type`
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop",
+    "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "static",
+    "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual",
+    "where", "while", "yield",
+];
+
+/// Keywords which cannot be raw identifiers
+const UNRAW_KEYWORDS: &[&str] = &["crate", "self", "Self", "super"];
+
 impl quote::ToTokens for Ident {
     fn to_tokens(&self, tokens: &mut TokenStream) {
-        let id = ident(&self.0);
+        let id = if KEYWORDS.contains(&self.0.as_str()) {
+            QIdent::new_raw(&self.0, proc_macro2::Span::call_site())
+        } else {
+            ident(&self.0)
+        };
         id.to_tokens(tokens)
     }
 }
@@ -240,27 +508,58 @@ impl quote::ToTokens for Ident {
 /// An ApiPath represents a nested location within the OpenAPI object.
 /// It can be used to keep track of where resources (particularly type
 /// definitions) are located.
+///
+/// Types are named after the path, which need not follow the spec exactly
+/// (e.g. it includes the operationId), so the location in the spec document
+/// is kept alongside it. This is what is displayed
 #[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
-pub(crate) struct ApiPath {
+pub struct ApiPath {
     path: Vec<String>,
+    location: Vec<String>,
 }
 
 impl ApiPath {
+    /// Descend into the spec, also adding to the names of types defined within
     fn push(mut self, s: impl Into<String>) -> Self {
+        let s = s.into();
+        self.location.push(s.clone());
+        self.path.push(s);
+        self
+    }
+
+    /// Add to the names of types defined within, without descending into the spec
+    fn push_name(mut self, s: impl Into<String>) -> Self {
         self.path.push(s.into());
         self
     }
+
+    /// Descend into the spec, without adding to the names of types defined within
+    fn push_location(mut self, s: impl Into<String>) -> Self {
+        self.location.push(s.into());
+        self
+    }
+
+    /// Move to where something is defined elsewhere in the spec, e.g. a referenced
+    /// component, keeping the names of types defined within
+    fn relocate(mut self, to: &ApiPath) -> Self {
+        self.location = to.location.clone();
+        self
+    }
 }
 
+// A type only knows its name, so that stands in for its location
 impl From<TypePath> for ApiPath {
     fn from(path: TypePath) -> Self {
-        Self { path: path.0 }
+        Self {
+            location: path.0.clone(),
+            path: path.0,
+        }
     }
 }
 
 impl std::fmt::Display for ApiPath {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
-        let joined = self.path.join(".");
+        let joined = self.location.join(".");
         write!(f, "{}", joined)
     }
 }
@@ -279,6 +578,11 @@ impl From<ApiPath> for TypePath {
 }
 
 impl TypePath {
+    /// Whether this type was defined somewhere within `path`
+    pub(crate) fn starts_with(&self, path: &ApiPath) -> bool {
+        self.0.starts_with(&path.path)
+    }
+
     pub(crate) fn from_reference(refr: &str) -> Result<Self> {
         let rx = Regex::new("^#/components/schemas/([[:alnum:]]+)$").unwrap();
         let cap = rx
@@ -293,6 +597,25 @@ impl TypePath {
         Ok(Self(path))
     }
 
+    /// The path of a variant of this type, named by adding a suffix to its last part
+    pub(crate) fn with_suffix(&self, suffix: &str) -> Self {
+        let mut path = self.0.clone();
+        if let Some(last) = path.last_mut() {
+            last.push_str(suffix);
+        }
+        Self(path)
+    }
+
+    /// The reference to this type, if it is defined in the component schemas
+    pub(crate) fn to_reference(&self) -> Option<String> {
+        match &self.0[..] {
+            [components, schemas, name] if components == "components" && schemas == "schemas" => {
+                Some(format!("#/components/schemas/{}", name))
+            }
+            _ => None,
+        }
+    }
+
     // Turn an TypePath into a TypeName, which generally
     // will be the name actually used for a type definition
     pub(crate) fn canonicalize(&self) -> TypeName {
@@ -338,67 +661,92 @@ impl quote::ToTokens for TypeName {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-enum PathSegment {
+enum PathPart {
     Literal(String),
     Parameter(String),
 }
 
+/// A path template, e.g. `/v1/files/{name}.json`. A segment may mix literal text
+/// and parameters
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub(crate) struct RoutePath {
-    segments: Vec<PathSegment>,
+    parts: Vec<PathPart>,
 }
 
 impl fmt::Display for RoutePath {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut path = String::new();
-        for segment in &self.segments {
-            match segment {
-                PathSegment::Literal(p) => {
-                    path.push('/');
-                    path.push_str(&p);
-                }
-                PathSegment::Parameter(p) => {
-                    path.push_str(&format!("/{{{}}}", p));
-                }
+        for part in &self.parts {
+            match part {
+                PathPart::Literal(p) => write!(f, "{}", p)?,
+                PathPart::Parameter(p) => write!(f, "{{{}}}", p)?,
             }
         }
-        write!(f, "{}", path)
+        Ok(())
     }
 }
 
 impl RoutePath {
-    /// Check a path is well-formed and break it into its respective `PathSegment`s
+    /// Check a path is well-formed and break it into its respective `PathPart`s
     fn analyse(path: &str) -> Result<RoutePath> {
         // "An alpha optionally followed by any of (alpha, number or _)"
-        let literal_re = Regex::new("^[[:alpha:]]([[:alnum:]]|_)*$").unwrap();
-        let param_re = Regex::new(r#"^\{([[:alpha:]]([[:alnum:]]|_)*)\}$"#).unwrap();
+        let param_re = Regex::new("^[[:alpha:]]([[:alnum:]]|_)*$").unwrap();
+        // The characters allowed in a path segment (RFC 3986), except for '*' and '%'
+        // as actix gives them special meaning
+        let literal_char = |c: char| c.is_ascii_alphanumeric() || "-._~!$&'()+,;=:@".contains(c);
 
         if !path.starts_with('/') {
             invalid!("Bad path '{}' (must start with '/')", path);
         }
 
-        let mut segments = Vec::new();
-
+        let mut parts = Vec::new();
+        let mut literal = String::new();
         let mut dupe_params = Set::new();
-        for segment in path.split('/').skip(1) {
-            if literal_re.is_match(segment) {
-                segments.push(PathSegment::Literal(segment.to_string()))
-            } else if let Some(seg) = param_re.captures(segment) {
-                let param = seg.get(1).unwrap().as_str().to_string();
-                if !dupe_params.insert(param.clone()) {
-                    invalid!("Duplicate parameter in path '{}'", path);
+        let segments: Vec<&str> = path.split('/').skip(1).collect();
+        for (ix, segment) in segments.iter().enumerate() {
+            // only the last segment may be empty, i.e. a trailing '/'
+            if segment.is_empty() && ix + 1 != segments.len() {
+                invalid!("Bad path '{}' (empty segment)", path);
+            }
+            literal.push('/');
+            let mut rest = *segment;
+            while !rest.is_empty() {
+                if rest.starts_with('{') {
+                    let end = match rest.find('}') {
+                        Some(end) => end,
+                        None => invalid!("Bad path '{}' (unclosed parameter)", path),
+                    };
+                    let param = &rest[1..end];
+                    if !param_re.is_match(param) {
+                        invalid!("Bad path '{}' (bad parameter name '{}')", path, param);
+                    }
+                    if literal.is_empty() {
+                        invalid!("Bad path '{}' (adjacent parameters are ambiguous)", path);
+                    }
+                    if !dupe_params.insert(param.to_string()) {
+                        invalid!("Duplicate parameter in path '{}'", path);
+                    }
+                    parts.push(PathPart::Literal(std::mem::take(&mut literal)));
+                    parts.push(PathPart::Parameter(param.to_string()));
+                    rest = &rest[end + 1..];
+                } else {
+                    let end = rest.find('{').unwrap_or(rest.len());
+                    if let Some(c) = rest[..end].chars().find(|c| !literal_char(*c)) {
+                        invalid!("Bad path '{}' (unexpected character '{}')", path, c);
+                    }
+                    literal.push_str(&rest[..end]);
+                    rest = &rest[end..];
                 }
-                segments.push(PathSegment::Parameter(param))
-            } else {
-                invalid!("Bad path '{}'", path);
             }
         }
-        Ok(RoutePath { segments })
+        if !literal.is_empty() {
+            parts.push(PathPart::Literal(literal));
+        }
+        Ok(RoutePath { parts })
     }
 
     fn path_args(&self) -> impl Iterator<Item = &str> {
-        self.segments.iter().filter_map(|s| {
-            if let PathSegment::Parameter(ref p) = s {
+        self.parts.iter().filter_map(|s| {
+            if let PathPart::Parameter(ref p) = s {
                 Some(p.as_ref())
             } else {
                 None
@@ -435,6 +783,8 @@ pub(crate) struct TypeMetadata {
     description: Option<String>,
     nullable: bool,
     visibility: Visibility,
+    // the default value, as json
+    default: Option<String>,
 }
 
 impl TypeMetadata {
@@ -465,6 +815,7 @@ impl From<openapiv3::SchemaData> for TypeMetadata {
             description: from.description,
             nullable: from.nullable,
             visibility: Visibility::Public,
+            default: from.default.map(|val| val.to_string()),
         }
     }
 }
@@ -473,12 +824,99 @@ impl From<openapiv3::SchemaData> for TypeMetadata {
 pub(crate) struct FieldMetadata {
     description: Option<String>,
     required: bool,
+    // name of the field on the wire, if it differs from the Rust identifier
+    rename: Option<String>,
+    // only sent in responses (readOnly) or only in requests (writeOnly)
+    read_only: bool,
+    write_only: bool,
+    // the fields of the value are serialized as if they were fields of the struct
+    flatten: bool,
+    // how a query parameter is serialized
+    query: Option<QueryParam>,
 }
 
 impl FieldMetadata {
     fn with_required(self, required: bool) -> Self {
         Self { required, ..self }
     }
+
+    fn with_rename(self, rename: String) -> Self {
+        Self {
+            rename: Some(rename),
+            ..self
+        }
+    }
+
+    fn with_access(self, read_only: bool, write_only: bool) -> Self {
+        Self {
+            read_only,
+            write_only,
+            ..self
+        }
+    }
+
+    fn with_query(self, query: QueryParam) -> Self {
+        Self {
+            query: Some(query),
+            ..self
+        }
+    }
+
+    fn flattened() -> Self {
+        Self {
+            required: true,
+            flatten: true,
+            ..Self::default()
+        }
+    }
+}
+
+/// How a query parameter is serialized, as described by `hsr::QueryParam`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct QueryParam {
+    style: QueryStyle,
+    object: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QueryStyle {
+    Form { explode: bool },
+    SpaceDelimited,
+    PipeDelimited,
+    DeepObject,
+}
+
+impl QueryParam {
+    fn new(style: &openapiv3::QueryStyle, explode: bool, object: bool) -> Self {
+        use openapiv3::QueryStyle::*;
+        // exploded delimited parameters are no different to exploded form parameters
+        let style = match (style, explode) {
+            (Form, explode)
+            | (SpaceDelimited, explode @ true)
+            | (PipeDelimited, explode @ true) => QueryStyle::Form { explode },
+            (SpaceDelimited, _) => QueryStyle::SpaceDelimited,
+            (PipeDelimited, _) => QueryStyle::PipeDelimited,
+            (DeepObject, _) => QueryStyle::DeepObject,
+        };
+        Self { style, object }
+    }
+
+    fn to_tokens(self, name: &str) -> TokenStream {
+        let style = match self.style {
+            QueryStyle::Form { explode } => quote! { Form { explode: #explode } },
+            QueryStyle::SpaceDelimited => quote! { SpaceDelimited },
+            QueryStyle::PipeDelimited => quote! { PipeDelimited },
+            QueryStyle::DeepObject => quote! { DeepObject },
+        };
+        let object = self.object;
+        quote! {
+            hsr::QueryParam {
+                name: #name,
+                style: hsr::QueryStyle::#style,
+                object: #object,
+            }
+        }
+    }
 }
 
 pub(crate) fn variant_from_status_code(code: &StatusCode) -> Ident {
@@ -512,10 +950,16 @@ fn generate_rust_interface(
             methods.extend(route.generate_api_signature());
         }
     }
+    // secured operations are passed the principal found by the authenticator
+    let authenticator_opt = if security::is_secured(routes) {
+        Some(quote! { hsr::Authenticator + })
+    } else {
+        None
+    };
     quote! {
         #descr
         #[hsr::async_trait::async_trait(?Send)]
-        pub trait #trait_name: 'static + Send + Sync {
+        pub trait #trait_name: #authenticator_opt 'static + Send + Sync {
             #methods
         }
     }
@@ -524,14 +968,15 @@ fn generate_rust_interface(
 fn generate_rust_dispatchers(
     routes: &Map<String, Vec<Route>>,
     trait_name: &TypeName,
-) -> TokenStream {
+    lookup: &walk::TypeLookup,
+) -> Result<TokenStream> {
     let mut dispatchers = TokenStream::new();
     for (_api_path, route_methods) in routes {
         for route in route_methods {
-            dispatchers.extend(route.generate_dispatcher(trait_name));
+            dispatchers.extend(route.generate_dispatcher(trait_name, lookup)?);
         }
     }
-    quote! {#dispatchers}
+    Ok(quote! {#dispatchers})
 }
 
 fn generate_rust_server(routemap: &Map<String, Vec<Route>>, trait_name: &TypeName) -> TokenStream {
@@ -599,28 +1044,35 @@ fn generate_rust_server(routemap: &Map<String, Vec<Route>>, trait_name: &TypeNam
     server
 }
 
-fn generate_rust_client(routes: &Map<String, Vec<Route>>) -> TokenStream {
+fn generate_rust_client(
+    routes: &Map<String, Vec<Route>>,
+    lookup: &walk::TypeLookup,
+) -> Result<TokenStream> {
     let mut method_impls = TokenStream::new();
     for (_, route_methods) in routes {
         for route in route_methods {
-            method_impls.extend(route.generate_client_impl());
+            method_impls.extend(route.generate_client_impl(lookup)?);
         }
     }
+    let credential_setters = security::generate_client_setters(routes);
 
-    quote! {
+    Ok(quote! {
         #[allow(dead_code)]
         #[allow(unused_imports)]
         pub mod client {
             use super::*;
-            use hsr::actix_http::http::Method;
+            use hsr::actix_http::{cookie::Cookie, http::Method};
             use hsr::awc::Client as ActixClient;
             use hsr::ClientError;
             use hsr::futures::future::{err as fut_err, ok as fut_ok};
             use hsr::serde_urlencoded;
 
+            #[derive(Clone)]
             pub struct Client {
                 domain: Url,
                 inner: ActixClient,
+                credentials: std::collections::HashMap<&'static str, hsr::Credential>,
+                validate: bool,
             }
 
             impl Client {
@@ -628,28 +1080,99 @@ fn generate_rust_client(routes: &Map<String, Vec<Route>>) -> TokenStream {
                 pub fn new(domain: Url) -> Self {
                     Client {
                         domain: domain,
-                        inner: ActixClient::new()
+                        inner: ActixClient::new(),
+                        credentials: Default::default(),
+                        validate: false,
                     }
                 }
 
+                /// Check requests against the constraints of the spec before sending them.
+                /// Requests which break them fail with `ClientError::Invalid`
+                pub fn validate_requests(mut self, validate: bool) -> Self {
+                    self.validate = validate;
+                    self
+                }
+
+                #credential_setters
+
                 #method_impls
             }
         }
-    }
+    })
+}
+
+/// Generate code from an OpenAPI spec file.
+///
+/// The format is chosen by file extension (`.json`, `.yaml` or `.yml`),
+/// falling back to inspecting the contents of the file.
+/// References to other files are followed, relative to the file they appear in.
+pub fn generate_from_file(path: impl AsRef<Path>) -> Result<String> {
+    generate_from_spec(&Spec::load(path)?)
 }
 
 pub fn generate_from_yaml_file(yaml: impl AsRef<Path>) -> Result<String> {
-    // TODO add generate_from_json_file
-    let f = fs::File::open(yaml)?;
-    generate_from_yaml_source(f)
+    generate_from_spec(&Spec::load_as(yaml, Some(SpecFormat::Yaml))?)
 }
 
-pub fn generate_from_yaml_source(mut yaml: impl std::io::Read) -> Result<String> {
-    // Read the yaml file into an OpenAPI struct
+pub fn generate_from_json_file(json: impl AsRef<Path>) -> Result<String> {
+    generate_from_spec(&Spec::load_as(json, Some(SpecFormat::Json))?)
+}
+
+/// Parse a spec which was read from `path`, so that errors say where they came from
+fn parse_file<T: DeserializeOwned>(path: &Path, source: &str, format: SpecFormat) -> Result<T> {
+    format.parse(source).map_err(|e| Error::Parse {
+        path: Some(path.into()),
+        format,
+        source: Box::new(e),
+    })
+}
+
+pub fn generate_from_yaml_source(yaml: impl std::io::Read) -> Result<String> {
+    generate_from_source(yaml, Some(SpecFormat::Yaml))
+}
+
+pub fn generate_from_json_source(json: impl std::io::Read) -> Result<String> {
+    generate_from_source(json, Some(SpecFormat::Json))
+}
+
+/// Generate code from an OpenAPI spec in the given format.
+/// If no format is given, it will be detected from the contents.
+pub fn generate_from_source(
+    mut source: impl std::io::Read,
+    format: Option<SpecFormat>,
+) -> Result<String> {
     let mut openapi_source = String::new();
-    yaml.read_to_string(&mut openapi_source)?;
-    let api: OpenAPI = serde_yaml::from_str(&openapi_source)?;
+    source.read_to_string(&mut openapi_source)?;
+    let format = format.unwrap_or_else(|| SpecFormat::detect(&openapi_source));
+    // the format may have been detected, so errors say which one failed
+    let parse_error = |e: Error| Error::Parse {
+        path: None,
+        format,
+        source: Box::new(e),
+    };
+    let root: serde_yaml::Value = format.parse(&openapi_source).map_err(parse_error)?;
+    let extensions = Extensions::gather(&root);
+    let api = serde_yaml::from_value(root).map_err(|e| parse_error(e.into()))?;
+    generate_from_api(&api, &extensions, &Options::default()).map(|(code, _)| code)
+}
+
+/// Generate code from a spec which has already been loaded
+pub fn generate_from_spec(spec: &Spec) -> Result<String> {
+    generate_from_spec_with_options(spec, &Options::default()).map(|(code, _)| code)
+}
 
+/// Generate code from a spec which has already been loaded.
+/// Also returns a report of anything which could not be generated faithfully
+/// (which will be mostly empty unless `Options::lenient` is set).
+pub fn generate_from_spec_with_options(spec: &Spec, options: &Options) -> Result<(String, Report)> {
+    generate_from_api(&spec.api, &spec.extensions, options)
+}
+
+fn generate_from_api(
+    api: &OpenAPI,
+    extensions: &Extensions,
+    options: &Options,
+) -> Result<(String, Report)> {
     // pull out various sections of the OpenAPI object which will be useful
     // let components = api.components.take().unwrap_or_default();
     // let schema_lookup = components.schemas;
@@ -658,17 +1181,19 @@ pub fn generate_from_yaml_source(mut yaml: impl std::io::Read) -> Result<String>
     // let req_body_lookup = components.request_bodies;
 
     // Generate the spec as json. This will be embedded in the binary
-    let json_spec = serde_json::to_string(&api).expect("Bad api serialization");
+    let json_spec = serde_json::to_string(api).expect("Bad api serialization");
 
-    let trait_name = api_trait_name(&api);
+    let trait_name = api_trait_name(api);
 
     // Walk the API to collect types and routes
     debug!("Gather types");
-    let (type_lookup, routes) = walk::walk_api(&api)?;
+    let mut diagnostics = walk::Diagnostics::new(options.lenient);
+    let (type_lookup, routes) = walk::walk_api(api, extensions, &mut diagnostics)?;
 
     // Generate type definitions
     debug!("Generate API types");
-    let rust_api_types = walk::generate_rust_types(&type_lookup)?;
+    let rust_api_types = walk::generate_rust_types(&type_lookup, options, &mut diagnostics)?;
+    let report = diagnostics.into_report();
 
     // Response types are slightly special cases (they need to implement Responder
     debug!("Generate response types");
@@ -682,13 +1207,16 @@ pub fn generate_from_yaml_source(mut yaml: impl std::io::Read) -> Result<String>
     let rust_trait = generate_rust_interface(&routes, &api.info.title, &trait_name);
 
     debug!("Generate dispatchers");
-    let rust_dispatchers = generate_rust_dispatchers(&routes, &trait_name);
+    let rust_dispatchers = generate_rust_dispatchers(&routes, &trait_name, &type_lookup)?;
+
+    debug!("Generate security requirements");
+    let rust_security = security::generate_rust_security(&routes);
 
     debug!("Generate server");
     let rust_server = generate_rust_server(&routes, &trait_name);
 
     debug!("Generate client");
-    let rust_client = generate_rust_client(&routes);
+    let rust_client = generate_rust_client(&routes, &type_lookup)?;
 
     let code = quote! {
         #[allow(dead_code)]
@@ -698,11 +1226,14 @@ pub fn generate_from_yaml_source(mut yaml: impl std::io::Read) -> Result<String>
         const UI_TEMPLATE: &'static str = #SWAGGER_UI_TEMPLATE;
 
         mod __imports {
-            pub use hsr::HasStatusCode;
+            pub use hsr::{
+                Cookies as AxCookies, HasStatusCode, Headers as AxHeaders, PathParams as AxPath,
+                QueryParams as AxQuery,
+            };
             pub use hsr::actix_web::{
                 self, App, HttpServer, HttpRequest, HttpResponse, Responder, Either as AxEither,
                 Error as ActixError,
-                web::{self, Json as AxJson, Query as AxQuery, Path as AxPath, Data as AxData, ServiceConfig},
+                web::{self, Json as AxJson, Data as AxData, ServiceConfig},
                 dev::HttpResponseBuilder,
                 middleware::Logger
             };
@@ -722,6 +1253,8 @@ pub fn generate_from_yaml_source(mut yaml: impl std::io::Read) -> Result<String>
         #(#rust_response_types)*
         // Interface definition
         #rust_trait
+        // Security requirements
+        #rust_security
         // Dispatcher definitions
         #rust_dispatchers
         // Server
@@ -731,14 +1264,11 @@ pub fn generate_from_yaml_source(mut yaml: impl std::io::Read) -> Result<String>
     };
     let code = code.to_string();
     #[cfg(feature = "pretty")]
-    {
+    let code = {
         debug!("Prettify");
-        prettify_code(code)
-    }
-    #[cfg(not(feature = "pretty"))]
-    {
-        Ok(code)
-    }
+        prettify_code(code)?
+    };
+    Ok((code, report))
 }
 
 /// Run the code through `rustfmt`.
@@ -797,30 +1327,36 @@ mod tests {
 
     #[test]
     fn test_analyse_path() {
-        use PathSegment::*;
+        use PathPart::*;
 
         // Should fail
         assert!(RoutePath::analyse("").is_err());
         assert!(RoutePath::analyse("a").is_err());
-        assert!(RoutePath::analyse("/a/").is_err());
-        assert!(RoutePath::analyse("/a/b/c/").is_err());
         assert!(RoutePath::analyse("/a{").is_err());
         assert!(RoutePath::analyse("/a{}").is_err());
         assert!(RoutePath::analyse("/{}a").is_err());
-        assert!(RoutePath::analyse("/{a}a").is_err());
+        assert!(RoutePath::analyse("/a}").is_err());
         assert!(RoutePath::analyse("/ a").is_err());
-        assert!(RoutePath::analyse("/1").is_err());
         assert!(RoutePath::analyse("/a//b").is_err());
+        assert!(RoutePath::analyse("/a/*").is_err());
+        assert!(RoutePath::analyse("/{a}{b}").is_err());
+        assert!(RoutePath::analyse("/{a-b}").is_err());
 
+        assert!(RoutePath::analyse("/").is_ok());
         assert!(RoutePath::analyse("/a").is_ok());
-        assert!(RoutePath::analyse("/a/b/c").is_ok());
+        assert!(RoutePath::analyse("/a/").is_ok());
+        assert!(RoutePath::analyse("/a/b/c/").is_ok());
         assert!(RoutePath::analyse("/a/a/a").is_ok());
         assert!(RoutePath::analyse("/a1/b2/c3").is_ok());
+        assert!(RoutePath::analyse("/1").is_ok());
+        assert!(RoutePath::analyse("/v1/pet-store/~me").is_ok());
 
         assert!(RoutePath::analyse("/{a1}").is_ok());
         assert!(RoutePath::analyse("/{a1}/b2/{c3}").is_ok());
         assert!(RoutePath::analyse("/{a1B2c3}").is_ok());
         assert!(RoutePath::analyse("/{a1_b2_c3}").is_ok());
+        assert!(RoutePath::analyse("/{a}a").is_ok());
+        assert!(RoutePath::analyse("/{a}-{b}").is_ok());
 
         // duplicate param
         assert!(RoutePath::analyse("/{a}/{b}/{a}").is_err());
@@ -828,14 +1364,100 @@ mod tests {
         assert_eq!(
             RoutePath::analyse("/{a_1}/{b2C3}/a/b").unwrap(),
             RoutePath {
-                segments: vec![
+                parts: vec![
+                    Literal("/".into()),
                     Parameter("a_1".into()),
+                    Literal("/".into()),
                     Parameter("b2C3".into()),
-                    Literal("a".into()),
-                    Literal("b".into())
+                    Literal("/a/b".into())
                 ]
             }
         );
+        let path = RoutePath::analyse("/v1/files/{name}.json/{id}:archive/").unwrap();
+        assert_eq!(
+            path.parts,
+            vec![
+                Literal("/v1/files/".into()),
+                Parameter("name".into()),
+                Literal(".json/".into()),
+                Parameter("id".into()),
+                Literal(":archive/".into())
+            ]
+        );
+        assert_eq!(path.to_string(), "/v1/files/{name}.json/{id}:archive/");
+    }
+
+    #[test]
+    fn test_dereference_cycle() {
+        let mut lookup: Map<String, ReferenceOr<u32>> = Map::new();
+        let refer = |name: &str| ReferenceOr::Reference {
+            reference: format!("#/components/schemas/{}", name),
+        };
+        lookup.insert("A".into(), refer("B"));
+        lookup.insert("B".into(), refer("A"));
+        lookup.insert("C".into(), ReferenceOr::Item(1));
+        assert_eq!(dereference(&refer("C"), &lookup).unwrap(), &1);
+        match dereference(&refer("A"), &lookup) {
+            Err(Error::CyclicReference(chain)) => assert_eq!(
+                chain,
+                "#/components/schemas/A -> #/components/schemas/B -> #/components/schemas/A"
+            ),
+            other => panic!("Expected cyclic reference, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_detect_spec_format() {
+        assert_eq!(SpecFormat::from_path("api.json"), Some(SpecFormat::Json));
+        assert_eq!(SpecFormat::from_path("api.YAML"), Some(SpecFormat::Yaml));
+        assert_eq!(SpecFormat::from_path("api.yml"), Some(SpecFormat::Yaml));
+        assert_eq!(SpecFormat::from_path("api"), None);
+        assert_eq!(SpecFormat::from_path("api.txt"), None);
+
+        assert_eq!(
+            SpecFormat::detect("  \n{\"openapi\": \"3.0.0\"}"),
+            SpecFormat::Json
+        );
+        assert_eq!(SpecFormat::detect("openapi: 3.0.0"), SpecFormat::Yaml);
+    }
+
+    #[test]
+    fn test_json_and_yaml_agree() {
+        let yaml = fs::read_to_string("../test/test-spec.yaml").unwrap();
+        let value: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+        let json = serde_json::to_string_pretty(&value).unwrap();
+
+        let from_yaml = generate_from_yaml_source(yaml.as_bytes()).unwrap();
+        let from_json = generate_from_json_source(json.as_bytes()).unwrap();
+        assert_eq!(from_yaml, from_json);
+        let detected = generate_from_source(json.as_bytes(), None).unwrap();
+        assert_eq!(from_yaml, detected);
+    }
+
+    #[test]
+    fn test_parse_error_location() {
+        let dir = tempdir::TempDir::new("hsr").unwrap();
+        let path = dir.path().join("broken.json");
+        fs::write(&path, "{\n  \"openapi\": \"3.0.0\",\n  \"info\": }").unwrap();
+        let err = generate_from_file(&path).unwrap_err().to_string();
+        assert!(err.contains("broken.json"), "{}", err);
+        assert!(err.contains("as JSON"), "{}", err);
+        assert!(err.contains("line 3"), "{}", err);
+
+        let source = "{\n  \"openapi\": \"3.0.0\",\n  \"info\": }";
+        let err = generate_from_source(source.as_bytes(), None)
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Failed to parse spec as JSON"), "{}", err);
+        assert!(err.contains("line 3"), "{}", err);
+        let err = generate_from_yaml_source("openapi: [".as_bytes())
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Failed to parse spec as YAML"), "{}", err);
+        let err = generate_from_yaml_source("openapi: 3.0.0".as_bytes())
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Failed to parse spec as YAML"), "{}", err);
     }
 
     // #[test]
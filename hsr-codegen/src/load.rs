@@ -0,0 +1,733 @@
+//! Loading of OpenAPI specs, which may be split across several files.
+//!
+//! References to other files (e.g. `$ref: './schemas/pet.yaml#/Pet'`) are
+//! resolved by copying the referenced fragment into the `components` section
+//! of the root document, and rewriting the reference to point at the copy.
+//! The rest of the code generator then only has to deal with local references.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use heck::CamelCase;
+use indexmap::{IndexMap as Map, IndexSet as Set};
+use log::debug;
+use openapiv3::OpenAPI;
+use percent_encoding::percent_decode_str;
+use serde_yaml::{Mapping, Value};
+
+use crate::{parse_file, Error, Extensions, Result, SpecFormat};
+
+/// A fully-loaded OpenAPI spec, along with every file that went into it
+#[derive(Debug, Clone)]
+pub struct Spec {
+    pub(crate) api: OpenAPI,
+    pub(crate) extensions: Extensions,
+    files: Vec<PathBuf>,
+}
+
+impl Spec {
+    /// Load a spec from a file, following references to any other files.
+    ///
+    /// The format of each file is chosen by extension, falling back to inspecting its contents
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::load_as(path, None)
+    }
+
+    /// Load a spec from a file. If a format is given, the root file is assumed to be in that format
+    pub fn load_as(path: impl AsRef<Path>, format: Option<SpecFormat>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+        let format = format
+            .or_else(|| SpecFormat::from_path(path))
+            .unwrap_or_else(|| SpecFormat::detect(&source));
+        let root: Value = parse_file(path, &source, format)?;
+        let mut files = vec![path.to_path_buf()];
+        let root = if has_external_refs(&root) {
+            debug!("Bundling external references of '{}'", path.display());
+            let root_path = canonicalize(path)?;
+            let mut bundler = Bundler::new(root_path.clone(), &root);
+            let root = bundler.bundle(root)?;
+            files.extend(
+                bundler
+                    .documents
+                    .into_iter()
+                    .map(|(path, _)| path)
+                    .filter(|path| *path != root_path),
+            );
+            root
+        } else {
+            root
+        };
+        let extensions = Extensions::gather(&root);
+        let api = serde_yaml::from_value(root).map_err(|e| Error::Parse {
+            path: Some(path.into()),
+            format,
+            source: Box::new(e.into()),
+        })?;
+        Ok(Spec {
+            api,
+            extensions,
+            files,
+        })
+    }
+
+    /// Every file which was read in order to load this spec
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Tell cargo to re-run the build script if any of the spec files change.
+    /// Intended to be called from `build.rs`.
+    pub fn rerun_if_changed(&self) {
+        for file in &self.files {
+            println!("cargo:rerun-if-changed={}", file.display());
+        }
+    }
+}
+
+fn canonicalize(path: &Path) -> Result<PathBuf> {
+    fs::canonicalize(path)
+        .map_err(|e| Error::BadReference(format!("Could not read '{}': {}", path.display(), e)))
+}
+
+fn has_external_refs(value: &Value) -> bool {
+    match value {
+        Value::Mapping(map) => map.iter().any(|(key, val)| match (key.as_str(), val) {
+            (Some("$ref"), Value::String(refr)) => !refr.starts_with('#'),
+            _ => has_external_refs(val),
+        }),
+        Value::Sequence(seq) => seq.iter().any(has_external_refs),
+        _ => false,
+    }
+}
+
+/// Get the `$ref` string, if the value is a reference object
+fn as_reference(value: &Value) -> Option<&str> {
+    value
+        .as_mapping()
+        .and_then(|map| map.get(&Value::String("$ref".into())))
+        .and_then(Value::as_str)
+}
+
+fn key_to_string(key: &Value) -> String {
+    match key {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Follow a JSON pointer (e.g. `/components/schemas/Pet`) into a document
+fn lookup_pointer<'a>(doc: &'a Value, pointer: &str) -> Option<&'a Value> {
+    if pointer.is_empty() || pointer == "/" {
+        return Some(doc);
+    }
+    if !pointer.starts_with('/') {
+        return None;
+    }
+    pointer.split('/').skip(1).try_fold(doc, |node, segment| {
+        let segment = unescape_segment(segment);
+        match node {
+            Value::Mapping(map) => map
+                .iter()
+                .find(|(key, _)| key_to_string(key) == segment)
+                .map(|(_, val)| val),
+            Value::Sequence(seq) => segment.parse::<usize>().ok().and_then(|ix| seq.get(ix)),
+            _ => None,
+        }
+    })
+}
+
+/// Undo the escaping of a segment of a JSON pointer in a URI fragment:
+/// percent-encoding, then `~1` for `/` and `~0` for `~`
+fn unescape_segment(segment: &str) -> String {
+    percent_decode_str(segment)
+        .decode_utf8_lossy()
+        .replace("~1", "/")
+        .replace("~0", "~")
+}
+
+/// Where a referenced fragment must go in the bundled document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// Into the named section of 'components'
+    Component(&'static str),
+    /// Path items cannot be components in OpenAPI 3.0, so they are inlined
+    PathItem,
+}
+
+const COMPONENT_KINDS: &[&str] = &[
+    "schemas",
+    "responses",
+    "parameters",
+    "examples",
+    "requestBodies",
+    "headers",
+    "securitySchemes",
+    "links",
+    "callbacks",
+];
+
+fn component_kind(name: &str) -> Option<&'static str> {
+    COMPONENT_KINDS.iter().find(|&&kind| kind == name).copied()
+}
+
+/// Work out what kind of object is expected at a given location in the document
+fn kind_at_location(location: &[String]) -> Kind {
+    let loc: Vec<&str> = location.iter().map(String::as_str).collect();
+    let kind = match &loc[..] {
+        ["components", kind, _] => component_kind(kind).unwrap_or("schemas"),
+        ["paths", _] => return Kind::PathItem,
+        [.., "properties", _]
+        | [.., "items"]
+        | [.., "additionalProperties"]
+        | [.., "not"]
+        | [.., "schema"]
+        | [.., "allOf", _]
+        | [.., "oneOf", _]
+        | [.., "anyOf", _] => "schemas",
+        [.., "parameters", _] => "parameters",
+        [.., "responses", _] => "responses",
+        [.., "requestBody"] => "requestBodies",
+        [.., "headers", _] => "headers",
+        [.., "examples", _] => "examples",
+        [.., "links", _] => "links",
+        _ => "schemas",
+    };
+    Kind::Component(kind)
+}
+
+/// If the pointer is already of the form `/components/<kind>/<name>`, extract the parts.
+/// The name is made safe to use in a local reference
+fn component_pointer(pointer: &str) -> Option<(&'static str, String)> {
+    let parts: Vec<&str> = pointer.split('/').collect();
+    match &parts[..] {
+        ["", "components", kind, name] => {
+            component_kind(kind).map(|kind| (kind, import_name(&unescape_segment(name))))
+        }
+        _ => None,
+    }
+}
+
+/// A location in a file, i.e. the target of a reference
+type Target = (PathBuf, String);
+
+struct Bundler {
+    root: PathBuf,
+    /// Every document we have loaded, keyed by canonical path
+    documents: Map<PathBuf, Value>,
+    /// The local reference which each external target has been rewritten to
+    rewritten: Map<Target, String>,
+    /// Component names already used in the root document, per kind
+    taken: Map<&'static str, Set<String>>,
+    /// Components copied in from other files, in the order they were found
+    imported: Vec<(&'static str, String, Value)>,
+    /// Path items currently being inlined, so we can spot them including themselves
+    inlining: Vec<Target>,
+}
+
+impl Bundler {
+    fn new(root: PathBuf, root_doc: &Value) -> Self {
+        let mut taken: Map<&'static str, Set<String>> = Map::new();
+        if let Some(components) =
+            lookup_pointer(root_doc, "/components").and_then(Value::as_mapping)
+        {
+            for (kind, section) in components {
+                let kind = match kind.as_str().and_then(component_kind) {
+                    Some(kind) => kind,
+                    None => continue,
+                };
+                if let Some(section) = section.as_mapping() {
+                    let names = taken.entry(kind).or_default();
+                    names.extend(section.iter().map(|(name, _)| key_to_string(name)));
+                }
+            }
+        }
+        let mut documents = Map::new();
+        documents.insert(root.clone(), root_doc.clone());
+        Self {
+            root,
+            documents,
+            rewritten: Map::new(),
+            taken,
+            imported: Vec::new(),
+            inlining: Vec::new(),
+        }
+    }
+
+    fn bundle(&mut self, mut root: Value) -> Result<Value> {
+        let root_path = self.root.clone();
+        self.resolve_refs(&mut root, &root_path, &mut Vec::new())?;
+
+        let root_map = root
+            .as_mapping_mut()
+            .ok_or_else(|| Error::Validation("Spec must be an object".into()))?;
+        let components_key = Value::String("components".into());
+        if !root_map.contains_key(&components_key) {
+            root_map.insert(components_key.clone(), Value::Mapping(Mapping::new()));
+        }
+        let components = root_map
+            .get_mut(&components_key)
+            .and_then(Value::as_mapping_mut)
+            .ok_or_else(|| Error::Validation("'components' must be an object".into()))?;
+        for (kind, name, fragment) in self.imported.drain(..) {
+            let kind = Value::String(kind.into());
+            if !components.contains_key(&kind) {
+                components.insert(kind.clone(), Value::Mapping(Mapping::new()));
+            }
+            let section = components
+                .get_mut(&kind)
+                .and_then(Value::as_mapping_mut)
+                .ok_or_else(|| {
+                    Error::Validation(format!("'components.{:?}' must be an object", kind))
+                })?;
+            section.insert(Value::String(name), fragment);
+        }
+        check_reference_cycles(&root)?;
+        Ok(root)
+    }
+
+    /// Walk a (part of a) document which was read from `doc`, rewriting all
+    /// references so that they point into the root document
+    fn resolve_refs(
+        &mut self,
+        value: &mut Value,
+        doc: &Path,
+        location: &mut Vec<String>,
+    ) -> Result<()> {
+        if let Some(reference) = as_reference(value).map(String::from) {
+            let target = match self.target(&reference, doc)? {
+                Some(target) => target,
+                // a local reference within the root document, nothing to do
+                None => return Ok(()),
+            };
+            if target.0 == self.root {
+                *value = reference_to(format!("#{}", target.1));
+                return Ok(());
+            }
+            match kind_at_location(location) {
+                Kind::PathItem => {
+                    if self.inlining.contains(&target) {
+                        return Err(Error::CyclicReference(reference));
+                    }
+                    let mut fragment = self.fragment(&target)?.clone();
+                    self.inlining.push(target.clone());
+                    self.resolve_refs(&mut fragment, &target.0, location)?;
+                    self.inlining.pop();
+                    *value = fragment;
+                }
+                Kind::Component(kind) => {
+                    let local = self.import(target, kind)?;
+                    *value = reference_to(local);
+                }
+            }
+            return Ok(());
+        }
+        match value {
+            Value::Mapping(map) => {
+                for (key, val) in map.iter_mut() {
+                    location.push(key_to_string(key));
+                    self.resolve_refs(val, doc, location)?;
+                    location.pop();
+                }
+            }
+            Value::Sequence(seq) => {
+                for (ix, val) in seq.iter_mut().enumerate() {
+                    location.push(ix.to_string());
+                    self.resolve_refs(val, doc, location)?;
+                    location.pop();
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Find the file and pointer that a reference found in `doc` points to.
+    /// Returns `None` for local references in the root document.
+    fn target(&self, reference: &str, doc: &Path) -> Result<Option<Target>> {
+        let (file, pointer) = match reference.find('#') {
+            Some(ix) => (&reference[..ix], &reference[ix + 1..]),
+            None => (reference, ""),
+        };
+        if file.is_empty() {
+            if doc == self.root {
+                return Ok(None);
+            }
+            return Ok(Some((doc.to_path_buf(), pointer.to_string())));
+        }
+        if file.contains("://") {
+            return Err(Error::BadReference(format!(
+                "{} (only references to local files are supported)",
+                reference
+            )));
+        }
+        let dir = doc.parent().unwrap_or_else(|| Path::new(""));
+        let path = canonicalize(&dir.join(file))?;
+        Ok(Some((path, pointer.to_string())))
+    }
+
+    /// Copy the target into the root document (if we have not done so already),
+    /// and return the local reference to it
+    fn import(&mut self, target: Target, kind: &'static str) -> Result<String> {
+        if let Some(local) = self.rewritten.get(&target) {
+            return Ok(local.clone());
+        }
+        let mut fragment = self.fragment(&target)?.clone();
+        let (kind, name) = match component_pointer(&target.1) {
+            Some((kind, name)) => (kind, name),
+            None => (kind, default_name(&target)),
+        };
+        let name = self.unique_name(kind, &name, &target.0);
+        let local = format!("#/components/{}/{}", kind, name);
+        debug!(
+            "Import '{}#{}' as '{}'",
+            target.0.display(),
+            target.1,
+            local
+        );
+        // Register before descending, so that (legitimately) recursive
+        // types refer back to this same copy rather than looping forever
+        self.rewritten.insert(target.clone(), local.clone());
+        let ix = self.imported.len();
+        self.imported.push((kind, name.clone(), Value::Null));
+        let mut location = vec!["components".into(), kind.to_string(), name];
+        self.resolve_refs(&mut fragment, &target.0, &mut location)?;
+        self.imported[ix].2 = fragment;
+        Ok(local)
+    }
+
+    fn fragment(&mut self, target: &Target) -> Result<&Value> {
+        let (path, pointer) = target;
+        if !self.documents.contains_key(path) {
+            debug!("Load '{}'", path.display());
+            let source = fs::read_to_string(path).map_err(|e| {
+                Error::BadReference(format!("Could not read '{}': {}", path.display(), e))
+            })?;
+            let format = SpecFormat::from_path(path).unwrap_or_else(|| SpecFormat::detect(&source));
+            let doc = parse_file(path, &source, format)?;
+            self.documents.insert(path.clone(), doc);
+        }
+        lookup_pointer(&self.documents[path], pointer).ok_or_else(|| {
+            Error::BadReference(format!("{}#{} (target not found)", path.display(), pointer))
+        })
+    }
+
+    /// Pick a name for an imported component which does not clash with any other
+    fn unique_name(&mut self, kind: &'static str, name: &str, file: &Path) -> String {
+        let taken = self.taken.entry(kind).or_default();
+        let stem = file
+            .file_stem()
+            .map(|stem| sanitize_name(&stem.to_string_lossy().to_camel_case()))
+            .unwrap_or_default();
+        let mut candidates = vec![name.to_string(), format!("{}{}", stem, name)];
+        candidates.extend((2..).map(|n| format!("{}{}", name, n)).take(1000));
+        let name = candidates
+            .into_iter()
+            .find(|candidate| !taken.contains(candidate))
+            .expect("ran out of names");
+        taken.insert(name.clone());
+        name
+    }
+}
+
+fn reference_to(reference: String) -> Value {
+    let mut map = Mapping::new();
+    map.insert(Value::String("$ref".into()), Value::String(reference));
+    Value::Mapping(map)
+}
+
+/// Components are referred to as `#/components/<kind>/<name>`,
+/// so names must be alphanumeric
+fn sanitize_name(name: &str) -> String {
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric()) {
+        name.to_string()
+    } else {
+        name.to_camel_case()
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .collect()
+    }
+}
+
+/// Name an imported component after the last part of the pointer,
+/// or after the file if we are importing the whole thing
+fn default_name(target: &Target) -> String {
+    let (path, pointer) = target;
+    let last = pointer
+        .rsplit('/')
+        .next()
+        .filter(|seg| !seg.is_empty())
+        .map(unescape_segment)
+        .or_else(|| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        })
+        .unwrap_or_default();
+    import_name(&last)
+}
+
+/// Turn a name into one which a local reference to a component may use
+fn import_name(name: &str) -> String {
+    let name = sanitize_name(name);
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("Imported{}", name)
+    } else {
+        name
+    }
+}
+
+/// A component which is nothing but a reference to another component
+/// (which refers back to the first) can never be resolved
+fn check_reference_cycles(root: &Value) -> Result<()> {
+    let components = match lookup_pointer(root, "/components").and_then(Value::as_mapping) {
+        Some(components) => components,
+        None => return Ok(()),
+    };
+    for (kind, section) in components {
+        let section = match section.as_mapping() {
+            Some(section) => section,
+            None => continue,
+        };
+        for (name, mut node) in section {
+            let start = format!(
+                "#/components/{}/{}",
+                key_to_string(kind),
+                key_to_string(name)
+            );
+            let mut chain = vec![start];
+            while let Some(reference) = as_reference(node) {
+                if chain.iter().any(|seen| seen == reference) {
+                    chain.push(reference.to_string());
+                    return Err(Error::CyclicReference(chain.join(" -> ")));
+                }
+                chain.push(reference.to_string());
+                let target = reference
+                    .strip_prefix('#')
+                    .and_then(|pointer| lookup_pointer(root, pointer));
+                node = match target {
+                    Some(node) => node,
+                    None => break,
+                };
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn write(dir: &TempDir, name: &str, contents: &str) {
+        let path = dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    const ROOT: &str = r##"
+openapi: "3.0.0"
+info:
+  version: 1.0.0
+  title: Multi
+paths:
+  /pets:
+    get:
+      operationId: get_pets
+      responses:
+        '200':
+          description: Pets
+          content:
+            application/json:
+              schema:
+                type: array
+                items:
+                  $ref: "./schemas/pet.yaml#/Pet"
+        default:
+          $ref: "common.yaml#/components/responses/Error"
+  /owners:
+    $ref: "paths/owners.yaml"
+components:
+  schemas:
+    Pet:
+      type: string
+"##;
+
+    #[test]
+    fn test_bundle_external_refs() {
+        let dir = TempDir::new("hsr").unwrap();
+        write(&dir, "api.yaml", ROOT);
+        write(
+            &dir,
+            "schemas/pet.yaml",
+            r##"
+Pet:
+  type: object
+  properties:
+    name:
+      type: string
+    owner:
+      $ref: "#/Owner"
+    error:
+      $ref: "../common.yaml#/components/schemas/Error"
+Owner:
+  type: object
+  properties:
+    pets:
+      type: array
+      items:
+        $ref: "#/Pet"
+"##,
+        );
+        write(
+            &dir,
+            "common.yaml",
+            r##"
+components:
+  schemas:
+    Error:
+      type: object
+      properties:
+        message:
+          type: string
+  responses:
+    Error:
+      description: Error
+      content:
+        application/json:
+          schema:
+            $ref: "#/components/schemas/Error"
+"##,
+        );
+        write(
+            &dir,
+            "paths/owners.yaml",
+            r##"
+get:
+  operationId: get_owners
+  responses:
+    '200':
+      description: Owners
+      content:
+        application/json:
+          schema:
+            $ref: "../schemas/pet.yaml#/Owner"
+"##,
+        );
+
+        let spec = Spec::load(dir.path().join("api.yaml")).unwrap();
+        assert_eq!(spec.files().len(), 4);
+
+        let components = spec.api.components.as_ref().unwrap();
+        // 'Pet' is already taken in the root document, so the import gets renamed
+        let names: Vec<_> = components.schemas.keys().cloned().collect();
+        assert_eq!(names, vec!["Pet", "PetPet", "Owner", "Error"]);
+        assert!(components.responses.contains_key("Error"));
+
+        let pets = serde_json::to_string(&spec.api.paths["/pets"]).unwrap();
+        assert!(pets.contains("#/components/schemas/PetPet"));
+        assert!(pets.contains("#/components/responses/Error"));
+        let owners = serde_json::to_string(&spec.api.paths["/owners"]).unwrap();
+        assert!(owners.contains("get_owners"));
+        assert!(owners.contains("#/components/schemas/Owner"));
+
+        // the recursive Pet <-> Owner types were only imported once
+        let owner = serde_json::to_string(&components.schemas["Owner"]).unwrap();
+        assert!(owner.contains("#/components/schemas/PetPet"));
+
+        crate::generate_from_spec(&spec).unwrap();
+    }
+
+    #[test]
+    fn test_bundle_component_names() {
+        let dir = TempDir::new("hsr").unwrap();
+        let root = ROOT.split("        default:").next().unwrap().replace(
+            "./schemas/pet.yaml#/Pet",
+            "common.yaml#/components/schemas/Pet-v2",
+        );
+        write(&dir, "api.yaml", &root);
+        write(
+            &dir,
+            "common.yaml",
+            r##"
+components:
+  schemas:
+    Pet-v2:
+      type: object
+      properties:
+        summary:
+          $ref: "#/components/schemas/pet_summary"
+        tag:
+          $ref: "#/components/schemas/a~1b%20c"
+    pet_summary:
+      type: string
+    a/b c:
+      type: string
+"##,
+        );
+        write(
+            &dir,
+            "paths/owners.yaml",
+            "get:\n  operationId: get_owners\n  responses: {}\n",
+        );
+
+        let spec = Spec::load(dir.path().join("api.yaml")).unwrap();
+        let components = spec.api.components.as_ref().unwrap();
+        let names: Vec<_> = components.schemas.keys().cloned().collect();
+        assert_eq!(names, vec!["PetV2", "PetSummary", "ABC"]);
+        let pets = serde_json::to_string(&spec.api.paths["/pets"]).unwrap();
+        assert!(pets.contains("#/components/schemas/PetV2"));
+        crate::generate_from_spec(&spec).unwrap();
+    }
+
+    #[test]
+    fn test_bundle_cyclic_refs() {
+        let dir = TempDir::new("hsr").unwrap();
+        let root = ROOT.split("        default:").next().unwrap();
+        write(&dir, "api.yaml", root);
+        write(
+            &dir,
+            "schemas/pet.yaml",
+            "Pet:\n  $ref: '../common.yaml#/Thing'\n",
+        );
+        write(
+            &dir,
+            "common.yaml",
+            "Thing:\n  $ref: 'schemas/pet.yaml#/Pet'\n",
+        );
+        match Spec::load(dir.path().join("api.yaml")) {
+            Err(Error::CyclicReference(_)) => {}
+            other => panic!("Expected cyclic reference error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bundle_missing_target() {
+        let dir = TempDir::new("hsr").unwrap();
+        write(&dir, "api.yaml", ROOT);
+        write(&dir, "schemas/pet.yaml", "NotPet:\n  type: string\n");
+        match Spec::load(dir.path().join("api.yaml")) {
+            Err(Error::BadReference(msg)) => assert!(msg.contains("pet.yaml#/Pet"), "{}", msg),
+            other => panic!("Expected bad reference error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bundle_invalid_spec() {
+        let dir = TempDir::new("hsr").unwrap();
+        write(
+            &dir,
+            "api.yaml",
+            ROOT.split("        default:").next().unwrap(),
+        );
+        write(&dir, "schemas/pet.yaml", "Pet:\n  properties: 3\n");
+        match Spec::load(dir.path().join("api.yaml")) {
+            Err(err @ Error::Parse { .. }) => {
+                assert!(err.to_string().contains("api.yaml"), "{}", err)
+            }
+            other => panic!("Expected parse error, got {:?}", other),
+        }
+    }
+}
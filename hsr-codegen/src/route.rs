@@ -9,7 +9,8 @@ use std::convert::TryFrom;
 use std::hash::Hash;
 use std::ops::Deref;
 
-use crate::walk::{generate_enum_def, Type, Variant};
+use crate::security::{requirements_const, Requirement};
+use crate::walk::{generate_enum_def, generate_validation, Tagging, Type, TypeLookup, Variant};
 use crate::*;
 
 // Just the bits of the Responses that the Route needs to know about
@@ -25,22 +26,64 @@ pub(crate) struct Response {
     pub type_path: Option<TypePath>,
 }
 
+/// The parameters found in one location, by argument name
+pub(crate) type ParamFields = Map<Ident, (FieldMetadata, TypePath)>;
+
+/// The type gathering the parameters in one location, if there are any
+pub(crate) type Params = Option<(TypePath, ParamFields)>;
+
+/// The parameters of an operation, by location
+#[derive(Debug, Clone)]
+pub(crate) struct Parameters {
+    pub path: Params,
+    pub query: Params,
+    pub header: Params,
+    pub cookie: Params,
+}
+
 /// Route contains all the information necessary to contruct the API
 ///
 /// If it has been constructed, the route is logically sound
-#[derive(Debug, Clone, derive_more::Constructor)]
+#[derive(Debug, Clone)]
 pub(crate) struct Route {
     summary: Option<String>,
     description: Option<String>,
     operation_id: Ident,
     method: Method,
     path: RoutePath,
-    path_params: Option<(TypePath, Map<Ident, (FieldMetadata, TypePath)>)>,
-    query_params: Option<(TypePath, Map<Ident, (FieldMetadata, TypePath)>)>,
+    path_params: Params,
+    query_params: Params,
+    header_params: Params,
+    cookie_params: Params,
+    security: Vec<Requirement>,
     responses: Responses,
 }
 
 impl Route {
+    pub(crate) fn new(
+        op: &openapiv3::Operation,
+        operation_id: Ident,
+        method: Method,
+        path: RoutePath,
+        params: Parameters,
+        security: Vec<Requirement>,
+        responses: Responses,
+    ) -> Self {
+        Self {
+            summary: op.summary.clone(),
+            description: op.description.clone(),
+            operation_id,
+            method,
+            path,
+            path_params: params.path,
+            query_params: params.query,
+            header_params: params.header,
+            cookie_params: params.cookie,
+            security,
+            responses,
+        }
+    }
+
     pub(crate) fn method(&self) -> &Method {
         &self.method
     }
@@ -49,6 +92,25 @@ impl Route {
         &self.operation_id
     }
 
+    pub(crate) fn body_type_mut(&mut self) -> Option<&mut TypePath> {
+        self.method.body_type_mut()
+    }
+
+    pub(crate) fn security(&self) -> &[Requirement] {
+        &self.security
+    }
+
+    pub(crate) fn is_secured(&self) -> bool {
+        !self.security.is_empty()
+    }
+
+    /// Whether the operation may be called without credentials
+    fn allows_anonymous(&self) -> bool {
+        self.security
+            .iter()
+            .any(|requirement| requirement.schemes.is_empty())
+    }
+
     fn return_ty_name(&self) -> TypeName {
         TypeName::from_str(&self.operation_id.deref().to_camel_case()).unwrap()
     }
@@ -62,6 +124,62 @@ impl Route {
         }
     }
 
+    /// Generate the statements which check the arguments of the operation against
+    /// the constraints of the spec, pushing any violations to `violations`.
+    /// Returns `None` if there is nothing to check
+    fn generate_validation(
+        &self,
+        body: TokenStream,
+        lookup: &TypeLookup,
+    ) -> Result<Option<TokenStream>> {
+        let mut checks = Vec::new();
+        let params = vec![
+            ("/path", &self.path_params),
+            ("/query", &self.query_params),
+            ("/header", &self.header_params),
+            ("/cookie", &self.cookie_params),
+        ];
+        for (location, params) in params {
+            let params = match params {
+                Some((_, params)) => params,
+                None => continue,
+            };
+            for (id, (meta, type_path)) in params {
+                let wire_name = meta.rename.clone().unwrap_or_else(|| id.to_string());
+                let pointer = quote! { &hsr::validate::pointer(#location, #wire_name) };
+                if meta.required {
+                    checks.extend(generate_validation(
+                        type_path,
+                        quote! { &#id },
+                        pointer,
+                        lookup,
+                    )?);
+                } else if let Some(check) =
+                    generate_validation(type_path, quote! { value }, pointer, lookup)?
+                {
+                    checks.push(quote! {
+                        if let Some(value) = &#id {
+                            #check
+                        }
+                    });
+                }
+            }
+        }
+        if let Some(body_type) = self.method.body_type() {
+            checks.extend(generate_validation(
+                body_type,
+                quote! { &#body },
+                quote! { "/body" },
+                lookup,
+            )?);
+        }
+        if checks.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(quote! { #(#checks)* }))
+        }
+    }
+
     /// The name of the return type. If none are found, returns '()'.
     /// If both Success and Error types exist, will be a Result type
     pub(crate) fn generate_return_type(&self) -> TokenStream {
@@ -88,7 +206,7 @@ impl Route {
             &meta,
             &variants,
             default_variant.as_ref(),
-            false,
+            Tagging::External,
         );
 
         let status_matches = {
@@ -211,38 +329,29 @@ impl Route {
             })
             .unwrap_or(Vec::new());
 
-        let queries: Vec<_> = self
-            .query_params
-            .as_ref()
-            .map(|(_, params)| {
-                params
-                    .iter()
-                    .map(|(id, (meta, ty))| {
-                        let type_name = ty.canonicalize();
-                        if meta.required {
-                            quote! {
-                                #id: #type_name
-                            }
-                        } else {
-                            quote! {
-                                #id: Option<#type_name>
-                            }
-                        }
-                    })
-                    .collect()
-            })
-            .unwrap_or(Vec::new());
+        let queries = optional_args(&self.query_params);
+        let headers = optional_args(&self.header_params);
+        let cookies = optional_args(&self.cookie_params);
 
         let body_arg_opt = self.method.body_type().map(|body_ty| {
             let body_ty = body_ty.canonicalize();
             let name = ident("payload");
             Some(quote! { #name: #body_ty, })
         });
+        // the principal returned by the Authenticator, if the operation is secured
+        let principal_arg_opt = if !self.is_secured() {
+            None
+        } else if self.allows_anonymous() {
+            Some(quote! { principal: Option<Self::Principal>, })
+        } else {
+            Some(quote! { principal: Self::Principal, })
+        };
+
         let docs = self.documentation();
         // define the trait method which the user must implement
         quote! {
             #docs
-            async fn #opid(&self, #(#paths,)* #(#queries,)* #body_arg_opt) -> #api_return_ty;
+            async fn #opid(&self, #principal_arg_opt #(#paths,)* #(#queries,)* #(#headers,)* #(#cookies,)* #body_arg_opt) -> #api_return_ty;
         }
     }
 
@@ -251,7 +360,7 @@ impl Route {
     /// It takes a bit of care to build up this code. Unfortunately we can't just implement
     /// the API trait because we have to be able to return connection errors etc
     /// Which requires a `Result` type.
-    pub(crate) fn generate_client_impl(&self) -> TokenStream {
+    pub(crate) fn generate_client_impl(&self, lookup: &TypeLookup) -> Result<TokenStream> {
         let opid = &self.operation_id;
         let result_type = self.return_ty_name();
 
@@ -267,27 +376,9 @@ impl Route {
             })
             .unwrap_or((Vec::new(), Vec::new()));
 
-        let query_name_type_pairs = self
-            .query_params
-            .as_ref()
-            .map(|(_, params)| {
-                params
-                    .iter()
-                    .map(|(id, (meta, ty))| {
-                        let type_name = ty.canonicalize();
-                        if meta.required {
-                            quote! {
-                                #id: #type_name
-                            }
-                        } else {
-                            quote! {
-                                #id: Option<#type_name>
-                            }
-                        }
-                    })
-                    .collect()
-            })
-            .unwrap_or(Vec::new());
+        let query_name_type_pairs = optional_args(&self.query_params);
+        let header_name_type_pairs = optional_args(&self.header_params);
+        let cookie_name_type_pairs = optional_args(&self.cookie_params);
 
         // template the code to add query parameters to the url, if necessary
         let add_query_string_to_url = self.query_params.as_ref().map(|(type_path, params)| {
@@ -296,16 +387,54 @@ impl Route {
             quote! {
                 {
                     // construct and instance of our query param type
-                    // then url-encode into the string
+                    // then encode it according to the style of each parameter
                     let qstyp = #type_name {
                         #(#fields,)*
                     };
-                    let qs = serde_urlencoded::to_string(qstyp).unwrap();
+                    let qs = hsr::encode_query(&qstyp)?;
                     url.set_query(Some(&qs));
                 }
             }
         });
 
+        // template the code to add headers to the request, if necessary
+        let add_headers_to_request = self.header_params.as_ref().map(|(type_path, params)| {
+            let type_name = type_path.canonicalize();
+            let fields = params.iter().map(|(id, _)| id);
+            quote! {
+                {
+                    // url-encoding our header type gives us the name-value pairs
+                    // without having to know how to stringify each field
+                    let hdrtyp = #type_name {
+                        #(#fields,)*
+                    };
+                    let encoded =
+                        serde_urlencoded::to_string(hdrtyp).map_err(ClientError::Header)?;
+                    for (name, value) in hsr::url::form_urlencoded::parse(encoded.as_bytes()) {
+                        req = req.header(&*name, &*value);
+                    }
+                }
+            }
+        });
+
+        // likewise for cookies, which are then sent in the 'Cookie' header
+        let add_cookies_to_request = self.cookie_params.as_ref().map(|(type_path, params)| {
+            let type_name = type_path.canonicalize();
+            let fields = params.iter().map(|(id, _)| id);
+            quote! {
+                {
+                    let cookietyp = #type_name {
+                        #(#fields,)*
+                    };
+                    let encoded =
+                        serde_urlencoded::to_string(cookietyp).map_err(ClientError::Cookie)?;
+                    for (name, value) in hsr::url::form_urlencoded::parse(encoded.as_bytes()) {
+                        req = req.cookie(Cookie::new(name.into_owned(), value.into_owned()));
+                    }
+                }
+            }
+        });
+
         // if there is a payload in the body, make sure to add it (as json)
         let (body_arg_opt, send_request) = match self.method.body_type() {
             None => (None, quote! {.send()}),
@@ -318,6 +447,26 @@ impl Route {
             }
         };
 
+        // if the operation is secured, send credentials for the first requirement we can satisfy
+        let (select_credentials_opt, add_credentials_to_request_opt) = if self.is_secured() {
+            let requirements = requirements_const(&self.operation_id);
+            (
+                Some(quote! {
+                    let credentials = hsr::select_credentials(security::#requirements, &self.credentials);
+                    for (scheme, credential) in &credentials {
+                        scheme.add_to_url(credential, &mut url);
+                    }
+                }),
+                Some(quote! {
+                    for (scheme, credential) in &credentials {
+                        req = scheme.add_to_request(credential, req);
+                    }
+                }),
+            )
+        } else {
+            (None, None)
+        };
+
         let method = ident(&self.method);
         let path_template = self.path.to_string();
 
@@ -390,23 +539,48 @@ impl Route {
             resp_match_arms
         };
 
+        // if requested, refuse to send requests which break the constraints of the spec
+        let validate_opt = self
+            .generate_validation(quote! { payload }, lookup)?
+            .map(|checks| {
+                quote! {
+                    if self.validate {
+                        let violations = &mut Vec::new();
+                        #checks
+                        hsr::Violations::check(std::mem::take(violations))?;
+                    }
+                }
+            });
+
         // Finally we can piece everything together
-        quote! {
+        Ok(quote! {
             #[allow(unused_mut)]
             pub async fn #opid(
                 &self,
                 #(#path_names: #path_types,)*
                 #(#query_name_type_pairs,)*
+                #(#header_name_type_pairs,)*
+                #(#cookie_name_type_pairs,)*
                 #body_arg_opt
             ) -> Result<#result_type, ClientError>
             {
+                #validate_opt
+
                 // Build up our request path
-                let path = format!(#path_template, #(#path_names = #path_names,)*);
-                let mut url = self.domain.join(&path).unwrap();
+                let path = format!(
+                    #path_template,
+                    #(#path_names = hsr::encode_path_param(&#path_names.to_string()),)*
+                );
+                let mut url = self.domain.join(&path)?;
                 #add_query_string_to_url
+                #select_credentials_opt
 
-                let mut resp = self.inner
-                    .request(Method::#method, url.as_str())
+                let mut req = self.inner.request(Method::#method, url.as_str());
+                #add_headers_to_request
+                #add_cookies_to_request
+                #add_credentials_to_request_opt
+
+                let mut resp = req
                     // Send, giving a future containing an HttpResponse
                     #send_request
                     .await.map_err(ActixError::from)?;
@@ -415,7 +589,7 @@ impl Route {
                     #(#resp_match_arms)*
                 }
             }
-        }
+        })
     }
 
     /// If there are multitple difference error types, construct an
@@ -428,7 +602,11 @@ impl Route {
     /// extracts the values from these types, calls the API function with the values,
     /// and wraps the resulting Future3 type to return a Future1 with corresponding Ok
     /// and Error types.
-    pub(crate) fn generate_dispatcher(&self, trait_name: &TypeName) -> TokenStream {
+    pub(crate) fn generate_dispatcher(
+        &self,
+        trait_name: &TypeName,
+        lookup: &TypeLookup,
+    ) -> Result<TokenStream> {
         // XXX this function is a total mess, there must be a better way to do it.
         // After all, it seems we have got the API signatures right/OK?
         let opid = &self.operation_id;
@@ -439,7 +617,7 @@ impl Route {
             .as_ref()
             .map(|(_, params)| params.keys().collect::<Vec<_>>())
             .unwrap_or_default();
-        let (path_arg_opt, path_destructure_opt) = {
+        let (path_arg_opt, path_destructure_opt, path_pattern_opt) = {
             self.path_params.as_ref().map(|(name, _params)| {
                 let name = name.canonicalize();
                 let path_destructure = quote! {
@@ -448,10 +626,17 @@ impl Route {
                 let path_arg = quote! {
                     path: AxPath<#name>,
                 };
-                (Some(path_arg), Some(path_destructure))
+                // the values are decoded from the raw path, which is matched again
+                let pattern = self.path.to_string();
+                let path_pattern = quote! {
+                    impl hsr::PathPattern for #name {
+                        const PATTERN: &'static str = #pattern;
+                    }
+                };
+                (Some(path_arg), Some(path_destructure), Some(path_pattern))
             })
         }
-        .unwrap_or((None, None));
+        .unwrap_or((None, None, None));
 
         // query args handling
         let query_param_fields = &self
@@ -460,8 +645,8 @@ impl Route {
             .map(|(_, params)| params.keys().collect::<Vec<_>>())
             .unwrap_or_default();
 
-        let (query_arg_opt, query_destructure_opt) = {
-            self.query_params.as_ref().map(|(name, _params)| {
+        let (query_arg_opt, query_destructure_opt, query_styles_opt) = {
+            self.query_params.as_ref().map(|(name, params)| {
                 let name = name.canonicalize();
                 let query_destructure = quote! {
                     let #name { #(#query_param_fields),* } = query.into_inner();
@@ -469,7 +654,58 @@ impl Route {
                 let query_arg = quote! {
                     query: AxQuery<#name>,
                 };
-                (Some(query_arg), Some(query_destructure))
+                let styles = params.iter().filter_map(|(id, (meta, _))| {
+                    let wire_name = meta.rename.clone().unwrap_or_else(|| id.to_string());
+                    meta.query.map(|query| query.to_tokens(&wire_name))
+                });
+                let query_styles = quote! {
+                    impl hsr::QueryStyles for #name {
+                        const STYLES: &'static [hsr::QueryParam] = &[#(#styles),*];
+                    }
+                };
+                (Some(query_arg), Some(query_destructure), Some(query_styles))
+            })
+        }
+        .unwrap_or((None, None, None));
+
+        // header args handling
+        let header_param_fields = &self
+            .header_params
+            .as_ref()
+            .map(|(_, params)| params.keys().collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        let (header_arg_opt, header_destructure_opt) = {
+            self.header_params.as_ref().map(|(name, _params)| {
+                let name = name.canonicalize();
+                let header_destructure = quote! {
+                    let #name { #(#header_param_fields),* } = headers.into_inner();
+                };
+                let header_arg = quote! {
+                    headers: AxHeaders<#name>,
+                };
+                (Some(header_arg), Some(header_destructure))
+            })
+        }
+        .unwrap_or((None, None));
+
+        // cookie args handling
+        let cookie_param_fields = &self
+            .cookie_params
+            .as_ref()
+            .map(|(_, params)| params.keys().collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        let (cookie_arg_opt, cookie_destructure_opt) = {
+            self.cookie_params.as_ref().map(|(name, _params)| {
+                let name = name.canonicalize();
+                let cookie_destructure = quote! {
+                    let #name { #(#cookie_param_fields),* } = cookies.into_inner();
+                };
+                let cookie_arg = quote! {
+                    cookies: AxCookies<#name>,
+                };
+                (Some(cookie_arg), Some(cookie_destructure))
             })
         }
         .unwrap_or((None, None));
@@ -488,30 +724,114 @@ impl Route {
 
         let return_ty = self.return_ty_name();
 
+        // reject requests which break the constraints of the spec
+        let validate_opt = self
+            .generate_validation(quote! { body }, lookup)?
+            .map(|checks| {
+                quote! {
+                    let violations = &mut Vec::new();
+                    #checks
+                    hsr::Violations::check(std::mem::take(violations))?;
+                }
+            });
+
+        // secured operations must authenticate before anything else
+        let (req_arg_opt, authenticate_opt, principal_opt, return_ty) = if self.is_secured() {
+            let requirements = requirements_const(opid);
+            let authenticate = if self.allows_anonymous() {
+                quote! { hsr::authenticate_optional }
+            } else {
+                quote! { hsr::authenticate }
+            };
+            (
+                Some(quote! { req: HttpRequest, }),
+                Some(quote! {
+                    let principal = #authenticate(data.get_ref(), &req, security::#requirements).await?;
+                }),
+                Some(quote! { principal, }),
+                quote! { Result<#return_ty, hsr::AuthError> },
+            )
+        } else {
+            (None, None, None, quote! { #return_ty })
+        };
+
+        let call = quote! {
+            data.#opid(
+                #principal_opt
+                #(#path_param_fields,)*
+                #(#query_param_fields,)*
+                #(#header_param_fields,)*
+                #(#cookie_param_fields,)*
+                #body_ident_opt
+            ).await
+        };
+        // validation failures are returned as errors, as are authentication failures
+        let return_ty = if validate_opt.is_some() {
+            let return_ty = self.return_ty_name();
+            quote! { Result<#return_ty, ActixError> }
+        } else {
+            return_ty
+        };
+        let call = if self.is_secured() || validate_opt.is_some() {
+            quote! { Ok(#call) }
+        } else {
+            call
+        };
+
         let code = quote! {
+            #path_pattern_opt
+            #query_styles_opt
+
             // define the 'top level' function which is called directly by actix
             async fn #opid<A: #trait_name + Send + Sync>(
                 data: AxData<A>,
+                #req_arg_opt
                 #path_arg_opt
                 #query_arg_opt
+                #header_arg_opt
+                #cookie_arg_opt
                 #body_arg_opt
             ) -> #return_ty {
+                #authenticate_opt
 
-                // destructure path and query parameters into variables, if any
+                // destructure path, query, header and cookie parameters into variables, if any
                 #path_destructure_opt
                 #query_destructure_opt
+                #header_destructure_opt
+                #cookie_destructure_opt
+                #validate_opt
                 // call our API handler function with requisite arguments
-                data.#opid(
-                    #(#path_param_fields,)*
-                    #(#query_param_fields,)*
-                    #body_ident_opt
-                ).await
+                #call
             }
         };
-        code
+        Ok(code)
     }
 }
 
+/// Function arguments for a set of parameters, which may not all be required
+fn optional_args(params: &Params) -> Vec<TokenStream> {
+    params
+        .as_ref()
+        .map(|(_, params)| {
+            params
+                .iter()
+                .map(|(id, (meta, ty))| {
+                    let type_name = ty.canonicalize();
+                    if meta.required {
+                        quote! {
+                            #id: #type_name
+                        }
+                    } else {
+                        quote! {
+                            #id: Option<#type_name>
+                        }
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 #[derive(Debug, Clone, derive_more::Constructor, derive_more::Deref)]
 struct Counter<A: PartialEq + Eq + Hash>(HashMap<A, usize>);
 
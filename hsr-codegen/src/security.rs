@@ -0,0 +1,339 @@
+use heck::{ShoutySnakeCase, SnakeCase};
+use openapiv3::{APIKeyLocation, Components, SecurityRequirement, SecurityScheme};
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::walk::Diagnostics;
+use crate::*;
+
+/// How a credential is carried by a request. Mirrors `hsr::AuthScheme`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum AuthScheme {
+    ApiKey {
+        location: APIKeyLocation,
+        name: String,
+    },
+    Basic,
+    Bearer,
+}
+
+/// One of the schemes of a security requirement. Mirrors `hsr::SchemeRequirement`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SchemeRequirement {
+    pub name: String,
+    pub scheme: AuthScheme,
+    pub scopes: Vec<String>,
+}
+
+/// A security requirement object. All of its schemes must be satisfied
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct Requirement {
+    pub schemes: Vec<SchemeRequirement>,
+}
+
+impl Requirement {
+    /// All the scopes needed to satisfy the requirement
+    fn scopes(&self) -> impl Iterator<Item = &String> {
+        self.schemes.iter().flat_map(|scheme| &scheme.scopes)
+    }
+}
+
+/// Resolve the security requirements of an operation against the declared schemes
+pub(crate) fn walk_security(
+    requirements: &[SecurityRequirement],
+    components: &Components,
+    path: &ApiPath,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<Requirement>> {
+    let path = path.clone().push("security");
+    let mut resolved = Vec::new();
+    for requirement in requirements {
+        let mut schemes = Vec::new();
+        for (name, scopes) in requirement {
+            let scheme = match components.security_schemes.get(name) {
+                Some(scheme) => dereference(scheme, &components.security_schemes)?,
+                None => invalid!("Unknown security scheme '{}'", name),
+            };
+            // Only OAuth2 and OpenID Connect have a notion of scopes
+            let scoped = matches!(
+                scheme,
+                SecurityScheme::OAuth2 { .. } | SecurityScheme::OpenIDConnect { .. }
+            );
+            if !scoped && !scopes.is_empty() {
+                invalid!("Security scheme '{}' does not support scopes", name)
+            }
+            let scheme = match scheme {
+                SecurityScheme::APIKey { location, name } => AuthScheme::ApiKey {
+                    location: location.clone(),
+                    name: name.clone(),
+                },
+                SecurityScheme::HTTP { scheme, .. } if scheme.eq_ignore_ascii_case("basic") => {
+                    AuthScheme::Basic
+                }
+                SecurityScheme::HTTP { scheme, .. } if scheme.eq_ignore_ascii_case("bearer") => {
+                    AuthScheme::Bearer
+                }
+                SecurityScheme::HTTP { scheme, .. } => {
+                    diagnostics.unsupported_operation(
+                        &path.clone().push(name),
+                        format!("HTTP '{}' authentication", scheme),
+                    );
+                    continue;
+                }
+                // The tokens are obtained elsewhere, we only need to pass them along
+                SecurityScheme::OAuth2 { .. } | SecurityScheme::OpenIDConnect { .. } => {
+                    AuthScheme::Bearer
+                }
+            };
+            schemes.push(SchemeRequirement {
+                name: name.clone(),
+                scheme,
+                scopes: scopes.clone(),
+            });
+        }
+        resolved.push(Requirement { schemes });
+    }
+    Ok(resolved)
+}
+
+/// Whether any route has security requirements (and so needs an `Authenticator`)
+pub(crate) fn is_secured(routes: &Map<String, Vec<Route>>) -> bool {
+    routes.values().flatten().any(Route::is_secured)
+}
+
+/// The name of the constant holding the security requirements of an operation
+pub(crate) fn requirements_const(operation_id: &Ident) -> QIdent {
+    ident(operation_id.to_shouty_snake_case())
+}
+
+impl AuthScheme {
+    fn to_tokens(&self) -> TokenStream {
+        match self {
+            AuthScheme::ApiKey { location, name } => {
+                let location = match location {
+                    APIKeyLocation::Header => quote! { Header },
+                    APIKeyLocation::Query => quote! { Query },
+                    APIKeyLocation::Cookie => quote! { Cookie },
+                };
+                quote! {
+                    hsr::AuthScheme::ApiKey {
+                        location: hsr::ApiKeyLocation::#location,
+                        name: #name,
+                    }
+                }
+            }
+            AuthScheme::Basic => quote! { hsr::AuthScheme::Basic },
+            AuthScheme::Bearer => quote! { hsr::AuthScheme::Bearer },
+        }
+    }
+
+    /// Generate the client method which sets the credential for this scheme
+    fn generate_client_setter(&self, scheme_name: &str) -> TokenStream {
+        let setter = ident(format!("with_{}", scheme_name.to_snake_case()));
+        let (args, credential) = match self {
+            AuthScheme::ApiKey { .. } => (
+                quote! { key: impl Into<String> },
+                quote! { hsr::Credential::ApiKey(key.into()) },
+            ),
+            AuthScheme::Basic => (
+                quote! { username: impl Into<String>, password: Option<String> },
+                quote! { hsr::Credential::Basic { username: username.into(), password } },
+            ),
+            AuthScheme::Bearer => (
+                quote! { token: impl Into<String> },
+                quote! { hsr::Credential::Bearer(token.into()) },
+            ),
+        };
+        let descr = doc_comment(format!(
+            "Set the credential for security scheme '{}'",
+            scheme_name
+        ));
+        quote! {
+            #descr
+            pub fn #setter(mut self, #args) -> Self {
+                self.credentials.insert(#scheme_name, #credential);
+                self
+            }
+        }
+    }
+}
+
+/// Generate constants describing the security requirements of each operation
+pub(crate) fn generate_rust_security(routes: &Map<String, Vec<Route>>) -> TokenStream {
+    let secured: Vec<_> = routes
+        .values()
+        .flatten()
+        .filter(|route| route.is_secured())
+        .collect();
+    let consts = secured.iter().map(|route| {
+        let name = requirements_const(route.operation_id());
+        let descr = doc_comment(format!(
+            "Security requirements of operation '{}'. Any one of them must be satisfied",
+            route.operation_id()
+        ));
+        let requirements = route.security().iter().map(|requirement| {
+            let schemes = requirement.schemes.iter().map(|req_scheme| {
+                let name = &req_scheme.name;
+                let scheme = req_scheme.scheme.to_tokens();
+                let scopes = &req_scheme.scopes;
+                quote! {
+                    hsr::SchemeRequirement {
+                        name: #name,
+                        scheme: #scheme,
+                        scopes: &[#(#scopes),*],
+                    }
+                }
+            });
+            quote! { &[#(#schemes),*] }
+        });
+        quote! {
+            #descr
+            pub const #name: &[hsr::SecurityRequirement] = &[#(#requirements),*];
+        }
+    });
+    let scope_consts = secured.iter().map(|route| {
+        let name = requirements_const(route.operation_id());
+        let descr = doc_comment(format!(
+            "Scopes required by operation '{}', for each of its security requirements",
+            route.operation_id()
+        ));
+        let scopes = route.security().iter().map(|requirement| {
+            let scopes = requirement.scopes();
+            quote! { &[#(#scopes),*] }
+        });
+        quote! {
+            #descr
+            pub const #name: &[&[&str]] = &[#(#scopes),*];
+        }
+    });
+    quote! {
+        #[allow(dead_code)]
+        pub mod security {
+            #(#consts)*
+
+            pub mod scopes {
+                #(#scope_consts)*
+            }
+        }
+    }
+}
+
+/// Generate a client method to set the credential for each security scheme in use
+pub(crate) fn generate_client_setters(routes: &Map<String, Vec<Route>>) -> TokenStream {
+    let mut schemes: Map<&str, &AuthScheme> = Map::new();
+    for route in routes.values().flatten() {
+        for requirement in route.security() {
+            for req_scheme in &requirement.schemes {
+                schemes.insert(&req_scheme.name, &req_scheme.scheme);
+            }
+        }
+    }
+    schemes
+        .iter()
+        .map(|(name, scheme)| scheme.generate_client_setter(name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::walk::walk_api;
+
+    #[test]
+    fn test_walk_security() {
+        let yaml = r#"
+openapi: "3.0.0"
+info:
+  version: 1.0.0
+  title: Security
+security:
+  - key: []
+paths:
+  /pets:
+    get:
+      operationId: get_pets
+      responses:
+        '200':
+          description: Pets
+    post:
+      operationId: add_pet
+      security:
+        - oauth: [pets:write]
+          key: []
+        - digest: []
+      responses:
+        '200':
+          description: Added
+    delete:
+      operationId: delete_pets
+      security: []
+      responses:
+        '200':
+          description: Deleted
+components:
+  securitySchemes:
+    key:
+      type: apiKey
+      in: query
+      name: api_key
+    oauth:
+      type: openIdConnect
+      openIdConnectUrl: https://example.com/.well-known/openid-configuration
+    digest:
+      type: http
+      scheme: digest
+"#;
+        let api: OpenAPI = serde_yaml::from_str(yaml).unwrap();
+        match walk_api(&api, &Extensions::default(), &mut Diagnostics::default()) {
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "HTTP 'digest' authentication not supported \
+                 (location: 'paths./pets.post.security.digest')"
+            ),
+            Ok(_) => panic!("Expected error"),
+        }
+
+        let root: serde_yaml::Value = serde_yaml::from_str(yaml).unwrap();
+        let mut diagnostics = Diagnostics::new(true);
+        let (_types, routes) =
+            walk_api(&api, &Extensions::gather(&root), &mut diagnostics).unwrap();
+        let routes = &routes["/pets"];
+        assert_eq!(routes.len(), 2);
+        // an empty list allows anonymous access
+        assert!(!routes[1].is_secured());
+        // the top-level requirement applies
+        let key = AuthScheme::ApiKey {
+            location: APIKeyLocation::Query,
+            name: "api_key".into(),
+        };
+        assert_eq!(
+            routes[0].security(),
+            &[Requirement {
+                schemes: vec![SchemeRequirement {
+                    name: "key".into(),
+                    scheme: key,
+                    scopes: vec![],
+                }]
+            }]
+        );
+
+        let scopes: Vec<_> = routes[0].security()[0].scopes().collect();
+        assert!(scopes.is_empty());
+
+        let bad_yaml = yaml.replace("- key: []", "- nokey: []");
+        let api: OpenAPI = serde_yaml::from_str(&bad_yaml).unwrap();
+        match walk_api(&api, &Extensions::default(), &mut Diagnostics::default()) {
+            Err(Error::Validation(msg)) => assert_eq!(msg, "Unknown security scheme 'nokey'"),
+            other => panic!("Expected validation error, got {:?}", other.map(|_| ())),
+        }
+
+        let bad_yaml = yaml.replace("- key: []", "- key: [pets:read]");
+        let api: OpenAPI = serde_yaml::from_str(&bad_yaml).unwrap();
+        match walk_api(&api, &Extensions::default(), &mut Diagnostics::new(true)) {
+            Err(Error::Validation(msg)) => {
+                assert_eq!(msg, "Security scheme 'key' does not support scopes")
+            }
+            other => panic!("Expected validation error, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -1,45 +1,139 @@
-use heck::CamelCase;
+use heck::{CamelCase, SnakeCase};
 use indexmap::{IndexMap as Map, IndexSet as Set};
-use log::debug;
+use log::{debug, warn};
 use openapiv3::{
     AdditionalProperties, AnySchema, Components, ObjectType, OpenAPI, Operation, Parameter,
-    ParameterSchemaOrContent, ReferenceOr, Schema, SchemaData, SchemaKind,
-    StatusCode as ApiStatusCode, Type as ApiType,
+    ParameterSchemaOrContent, ReferenceOr, Schema, SchemaData, SchemaKind, SecurityRequirement,
+    StatusCode as ApiStatusCode, Type as ApiType, VariantOrUnknownOrEmpty,
 };
 use proc_macro2::TokenStream;
 use quote::quote;
 use regex::Regex;
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::convert::TryFrom;
 use std::fmt;
 use std::ops::Deref;
 
 use crate::{
-    dereference, doc_comment, get_derive_tokens, unwrap_ref, variant_from_status_code, ApiPath,
-    Error, FieldMetadata, Ident, Method, MethodWithBody, MethodWithoutBody, RawMethod, Result,
-    RoutePath, SchemaLookup, StatusCode, TypeMetadata, TypeName, TypePath, Visibility,
+    component_name, dereference, dereference_at, doc_comment, get_derive_tokens, ident, unwrap_ref,
+    variant_from_status_code, ApiPath, Error, FieldMetadata, Ident, Method, MethodWithBody,
+    MethodWithoutBody, Options, QueryParam, RawMethod, Report, Result, RoutePath, SchemaLookup,
+    StatusCode, TypeMetadata, TypeName, TypePath, Visibility, UNRAW_KEYWORDS,
 };
 
-use crate::route::{validate_routes, Response, Responses, Route};
+use crate::extensions::{self, Extensions, RUST_UNSIGNED, RUST_VARIANT};
+use crate::route::{validate_routes, Parameters, Response, Responses, Route};
+use crate::security::walk_security;
 
 use proc_macro2::Ident as QIdent;
 
 pub(crate) type TypeLookup = BTreeMap<TypePath, ReferenceOr<Type>>;
 
+/// Collects the constructs we could not handle while walking the spec,
+/// so they can all be reported at once rather than stopping at the first.
+///
+/// In lenient mode they are not errors at all. Instead we work around them
+/// where we can, or leave out the affected operation, and note it in the `Report`.
+#[derive(Debug, Default)]
+pub(crate) struct Diagnostics {
+    lenient: bool,
+    errors: Vec<Error>,
+    // problems which mean the operation being walked cannot be generated
+    operation: Vec<Error>,
+    report: Report,
+}
+
+impl Diagnostics {
+    pub(crate) fn new(lenient: bool) -> Self {
+        Self {
+            lenient,
+            ..Default::default()
+        }
+    }
+
+    /// Record a construct which we can work around, either by ignoring
+    /// it or by falling back to an 'any' type
+    fn unsupported(&mut self, path: &ApiPath, construct: impl Into<String>) {
+        let err = Error::Unsupported {
+            path: path.clone(),
+            construct: construct.into(),
+        };
+        if self.lenient {
+            warn!("{}", err);
+            self.report.degraded.push(err)
+        } else {
+            debug!("{}", err);
+            self.errors.push(err)
+        }
+    }
+
+    /// Record a construct which we work around in any mode, because the
+    /// workaround is good enough. It is noted in the `Report` but never fails
+    fn degraded(&mut self, path: &ApiPath, construct: impl Into<String>) {
+        let err = Error::Unsupported {
+            path: path.clone(),
+            construct: construct.into(),
+        };
+        warn!("{}", err);
+        self.report.degraded.push(err)
+    }
+
+    /// Record a construct which means the current operation cannot be generated
+    pub(crate) fn unsupported_operation(&mut self, path: &ApiPath, construct: impl Into<String>) {
+        let err = Error::Unsupported {
+            path: path.clone(),
+            construct: construct.into(),
+        };
+        debug!("{}", err);
+        self.operation.push(err)
+    }
+
+    /// Call once an operation has been walked. Returns whether it should be kept
+    fn finish_operation(&mut self, operation: &ApiPath) -> bool {
+        let problems = std::mem::take(&mut self.operation);
+        if problems.is_empty() {
+            true
+        } else if self.lenient {
+            warn!("Skipping operation '{}'", operation);
+            self.report.skipped.push((operation.to_string(), problems));
+            false
+        } else {
+            self.errors.extend(problems);
+            true
+        }
+    }
+
+    /// Fail if anything unsupported has been found (never fails in lenient mode)
+    pub(crate) fn check(&mut self) -> Result<()> {
+        Error::collect(std::mem::take(&mut self.errors))
+    }
+
+    pub(crate) fn into_report(self) -> Report {
+        self.report
+    }
+}
+
 fn lookup_type_recursive<'a>(
-    item: &'a ReferenceOr<Type>,
+    mut item: &'a ReferenceOr<Type>,
     lookup: &'a TypeLookup,
 ) -> Result<&'a Type> {
-    match item {
-        ReferenceOr::Reference { reference } => {
-            let path = TypePath::from_reference(reference)?.into();
-            lookup
-                .get(&path)
-                .ok_or_else(|| Error::BadReference(reference.clone()))
-                .and_then(|refr| lookup_type_recursive(refr, lookup))
+    let mut chain: Vec<&str> = Vec::new();
+    loop {
+        match item {
+            ReferenceOr::Reference { reference } => {
+                if chain.contains(&reference.as_str()) {
+                    chain.push(reference);
+                    return Err(Error::CyclicReference(chain.join(" -> ")));
+                }
+                chain.push(reference);
+                let path = TypePath::from_reference(reference)?;
+                item = lookup
+                    .get(&path)
+                    .ok_or_else(|| Error::BadReference(reference.clone()))?;
+            }
+            ReferenceOr::Item(typ) => return Ok(typ),
         }
-        ReferenceOr::Item(typ) => Ok(typ),
     }
 }
 
@@ -47,6 +141,142 @@ fn lookup_type_recursive<'a>(
 pub(crate) struct Type {
     meta: TypeMetadata,
     typ: TypeInner,
+    constraints: Constraints,
+}
+
+impl Type {
+    fn with_constraints(self, constraints: Constraints) -> Self {
+        Self {
+            constraints,
+            ..self
+        }
+    }
+}
+
+/// Constraints on a value which its Rust type does not capture.
+/// They are checked by the generated `Validate` implementations
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Constraints {
+    // the number of characters, items or properties
+    min_count: Option<usize>,
+    max_count: Option<usize>,
+    pattern: Option<String>,
+    // bounds are paired with whether they are exclusive
+    minimum: Option<(Number, bool)>,
+    maximum: Option<(Number, bool)>,
+    multiple_of: Option<Number>,
+    unique_items: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    /// The number as a literal of the type we compare values as
+    fn literal(self, prim: &Primitive) -> TokenStream {
+        match (self, prim) {
+            (Number::Int(n), Primitive::Decimal) => {
+                let n = n.to_string();
+                quote! { #n.parse::<hsr::rust_decimal::Decimal>().unwrap() }
+            }
+            (Number::Float(n), Primitive::Decimal) => {
+                let n = n.to_string();
+                quote! { #n.parse::<hsr::rust_decimal::Decimal>().unwrap() }
+            }
+            // f32 values are compared as f32, so that e.g. 0.1f32 is not above 0.1f64
+            (Number::Int(n), Primitive::F32) => {
+                let lit = proc_macro2::Literal::f32_suffixed(n as f32);
+                quote! { #lit }
+            }
+            (Number::Float(n), Primitive::F32) => {
+                let lit = proc_macro2::Literal::f32_suffixed(n as f32);
+                quote! { #lit }
+            }
+            (Number::Int(n), Primitive::F64) => {
+                let lit = proc_macro2::Literal::f64_suffixed(n as f64);
+                quote! { #lit }
+            }
+            (Number::Int(n), _) => {
+                let lit = proc_macro2::Literal::i128_suffixed(i128::from(n));
+                quote! { #lit }
+            }
+            (Number::Float(n), _) => {
+                let lit = proc_macro2::Literal::f64_suffixed(n);
+                quote! { #lit }
+            }
+        }
+    }
+}
+
+impl Constraints {
+    /// Generate the statements which check `value` (a reference to the primitive)
+    fn generate_primitive_checks(&self, prim: &Primitive) -> TokenStream {
+        let mut checks = TokenStream::new();
+        // the value as the type we compare it as
+        let number = match prim {
+            Primitive::String => {
+                checks.extend(self.generate_count_checks(quote! { value.chars() }, "characters"));
+                if let Some(pattern) = &self.pattern {
+                    checks.extend(quote! {
+                        hsr::validate::check_pattern(value, #pattern, pointer, violations);
+                    });
+                }
+                return checks;
+            }
+            Primitive::I32 | Primitive::I64 | Primitive::U32 | Primitive::U64 => {
+                quote! { i128::from(*value) }
+            }
+            Primitive::F32 | Primitive::F64 | Primitive::Decimal => quote! { *value },
+            _ => return checks,
+        };
+        if let Some((minimum, exclusive)) = self.minimum {
+            let minimum = minimum.literal(prim);
+            checks.extend(quote! {
+                hsr::validate::check_minimum(#number, #minimum, #exclusive, pointer, violations);
+            });
+        }
+        if let Some((maximum, exclusive)) = self.maximum {
+            let maximum = maximum.literal(prim);
+            checks.extend(quote! {
+                hsr::validate::check_maximum(#number, #maximum, #exclusive, pointer, violations);
+            });
+        }
+        if let Some(multiple_of) = self.multiple_of {
+            let multiple_of_lit = multiple_of.literal(prim);
+            let check = match prim {
+                Primitive::F32 => quote! { check_f32_multiple_of },
+                Primitive::F64 => quote! { check_float_multiple_of },
+                _ => quote! { check_multiple_of },
+            };
+            checks.extend(quote! {
+                hsr::validate::#check(#number, #multiple_of_lit, pointer, violations);
+            });
+        }
+        checks
+    }
+
+    /// Generate the statements which check the number of characters, items or
+    /// properties in a collection
+    fn generate_count_checks(&self, collection: TokenStream, noun: &str) -> TokenStream {
+        if self.min_count.is_none() && self.max_count.is_none() {
+            return TokenStream::new();
+        }
+        let min = option_tokens(self.min_count);
+        let max = option_tokens(self.max_count);
+        quote! {
+            hsr::validate::check_count(#collection.count(), #min, #max, #noun, pointer, violations);
+        }
+    }
+}
+
+fn option_tokens(val: Option<usize>) -> TokenStream {
+    match val {
+        Some(val) => quote! { Some(#val) },
+        None => quote! { None },
+    }
 }
 
 impl fmt::Debug for Type {
@@ -59,12 +289,194 @@ impl fmt::Debug for Type {
 enum Primitive {
     #[display(fmt = "String")]
     String,
+    #[display(fmt = "f32")]
+    F32,
     #[display(fmt = "f64")]
     F64,
+    #[display(fmt = "i32")]
+    I32,
     #[display(fmt = "i64")]
     I64,
+    #[display(fmt = "u32")]
+    U32,
+    #[display(fmt = "u64")]
+    U64,
+    #[display(fmt = "Decimal")]
+    Decimal,
     #[display(fmt = "bool")]
     Bool,
+    #[display(fmt = "DateTime")]
+    DateTime,
+    #[display(fmt = "Date")]
+    Date,
+    #[display(fmt = "Uuid")]
+    Uuid,
+    #[display(fmt = "Url")]
+    Url,
+    #[display(fmt = "Base64")]
+    Bytes,
+    #[display(fmt = "Ipv4Addr")]
+    Ipv4,
+    #[display(fmt = "Ipv6Addr")]
+    Ipv6,
+}
+
+impl Primitive {
+    /// The primitive for a string with the given format. Formats which need an
+    /// optional feature fall back to `String` when it is not enabled, as do
+    /// unknown formats
+    fn from_string_format(format: &str, path: &ApiPath, diagnostics: &mut Diagnostics) -> Self {
+        let (primitive, enabled, feature) = match format {
+            "date-time" => (Primitive::DateTime, cfg!(feature = "chrono"), "chrono"),
+            "date" => (Primitive::Date, cfg!(feature = "chrono"), "chrono"),
+            "uuid" => (Primitive::Uuid, cfg!(feature = "uuid"), "uuid"),
+            "uri" => (Primitive::Url, cfg!(feature = "uri"), "uri"),
+            "byte" => (Primitive::Bytes, cfg!(feature = "byte"), "byte"),
+            "ipv4" => return Primitive::Ipv4,
+            "ipv6" => return Primitive::Ipv6,
+            // Hints for validation or display, the value is still just a string
+            "email" | "idn-email" | "hostname" | "idn-hostname" | "uri-reference" | "iri"
+            | "iri-reference" | "password" | "binary" => return Primitive::String,
+            _ => {
+                diagnostics.degraded(path, format!("String format '{}'", format));
+                return Primitive::String;
+            }
+        };
+        primitive.if_enabled(
+            enabled,
+            feature,
+            format,
+            Primitive::String,
+            path,
+            diagnostics,
+        )
+    }
+
+    /// The primitive for an integer with the given format, if any
+    fn from_integer_format(
+        format: Option<&str>,
+        unsigned: bool,
+        path: &ApiPath,
+        diagnostics: &mut Diagnostics,
+    ) -> Self {
+        match (format, unsigned) {
+            (Some("int32"), false) => Primitive::I32,
+            (Some("int32"), true) => Primitive::U32,
+            (Some("int64"), false) | (None, false) => Primitive::I64,
+            (Some("int64"), true) | (None, true) => Primitive::U64,
+            (Some(format), _) => {
+                diagnostics.degraded(path, format!("Integer format '{}'", format));
+                Primitive::I64
+            }
+        }
+    }
+
+    /// The primitive for a number with the given format, if any
+    fn from_number_format(
+        format: Option<&str>,
+        path: &ApiPath,
+        diagnostics: &mut Diagnostics,
+    ) -> Self {
+        match format {
+            Some("float") => Primitive::F32,
+            Some("double") | None => Primitive::F64,
+            Some("decimal") => Primitive::Decimal.if_enabled(
+                cfg!(feature = "decimal"),
+                "decimal",
+                "decimal",
+                Primitive::F64,
+                path,
+                diagnostics,
+            ),
+            Some(format) => {
+                diagnostics.degraded(path, format!("Number format '{}'", format));
+                Primitive::F64
+            }
+        }
+    }
+
+    /// Use this primitive if the feature it needs is enabled, otherwise the fallback
+    fn if_enabled(
+        self,
+        enabled: bool,
+        feature: &str,
+        format: &str,
+        fallback: Self,
+        path: &ApiPath,
+        diagnostics: &mut Diagnostics,
+    ) -> Self {
+        if enabled {
+            self
+        } else {
+            diagnostics.degraded(
+                path,
+                format!("Format '{}' without feature '{}'", format, feature),
+            );
+            fallback
+        }
+    }
+
+    /// Whether a json string can be deserialized into this primitive, so checking
+    /// it against the format
+    fn fits_string(&self, val: &str) -> bool {
+        let matches = |re: &str| Regex::new(re).unwrap().is_match(val);
+        match self {
+            Primitive::String => true,
+            Primitive::DateTime => {
+                matches(
+                    r"^\d{4}-\d{2}-\d{2}[Tt]([01]\d|2[0-3]):[0-5]\d:([0-5]\d|60)(\.\d+)?([Zz]|[+-]([01]\d|2[0-3]):[0-5]\d)$",
+                ) && is_date(&val[..10])
+            }
+            Primitive::Date => matches(r"^\d{4}-\d{2}-\d{2}$") && is_date(val),
+            Primitive::Uuid => matches(
+                r"^(urn:uuid:)?([[:xdigit:]]{32}|[[:xdigit:]]{8}-[[:xdigit:]]{4}-[[:xdigit:]]{4}-[[:xdigit:]]{4}-[[:xdigit:]]{12})$",
+            ),
+            // an absolute URL, with a scheme
+            Primitive::Url => matches(r"^[[:alpha:]][[:alnum:]+.-]*:[^[:space:]]+$"),
+            Primitive::Bytes => {
+                matches(r"^([[:alnum:]+/]{4})*([[:alnum:]+/]{2}==|[[:alnum:]+/]{3}=)?$")
+            }
+            Primitive::Ipv4 => val.parse::<std::net::Ipv4Addr>().is_ok(),
+            Primitive::Ipv6 => val.parse::<std::net::Ipv6Addr>().is_ok(),
+            _ => false,
+        }
+    }
+
+    /// Whether a json number can be deserialized into this primitive
+    fn fits_number(&self, num: &serde_json::Number) -> bool {
+        match self {
+            Primitive::I32 => matches!(num.as_i64(), Some(n) if i32::try_from(n).is_ok()),
+            Primitive::I64 => num.is_i64(),
+            Primitive::U32 => matches!(num.as_u64(), Some(n) if u32::try_from(n).is_ok()),
+            Primitive::U64 => num.is_u64(),
+            Primitive::F32 | Primitive::F64 | Primitive::Decimal => true,
+            _ => false,
+        }
+    }
+}
+
+impl quote::ToTokens for Primitive {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let ty = match self {
+            Primitive::String => quote! { String },
+            Primitive::F32 => quote! { f32 },
+            Primitive::F64 => quote! { f64 },
+            Primitive::I32 => quote! { i32 },
+            Primitive::I64 => quote! { i64 },
+            Primitive::U32 => quote! { u32 },
+            Primitive::U64 => quote! { u64 },
+            Primitive::Decimal => quote! { hsr::rust_decimal::Decimal },
+            Primitive::Bool => quote! { bool },
+            Primitive::DateTime => quote! { hsr::chrono::DateTime<hsr::chrono::Utc> },
+            Primitive::Date => quote! { hsr::chrono::NaiveDate },
+            Primitive::Uuid => quote! { hsr::uuid::Uuid },
+            Primitive::Url => quote! { hsr::url::Url },
+            Primitive::Bytes => quote! { hsr::Base64 },
+            Primitive::Ipv4 => quote! { std::net::Ipv4Addr },
+            Primitive::Ipv6 => quote! { std::net::Ipv6Addr },
+        };
+        tokens.extend(ty)
+    }
 }
 
 /// Represent a variant of an enum
@@ -74,6 +486,7 @@ pub(crate) struct Variant {
     pub description: Option<String>,
     pub type_path: Option<TypePath>,
     pub rename: Option<String>,
+    pub aliases: Vec<String>,
 }
 
 impl Variant {
@@ -83,6 +496,7 @@ impl Variant {
             description: None,
             type_path: None,
             rename: None,
+            aliases: Vec::new(),
         }
     }
 
@@ -103,6 +517,10 @@ impl Variant {
             ..self
         }
     }
+
+    pub(crate) fn aliases(self, aliases: Vec<String>) -> Self {
+        Self { aliases, ..self }
+    }
 }
 
 impl quote::ToTokens for Variant {
@@ -114,6 +532,11 @@ impl quote::ToTokens for Variant {
                 #[serde(rename = #name)]
             }
         });
+        let aliases = &self.aliases;
+        let rename = quote! {
+            #rename
+            #(#[serde(alias = #aliases)])*
+        };
         let tok = match self.type_path.as_ref() {
             Some(path) => {
                 let varty = path.canonicalize();
@@ -141,19 +564,46 @@ enum TypeInner {
     Primitive(Primitive),
     // String that can only take set values
     StringEnum(Vec<String>),
+    // Integer (as I64), number (as F64) or boolean that can only take set values
+    ValueEnum(Primitive, Vec<serde_json::Value>),
     // An array of of some inner type
     Array(Box<ReferenceOr<Type>>),
+    // A map from strings to the type at the path (an object with only additionalProperties)
+    Map(TypePath),
     // Any type. Could be anything! Probably a user-error
     Any,
+    // The type at the path, boxed because it (indirectly) contains this type
+    Boxed(TypePath),
     AllOf(Vec<ReferenceOr<Type>>),
-    OneOf(Vec<TypePath>),
+    OneOf(OneOf),
+    // Any (at least one) of the alternatives may match, so each is optional
+    AnyOf(Vec<(Ident, TypePath)>),
     Struct(Struct),
 }
 
+#[derive(Clone, Debug, PartialEq)]
+struct OneOf {
+    variants: Vec<(Ident, TypePath)>,
+    // if present, the variants are distinguished by the value of a property
+    discriminator: Option<Discriminator>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Discriminator {
+    property: String,
+    // for each variant, the referenced schema and the values of the property
+    // which select it. The first value is the one we serialize
+    variants: Vec<(TypePath, Vec<String>)>,
+}
+
 impl TypeInner {
     /// Attach metadata
     fn with_meta(self, meta: TypeMetadata) -> Type {
-        Type { meta, typ: self }
+        Type {
+            meta,
+            typ: self,
+            constraints: Constraints::default(),
+        }
     }
 }
 
@@ -162,8 +612,26 @@ struct Struct {
     // each field must carry some struct-specific metadata
     // (on top of metadata attached to the type)
     fields: Map<Ident, (FieldMetadata, TypePath)>,
+    // what to do with properties which are not listed in `fields`
+    additional: Additional,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Additional {
+    // the serde default
+    Ignore,
+    // additionalProperties: false
+    Deny,
+    // additionalProperties: true, or a schema. The values are of the type at the path
+    Keep(TypePath),
 }
 
+/// The name of the field which holds additional properties
+const EXTRA_FIELD: &str = "extra";
+/// The name under which the type of additional properties is stored,
+/// alongside the types of the fields
+const ADDITIONAL_TYPE: &str = "additional";
+
 impl Struct {
     /// Build a struct from an object-like OpenApi type
     /// We look recursively inside the object definition
@@ -172,21 +640,92 @@ impl Struct {
         obj: &T,
         path: ApiPath,
         type_index: &mut TypeLookup,
+        extensions: &Extensions,
+        diagnostics: &mut Diagnostics,
     ) -> Result<Self> {
         let mut fields = Map::new();
         let required_args: Set<String> = obj.required().iter().cloned().collect();
-        for (name, schemaref) in obj.properties() {
+        let names: Vec<&str> = obj.properties().keys().map(String::as_str).collect();
+        let keep_additional = match obj.additional_properties() {
+            None | Some(AdditionalProperties::Any(false)) => false,
+            Some(_) => true,
+        };
+        // properties may not take the names used for additional properties
+        let reserved: &[&str] = if keep_additional {
+            &[EXTRA_FIELD, ADDITIONAL_TYPE]
+        } else {
+            &[]
+        };
+        let field_names = field_names(&names, reserved)?;
+        for ((name, schemaref), field) in obj.properties().iter().zip(field_names) {
             let schemaref = schemaref.clone().unbox();
-            let path = path.clone().push(name);
-            let ty = build_type_recursive(&schemaref, path.clone(), type_index)?;
+            // the field name, rather than the property, so that the type names are distinct
+            let path = path
+                .clone()
+                .push_name(field.as_str())
+                .push_location("properties")
+                .push_location(name);
+            let ty = build_type_recursive(
+                &schemaref,
+                path.clone(),
+                type_index,
+                extensions,
+                diagnostics,
+            )?;
+            let (read_only, write_only) = match &schemaref {
+                ReferenceOr::Item(schema) => {
+                    (schema.schema_data.read_only, schema.schema_data.write_only)
+                }
+                ReferenceOr::Reference { .. } => (false, false),
+            };
+            if read_only && write_only {
+                invalid!(
+                    "Property cannot be both readOnly and writeOnly (location: '{}')",
+                    path
+                )
+            }
             let type_path = TypePath::from(path);
             assert!(type_index.insert(type_path.clone(), ty.clone()).is_none());
-            let meta = FieldMetadata::default().with_required(required_args.contains(name));
-            if let Some(_) = fields.insert(name.parse()?, (meta, type_path)) {
-                invalid!("Duplicate field name: '{}'", name);
+            let mut meta = FieldMetadata::default()
+                .with_required(required_args.contains(name))
+                .with_access(read_only, write_only);
+            if *field != *name {
+                meta = meta.with_rename(name.clone());
+            }
+            fields.insert(field, (meta, type_path));
+        }
+        let additional = match obj.additional_properties() {
+            None => Additional::Ignore,
+            Some(AdditionalProperties::Any(false)) => Additional::Deny,
+            Some(additional) => {
+                let path = path
+                    .clone()
+                    .push_name(ADDITIONAL_TYPE)
+                    .push_location("additionalProperties");
+                let ty = match additional {
+                    AdditionalProperties::Schema(schema) => build_type_recursive(
+                        schema,
+                        path.clone(),
+                        type_index,
+                        extensions,
+                        diagnostics,
+                    )?,
+                    _ => ReferenceOr::Item(TypeInner::Any.with_meta(TypeMetadata::default())),
+                };
+                let type_path = TypePath::from(path);
+                assert!(type_index.insert(type_path.clone(), ty).is_none());
+                Additional::Keep(type_path)
             }
+        };
+        Ok(Self { fields, additional })
+    }
+
+    /// An object with no properties of its own, only additional properties, is a map
+    fn into_type(self) -> TypeInner {
+        if let (true, Additional::Keep(values)) = (self.fields.is_empty(), &self.additional) {
+            return TypeInner::Map(values.clone());
         }
-        Ok(Self { fields })
+        TypeInner::Struct(self)
     }
 }
 
@@ -217,34 +756,438 @@ macro_rules! impl_objlike {
 impl_objlike!(ObjectType);
 impl_objlike!(AnySchema);
 
-pub(crate) fn walk_api(api: &OpenAPI) -> Result<(TypeLookup, Map<String, Vec<Route>>)> {
-    if !api.security.is_empty() {
-        todo!("Security not supported")
-    }
+pub(crate) fn walk_api(
+    api: &OpenAPI,
+    extensions: &Extensions,
+    diagnostics: &mut Diagnostics,
+) -> Result<(TypeLookup, Map<String, Vec<Route>>)> {
     let mut type_index = TypeLookup::new();
     let dummy = Default::default();
     let components = api.components.as_ref().unwrap_or(&dummy);
-    walk_component_schemas(&components.schemas, &mut type_index)?;
-    let routes = walk_paths(&api.paths, &mut type_index, &components)?;
+    walk_component_schemas(
+        &components.schemas,
+        &mut type_index,
+        extensions,
+        diagnostics,
+    )?;
+    let mut routes = walk_paths(
+        &api.paths,
+        &mut type_index,
+        extensions,
+        &components,
+        &api.security,
+        diagnostics,
+    )?;
+    check_reference_cycles(&type_index)?;
+    apply_discriminators(&mut type_index)?;
+    split_read_write(&mut type_index, &mut routes)?;
+    box_cycles(&mut type_index)?;
+    diagnostics.check()?;
     validate_routes(&routes)?;
     Ok((type_index, routes))
 }
 
-fn walk_component_schemas(schema_lookup: &SchemaLookup, type_index: &mut TypeLookup) -> Result<()> {
+/// Fail if a chain of references leads back to where it started
+fn check_reference_cycles(type_index: &TypeLookup) -> Result<()> {
+    for typ in type_index.values() {
+        lookup_type_recursive(typ, type_index)?;
+    }
+    Ok(())
+}
+
+/// A type which contains itself (perhaps through other types) would have infinite
+/// size, so for each cycle of types containing one another, we box one of them.
+/// We box a reference, preferably a field or variant rather than a component
+/// schema, as that only changes the type of the field or variant
+fn box_cycles(type_index: &mut TypeLookup) -> Result<()> {
+    while let Some(cycle) = find_cycle(type_index)? {
+        let references: Vec<&TypePath> = cycle
+            .iter()
+            .filter(|path| matches!(type_index[*path], ReferenceOr::Reference { .. }))
+            .collect();
+        let path = match references
+            .iter()
+            .rev()
+            .find(|path| path.to_reference().is_none())
+            .or_else(|| references.last())
+        {
+            Some(&path) => path.clone(),
+            // e.g. schemas which are allOf one another
+            None => {
+                let names: Vec<_> = cycle
+                    .iter()
+                    .map(|path| path.canonicalize().to_string())
+                    .collect();
+                return Err(Error::CyclicReference(names.join(" -> ")));
+            }
+        };
+        let target = match &type_index[&path] {
+            ReferenceOr::Reference { reference } => TypePath::from_reference(reference)?,
+            ReferenceOr::Item(_) => unreachable!(),
+        };
+        let meta = lookup_type_recursive(&type_index[&path], type_index)?
+            .meta
+            .clone();
+        let boxed = TypeInner::Boxed(target).with_meta(meta);
+        type_index.insert(path, ReferenceOr::Item(boxed));
+    }
+    Ok(())
+}
+
+/// Find a cycle of types which contain one another directly (not via a `Vec`,
+/// `HashMap` or `Box`), as the list of types in the cycle
+fn find_cycle(type_index: &TypeLookup) -> Result<Option<Vec<TypePath>>> {
+    fn visit(
+        path: &TypePath,
+        type_index: &TypeLookup,
+        stack: &mut Vec<TypePath>,
+        done: &mut BTreeSet<TypePath>,
+    ) -> Result<Option<Vec<TypePath>>> {
+        if let Some(ix) = stack.iter().position(|seen| seen == path) {
+            return Ok(Some(stack[ix..].to_vec()));
+        }
+        if done.contains(path) {
+            return Ok(None);
+        }
+        let typ = type_index
+            .get(path)
+            .ok_or_else(|| Error::BadReference(path.canonicalize().to_string()))?;
+        stack.push(path.clone());
+        for contained in contained_types(typ, path)? {
+            if let Some(cycle) = visit(&contained, type_index, stack, done)? {
+                return Ok(Some(cycle));
+            }
+        }
+        stack.pop();
+        done.insert(path.clone());
+        Ok(None)
+    }
+
+    let mut done = BTreeSet::new();
+    for path in type_index.keys() {
+        if let Some(cycle) = visit(path, type_index, &mut Vec::new(), &mut done)? {
+            return Ok(Some(cycle));
+        }
+    }
+    Ok(None)
+}
+
+/// The types which a type contains directly
+fn contained_types(typ: &ReferenceOr<Type>, path: &TypePath) -> Result<Vec<TypePath>> {
+    let typ = match typ {
+        ReferenceOr::Reference { reference } => {
+            return Ok(vec![TypePath::from_reference(reference)?])
+        }
+        ReferenceOr::Item(typ) => typ,
+    };
+    let contained = match &typ.typ {
+        TypeInner::Struct(strukt) => strukt
+            .fields
+            .values()
+            .map(|(_, path)| path.clone())
+            .collect(),
+        TypeInner::OneOf(OneOf { variants, .. }) | TypeInner::AnyOf(variants) => {
+            variants.iter().map(|(_, path)| path.clone()).collect()
+        }
+        TypeInner::AllOf(parts) => {
+            let mut contained = Vec::new();
+            for (ix, part) in parts.iter().enumerate() {
+                let part_path = child(path, &format!("AllOf_{}", ix));
+                contained.extend(contained_types(part, &part_path)?);
+            }
+            contained
+        }
+        _ => Vec::new(),
+    };
+    Ok(contained)
+}
+
+/// Properties which are `readOnly` are only sent in responses, and those which are
+/// `writeOnly` only in requests. So a type which has such properties (perhaps
+/// through other types) is split in two: the original without the writeOnly
+/// properties, and an `...Input` variant without the readOnly ones.
+/// Request bodies use the input variants
+fn split_read_write(
+    type_index: &mut TypeLookup,
+    routes: &mut Map<String, Vec<Route>>,
+) -> Result<()> {
+    let mut splitter = Splitter::new(type_index)?;
+    if splitter.split.is_empty() {
+        return Ok(());
+    }
+    // every component schema which is split gets an input variant, used or not
+    let components = ApiPath::default().push("components").push("schemas");
+    for path in type_index.keys() {
+        if path.starts_with(&components) && path.to_reference().is_some() {
+            splitter.input_of(path)?;
+        }
+    }
+    for route in routes.values_mut().flatten() {
+        if let Some(body_type) = route.body_type_mut() {
+            *body_type = splitter.input_of(body_type)?;
+        }
+    }
+    let inputs = splitter.inputs;
+    for typ in type_index.values_mut() {
+        if let ReferenceOr::Item(typ) = typ {
+            strip_write_only(&mut typ.typ);
+        }
+    }
+    type_index.extend(inputs);
+    Ok(())
+}
+
+fn strip_write_only(typ: &mut TypeInner) {
+    match typ {
+        TypeInner::Struct(strukt) => strukt.fields.retain(|_, (meta, _)| !meta.write_only),
+        TypeInner::AllOf(parts) => {
+            for part in parts {
+                if let ReferenceOr::Item(part) = part {
+                    strip_write_only(&mut part.typ)
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+struct Splitter<'a> {
+    lookup: &'a TypeLookup,
+    // the types which need an input variant
+    split: BTreeSet<TypePath>,
+    // the input variants made so far
+    inputs: TypeLookup,
+}
+
+impl<'a> Splitter<'a> {
+    fn new(lookup: &'a TypeLookup) -> Result<Self> {
+        let mut splitter = Self {
+            lookup,
+            split: BTreeSet::new(),
+            inputs: TypeLookup::new(),
+        };
+        // A type must be split if it refers to one which is, so repeat until
+        // nothing changes (this copes with cycles of references)
+        loop {
+            let mut changed = false;
+            for (path, typ) in lookup {
+                if !splitter.split.contains(path) && splitter.must_split(typ, path)? {
+                    splitter.split.insert(path.clone());
+                    changed = true;
+                }
+            }
+            if !changed {
+                return Ok(splitter);
+            }
+        }
+    }
+
+    fn must_split(&self, typ: &ReferenceOr<Type>, path: &TypePath) -> Result<bool> {
+        let typ = match typ {
+            ReferenceOr::Reference { reference } => {
+                return Ok(self.split.contains(&TypePath::from_reference(reference)?))
+            }
+            ReferenceOr::Item(typ) => typ,
+        };
+        let split = match &typ.typ {
+            TypeInner::Struct(strukt) => {
+                let fields = strukt.fields.values().any(|(meta, field)| {
+                    meta.read_only || meta.write_only || self.split.contains(field)
+                });
+                let additional = match &strukt.additional {
+                    Additional::Keep(values) => self.split.contains(values),
+                    _ => false,
+                };
+                fields || additional
+            }
+            TypeInner::Array(_) => self.split.contains(&child(path, "array")),
+            TypeInner::Map(values) => self.split.contains(values),
+            TypeInner::OneOf(OneOf { variants, .. }) | TypeInner::AnyOf(variants) => {
+                variants.iter().any(|(_, var)| self.split.contains(var))
+            }
+            TypeInner::AllOf(parts) => {
+                for (ix, part) in parts.iter().enumerate() {
+                    let part_path = child(path, &format!("AllOf_{}", ix));
+                    if self.must_split(part, &part_path)? {
+                        return Ok(true);
+                    }
+                }
+                false
+            }
+            _ => false,
+        };
+        Ok(split)
+    }
+
+    /// The type to use in requests in place of the type at `path`.
+    /// Makes the input variant, if needed
+    fn input_of(&mut self, path: &TypePath) -> Result<TypePath> {
+        if !self.split.contains(path) {
+            return Ok(path.clone());
+        }
+        let typ = match &self.lookup[path] {
+            // skip straight to the input variant of the referenced type
+            ReferenceOr::Reference { reference } => {
+                return self.input_of(&TypePath::from_reference(reference)?)
+            }
+            ReferenceOr::Item(typ) => typ,
+        };
+        let input = path.with_suffix("Input");
+        if self.inputs.contains_key(&input) {
+            return Ok(input);
+        }
+        if self.lookup.contains_key(&input) {
+            invalid!(
+                "Cannot name the input variant of '{}' (for readOnly properties), \
+                 as '{}' is already used",
+                path.canonicalize(),
+                input.canonicalize()
+            )
+        }
+        // in case the type refers to itself
+        let placeholder = TypeInner::Any.with_meta(TypeMetadata::default());
+        self.inputs
+            .insert(input.clone(), ReferenceOr::Item(placeholder));
+        let copy = self.input_type(typ, path, &input)?;
+        self.inputs.insert(input.clone(), ReferenceOr::Item(copy));
+        Ok(input)
+    }
+
+    /// Make the input variant of a type, to be found at `to`
+    fn input_type(&mut self, typ: &Type, from: &TypePath, to: &TypePath) -> Result<Type> {
+        let inner = match &typ.typ {
+            TypeInner::Struct(strukt) => {
+                let mut fields = Map::new();
+                for (field, (meta, field_path)) in &strukt.fields {
+                    if !meta.read_only {
+                        let field_path = self.input_of(field_path)?;
+                        fields.insert(field.clone(), (meta.clone(), field_path));
+                    }
+                }
+                let additional = match &strukt.additional {
+                    Additional::Keep(values) => Additional::Keep(self.input_of(values)?),
+                    other => other.clone(),
+                };
+                TypeInner::Struct(Struct { fields, additional })
+            }
+            TypeInner::Array(_) => {
+                // the items of an array are always found at the 'array' child
+                let (from, to) = (child(from, "array"), child(to, "array"));
+                let items = self.input_ref_or(&self.lookup[&from], &from, &to)?;
+                self.inputs.insert(to, items.clone());
+                TypeInner::Array(Box::new(items))
+            }
+            TypeInner::Map(values) => TypeInner::Map(self.input_of(values)?),
+            TypeInner::OneOf(OneOf {
+                variants,
+                discriminator,
+            }) => {
+                let variants = self.input_variants(variants)?;
+                let discriminator = match discriminator {
+                    Some(discriminator) => {
+                        let mut variants = Vec::new();
+                        for (path, values) in &discriminator.variants {
+                            variants.push((self.input_of(path)?, values.clone()));
+                        }
+                        Some(Discriminator {
+                            property: discriminator.property.clone(),
+                            variants,
+                        })
+                    }
+                    None => None,
+                };
+                TypeInner::OneOf(OneOf {
+                    variants,
+                    discriminator,
+                })
+            }
+            TypeInner::AnyOf(variants) => TypeInner::AnyOf(self.input_variants(variants)?),
+            TypeInner::AllOf(parts) => {
+                let mut inputs = Vec::new();
+                for (ix, part) in parts.iter().enumerate() {
+                    let part_name = format!("AllOf_{}", ix);
+                    let (from, to) = (child(from, &part_name), child(to, &part_name));
+                    let input = self.input_ref_or(part, &from, &to)?;
+                    // parts which are not objects are registered
+                    if self.lookup.contains_key(&from) {
+                        self.inputs.insert(to, input.clone());
+                    }
+                    inputs.push(input);
+                }
+                TypeInner::AllOf(inputs)
+            }
+            other => other.clone(),
+        };
+        let mut meta = typ.meta.clone();
+        // the title names the struct, which must be distinct
+        meta.title = meta.title.map(|title| format!("{}Input", title));
+        Ok(inner
+            .with_meta(meta)
+            .with_constraints(typ.constraints.clone()))
+    }
+
+    fn input_ref_or(
+        &mut self,
+        typ: &ReferenceOr<Type>,
+        from: &TypePath,
+        to: &TypePath,
+    ) -> Result<ReferenceOr<Type>> {
+        match typ {
+            ReferenceOr::Reference { reference } => {
+                let input = self.input_of(&TypePath::from_reference(reference)?)?;
+                let reference = input
+                    .to_reference()
+                    .expect("references are to component schemas");
+                Ok(ReferenceOr::Reference { reference })
+            }
+            ReferenceOr::Item(typ) => Ok(ReferenceOr::Item(self.input_type(typ, from, to)?)),
+        }
+    }
+
+    fn input_variants(&mut self, variants: &[(Ident, TypePath)]) -> Result<Vec<(Ident, TypePath)>> {
+        variants
+            .iter()
+            .map(|(name, path)| Ok((name.clone(), self.input_of(path)?)))
+            .collect()
+    }
+}
+
+fn child(path: &TypePath, name: &str) -> TypePath {
+    TypePath::from(ApiPath::from(path.clone()).push(name))
+}
+
+fn walk_component_schemas(
+    schema_lookup: &SchemaLookup,
+    type_index: &mut TypeLookup,
+    extensions: &Extensions,
+    diagnostics: &mut Diagnostics,
+) -> Result<()> {
     let path = ApiPath::default().push("components").push("schemas");
     // gather types defined in components
     for (name, schema) in schema_lookup {
         let path = path.clone().push(name);
-        let typ = build_type_recursive(&schema, path.clone(), type_index)?;
+        let typ = build_type_recursive(&schema, path.clone(), type_index, extensions, diagnostics)?;
         assert!(type_index.insert(TypePath::from(path), typ).is_none());
     }
     Ok(())
 }
 
+/// Where the parts of an operation are looked up, and the types and problems
+/// found in them are gathered
+struct OperationContext<'a> {
+    type_index: &'a mut TypeLookup,
+    extensions: &'a Extensions,
+    components: &'a Components,
+    diagnostics: &'a mut Diagnostics,
+}
+
 fn walk_paths(
     paths: &openapiv3::Paths,
     type_index: &mut TypeLookup,
+    extensions: &Extensions,
     components: &Components,
+    global_security: &[SecurityRequirement],
+    diagnostics: &mut Diagnostics,
 ) -> Result<Map<String, Vec<Route>>> {
     let mut routes: Map<String, Vec<Route>> = Map::new();
     let api_path = ApiPath::default().push("paths");
@@ -256,21 +1199,45 @@ fn walk_paths(
         // TODO lookup rather than unwrap
         let pathitem = unwrap_ref(&ref_or_item)?;
 
-        if !pathitem.parameters.is_empty() {
-            todo!("Path-level paraters are not supported")
-        }
-
         apply_over_operations(pathitem, |op, method| {
-            let api_path = api_path.clone().push(method.to_string());
+            let op_path = api_path.clone().push(method.to_string().to_lowercase());
+            // operation-level security overrides the top-level, and `security: []`
+            // removes it (openapiv3 reads this the same as a missing list)
+            let security = if !op.security.is_empty() {
+                &op.security[..]
+            } else if extensions.is_anonymous(&op_path) {
+                &[]
+            } else {
+                global_security
+            };
+            let params = merge_parameters(
+                &pathitem.parameters,
+                &api_path,
+                &op.parameters,
+                &op_path,
+                components,
+            )?;
+            let cx = OperationContext {
+                type_index,
+                extensions,
+                components,
+                diagnostics,
+            };
             let route = walk_operation(
                 op,
                 method,
-                api_path.clone(),
+                op_path.clone(),
                 &route_path,
-                type_index,
-                components,
+                params,
+                security,
+                cx,
             )?;
-            routes.entry(path.clone()).or_default().push(route);
+            if diagnostics.finish_operation(&op_path) {
+                routes.entry(path.clone()).or_default().push(route);
+            } else {
+                // Leave the operation out, along with any types defined for it
+                type_index.retain(|type_path, _| !type_path.starts_with(&op_path));
+            }
             Ok(())
         })?;
     }
@@ -310,24 +1277,100 @@ where
     Ok(())
 }
 
-fn walk_operation(
-    op: &Operation,
-    method: RawMethod,
-    path: ApiPath,
-    route_path: &RoutePath,
-    type_index: &mut TypeLookup,
-    components: &Components,
-) -> Result<Route> {
-    // TODO: Send in params from path-level
-
+fn parameter_data(param: &Parameter) -> &openapiv3::ParameterData {
     use Parameter::*;
+    match param {
+        Path { parameter_data, .. }
+        | Query { parameter_data, .. }
+        | Header { parameter_data, .. }
+        | Cookie { parameter_data, .. } => parameter_data,
+    }
+}
 
-    if !op.security.is_empty() {
-        todo!("Security not supported")
+fn parameter_location(param: &Parameter) -> &'static str {
+    use Parameter::*;
+    match param {
+        Path { .. } => "path",
+        Query { .. } => "query",
+        Header { .. } => "header",
+        Cookie { .. } => "cookie",
     }
+}
+
+/// Whether a parameter is a single value, rather than an array or object
+fn is_primitive(format: &ParameterSchemaOrContent, components: &Components) -> Result<bool> {
+    let schema = match format {
+        ParameterSchemaOrContent::Schema(schema) => dereference(schema, &components.schemas)?,
+        ParameterSchemaOrContent::Content(_) => return Ok(true),
+    };
+    Ok(match &schema.schema_kind {
+        SchemaKind::Type(ApiType::Array(_)) | SchemaKind::Type(ApiType::Object(_)) => false,
+        SchemaKind::OneOf { .. } | SchemaKind::AnyOf { .. } | SchemaKind::AllOf { .. } => false,
+        SchemaKind::Any(obj) => obj.properties.is_empty() && obj.additional_properties.is_none(),
+        SchemaKind::Type(_) => true,
+    })
+}
+
+/// Combine path-level and operation-level parameters, along with where each is defined.
+/// An operation-level parameter overrides a path-level one with the same name and location.
+fn merge_parameters<'a>(
+    path_level: &'a [ReferenceOr<Parameter>],
+    path_item_path: &ApiPath,
+    op_level: &'a [ReferenceOr<Parameter>],
+    op_path: &ApiPath,
+    components: &'a Components,
+) -> Result<Vec<(&'a Parameter, ApiPath)>> {
+    let locate = |param: &'a ReferenceOr<Parameter>, path: &ApiPath| {
+        let name = match param {
+            ReferenceOr::Item(param) => parameter_data(param).name.as_str(),
+            ReferenceOr::Reference { reference } => reference.as_str(),
+        };
+        let path = path.clone().push_location("parameters").push_location(name);
+        dereference_at(param, &components.parameters, "parameters", path)
+    };
+    let op_level = op_level
+        .iter()
+        .map(|param| locate(param, op_path))
+        .collect::<Result<Vec<_>>>()?;
+    let mut params = Vec::new();
+    for param in path_level {
+        let (param, location) = locate(param, path_item_path)?;
+        let overridden = op_level.iter().any(|(op_param, _)| {
+            let (name, op_name) = (&parameter_data(param).name, &parameter_data(op_param).name);
+            parameter_location(op_param) == parameter_location(param)
+                && match param {
+                    // header names are case-insensitive
+                    Parameter::Header { .. } => name.eq_ignore_ascii_case(op_name),
+                    _ => name == op_name,
+                }
+        });
+        if !overridden {
+            params.push((param, location));
+        }
+    }
+    params.extend(op_level);
+    Ok(params)
+}
+
+fn walk_operation(
+    op: &Operation,
+    method: RawMethod,
+    path: ApiPath,
+    route_path: &RoutePath,
+    params: Vec<(&Parameter, ApiPath)>,
+    security: &[SecurityRequirement],
+    cx: OperationContext,
+) -> Result<Route> {
+    use Parameter::*;
+    let OperationContext {
+        type_index,
+        extensions,
+        components,
+        diagnostics,
+    } = cx;
 
     let (operation_id, path) = match op.operation_id {
-        Some(ref op) => op.parse().map(|opid| (opid, path.push(op))),
+        Some(ref op) => op.parse().map(|opid| (opid, path.push_name(op))),
         None => invalid!("Missing operationId for '{}'", route_path),
     }?;
 
@@ -335,39 +1378,79 @@ fn walk_operation(
 
     let mut path_params = Map::new();
     let mut query_params = Map::new();
+    let mut header_params = Map::new();
+    let mut cookie_params = Map::new();
 
     let mut expected_route_params: Set<&str> = route_path.path_args().collect();
-    let mut duplicate_param_name_check = Set::new();
+    // Every parameter becomes an argument of the handler, so their names must differ.
+    // Path parameters fill in the route, so keep their names
+    let path_param_names = params
+        .iter()
+        .filter(|(param, _)| matches!(param, Path { .. }))
+        .map(|(param, _)| parameter_data(param).name.parse::<Ident>())
+        .collect::<Result<Set<_>>>()?;
+    let mut argument_names = Set::new();
 
-    for param in &op.parameters {
+    for (param, param_path) in params {
         // for each parameter we gather the type but we also need to
         // collect the Queries and Paths to make the parent Query and Path types
-        let param = dereference(param, &components.parameters)?;
-
-        let parameter_data = match param {
-            Path { parameter_data, .. }
-            | Query { parameter_data, .. }
-            | Header { parameter_data, .. }
-            | Cookie { parameter_data, .. } => parameter_data,
-        };
+        let parameter_data = parameter_data(param);
 
-        // We use macros here and below to cut down on duplication between path and query params
+        // We use macros here and below to cut down on duplication between param locations
         macro_rules! build_param_type {
             ($params: ident, $path: expr) => {
-                if !duplicate_param_name_check.insert(&parameter_data.name) {
+                build_param_type!(
+                    $params,
+                    $path,
+                    parameter_data.name.parse::<Ident>()?,
+                    FieldMetadata::default()
+                )
+            };
+            ($params: ident, $path: expr, $name: expr, $meta: expr) => {
+                let mut name: Ident = $name;
+                let mut meta = $meta;
+                // A name used in another location gets the location as a suffix
+                let taken = path_param_names.contains(&name) || argument_names.contains(&name);
+                if $path != "path" && taken && !$params.contains_key(&name) {
+                    if meta.rename.is_none() {
+                        meta = meta.with_rename(parameter_data.name.clone());
+                    }
+                    name = format!("{}_{}", name, $path).parse()?;
+                }
+                if !argument_names.insert(name.clone()) {
                     invalid!("Duplicated parameter '{}'", parameter_data.name)
                 }
-                let path = path.clone().push($path).push(&parameter_data.name);
-                let name: Ident = parameter_data.name.parse()?;
-                let meta = FieldMetadata::default().with_required(parameter_data.required);
-                $params.insert(name, (meta, TypePath::from(path.clone())));
-                match &parameter_data.format {
+                let path = path
+                    .clone()
+                    .push_name($path)
+                    .push_name(&parameter_data.name)
+                    .relocate(&param_path);
+                // a parameter with a default is never missing, so treat it as required
+                let has_default = match &parameter_data.format {
                     ParameterSchemaOrContent::Schema(schema) => {
-                        let typ = build_type_recursive(&schema, path.clone(), type_index)?;
-                        assert!(type_index.insert(TypePath::from(path), typ).is_none());
+                        dereference(schema, &components.schemas)?
+                            .schema_data
+                            .default
+                            .is_some()
                     }
-                    ParameterSchemaOrContent::Content(_) => todo!(),
-                }
+                    ParameterSchemaOrContent::Content(_) => false,
+                };
+                let meta = meta.with_required(parameter_data.required || has_default);
+                $params.insert(name, (meta, TypePath::from(path.clone())));
+                let typ = match &parameter_data.format {
+                    ParameterSchemaOrContent::Schema(schema) => build_type_recursive(
+                        &schema,
+                        path.clone().push_location("schema"),
+                        type_index,
+                        extensions,
+                        diagnostics,
+                    )?,
+                    ParameterSchemaOrContent::Content(_) => {
+                        diagnostics.unsupported_operation(&path, "Parameters with 'content'");
+                        ReferenceOr::Item(TypeInner::Any.with_meta(TypeMetadata::default()))
+                    }
+                };
+                assert!(type_index.insert(TypePath::from(path), typ).is_none());
             };
         }
 
@@ -384,11 +1467,62 @@ fn walk_operation(
                 }
                 build_param_type!(path_params, "path");
             }
-            Query { .. } => {
-                build_param_type!(query_params, "query");
+            Query { style, .. } => {
+                // openapiv3 drops `explode`, so it is gathered with the extensions
+                let explode = extensions.get_explode(param, &param_path)?;
+                let object = match &parameter_data.format {
+                    ParameterSchemaOrContent::Schema(schema) => {
+                        match &dereference(schema, &components.schemas)?.schema_kind {
+                            SchemaKind::Type(ApiType::Object(_)) | SchemaKind::AllOf { .. } => true,
+                            SchemaKind::Any(obj) => {
+                                !obj.properties.is_empty() || obj.additional_properties.is_some()
+                            }
+                            _ => false,
+                        }
+                    }
+                    ParameterSchemaOrContent::Content(_) => false,
+                };
+                build_param_type!(
+                    query_params,
+                    "query",
+                    parameter_data.name.parse()?,
+                    FieldMetadata::default().with_query(QueryParam::new(style, explode, object))
+                );
+            }
+            Header { .. } => {
+                // These are described elsewhere in the spec, so definitions must be ignored
+                let name = parameter_data.name.to_lowercase();
+                if ["accept", "content-type", "authorization"].contains(&name.as_str()) {
+                    continue;
+                }
+                // Headers are sent as a single value, so arrays and objects can't be encoded
+                if !is_primitive(&parameter_data.format, components)? {
+                    diagnostics
+                        .unsupported_operation(&param_path, "Non-primitive header parameters");
+                }
+                // Header names are case-insensitive so always match on lowercase
+                build_param_type!(
+                    header_params,
+                    "header",
+                    name.to_snake_case().parse()?,
+                    FieldMetadata::default().with_rename(name)
+                );
+            }
+            Cookie { .. } => {
+                // Cookies hold a single value, so arrays and objects can't be encoded
+                if !is_primitive(&parameter_data.format, components)? {
+                    diagnostics
+                        .unsupported_operation(&param_path, "Non-primitive cookie parameters");
+                }
+                // Cookie names need not be valid identifiers
+                let name = parameter_data.name.to_snake_case();
+                let meta = if name == parameter_data.name {
+                    FieldMetadata::default()
+                } else {
+                    FieldMetadata::default().with_rename(parameter_data.name.clone())
+                };
+                build_param_type!(cookie_params, "cookie", name.parse()?, meta);
             }
-            Header { .. } => todo!(),
-            Cookie { .. } => todo!(),
         };
     }
 
@@ -408,9 +1542,10 @@ fn walk_operation(
                 // This will be used as an Extractor in actix-web
                 let typ = TypeInner::Struct(Struct {
                     fields: $params.clone(),
+                    additional: Additional::Ignore,
                 })
                 .with_meta(TypeMetadata::default().with_visibility(Visibility::Private));
-                let type_path = TypePath::from(path.clone().push($path));
+                let type_path = TypePath::from(path.clone().push_name($path));
                 let exists = type_index
                     .insert(type_path.clone(), ReferenceOr::Item(typ))
                     .is_some();
@@ -418,18 +1553,32 @@ fn walk_operation(
                 Some((type_path, $params))
             }
         };
-    };
+    }
 
-    let path_params = type_from_params!(path_params, "path");
-    let query_params = type_from_params!(query_params, "query");
+    let params = Parameters {
+        path: type_from_params!(path_params, "path"),
+        query: type_from_params!(query_params, "query"),
+        header: type_from_params!(header_params, "header"),
+        cookie: type_from_params!(cookie_params, "cookie"),
+    };
 
     let body_path: Option<TypePath> = op
         .request_body
         .as_ref()
         .map::<Result<Option<TypePath>>, _>(|reqbody| {
-            let path = path.clone().push("request_body");
-            let reqbody = dereference(reqbody, &components.request_bodies)?;
-            let path: Option<TypePath> = walk_contents(&reqbody.content, path.clone(), type_index)?;
+            let path = path
+                .clone()
+                .push_name("request_body")
+                .push_location("requestBody");
+            let (reqbody, path) =
+                dereference_at(reqbody, &components.request_bodies, "requestBodies", path)?;
+            let path: Option<TypePath> = walk_contents(
+                &reqbody.content,
+                path.clone(),
+                type_index,
+                extensions,
+                diagnostics,
+            )?;
             Ok(path)
         })
         .transpose()?
@@ -437,16 +1586,24 @@ fn walk_operation(
 
     let method = Method::from_raw(method, body_path)?;
 
-    let responses = walk_responses(&op.responses, path, type_index, components)?;
+    let security = walk_security(security, components, &path, diagnostics)?;
+
+    let responses = walk_responses(
+        &op.responses,
+        path,
+        type_index,
+        extensions,
+        components,
+        diagnostics,
+    )?;
 
     let route = Route::new(
-        op.summary.clone(),
-        op.description.clone(),
+        op,
         operation_id,
         method,
         route_path.clone(),
-        path_params,
-        query_params,
+        params,
+        security,
         responses,
     );
 
@@ -457,19 +1614,35 @@ fn walk_contents(
     content: &Map<String, openapiv3::MediaType>,
     path: ApiPath,
     type_index: &mut TypeLookup,
+    extensions: &Extensions,
+    diagnostics: &mut Diagnostics,
 ) -> Result<Option<TypePath>> {
+    let path = path.push_location("content");
     if content.len() > 1 {
-        todo!("Can't have more than one content type");
+        diagnostics.unsupported_operation(&path, "More than one content type");
+        return Ok(None);
     }
     content
         .iter()
         .next()
         .and_then(|(contentty, mediaty)| {
+            let path = path.clone().push_location(contentty);
             if contentty != "application/json" {
-                todo!("Content other than application/json not supported")
+                diagnostics.unsupported_operation(
+                    &path,
+                    format!("Content type '{}' (only application/json)", contentty),
+                );
+                return None;
             }
+            let path = path.push_location("schema");
             mediaty.schema.as_ref().map(|schema| {
-                let typ = build_type_recursive(schema, path.clone(), type_index)?;
+                let typ = build_type_recursive(
+                    schema,
+                    path.clone(),
+                    type_index,
+                    extensions,
+                    diagnostics,
+                )?;
                 assert!(type_index
                     .insert(TypePath::from(path.clone()), typ)
                     .is_none());
@@ -483,7 +1656,9 @@ fn walk_responses(
     resps: &openapiv3::Responses,
     path: ApiPath,
     type_index: &mut TypeLookup,
+    extensions: &Extensions,
     components: &Components,
+    diagnostics: &mut Diagnostics,
 ) -> Result<Responses> {
     let with_codes: Map<StatusCode, Response> = resps
         .responses
@@ -494,13 +1669,12 @@ fn walk_responses(
                     .map_err(|_| Error::Validation(format!("Unknown status code '{}'", v))),
                 ApiStatusCode::Range(v) => invalid!("Status code ranges not supported '{}'", v),
             }?;
-            let resp = dereference(resp, &components.responses)?;
-            walk_response(
-                resp,
-                path.clone().push(code.as_u16().to_string()),
-                type_index,
-            )
-            .map(|pth| (code, pth))
+            let path = path
+                .clone()
+                .push_location("responses")
+                .push(code.as_u16().to_string());
+            let (resp, path) = dereference_at(resp, &components.responses, "responses", path)?;
+            walk_response(resp, path, type_index, extensions, diagnostics).map(|pth| (code, pth))
         })
         .collect::<Result<_>>()?;
 
@@ -508,9 +1682,9 @@ fn walk_responses(
         .default
         .as_ref()
         .map::<Result<Response>, _>(|dflt| {
-            let resp = dereference(dflt, &components.responses)?;
-            let path = path.clone().push("default");
-            walk_response(&resp, path, type_index)
+            let path = path.clone().push_location("responses").push("default");
+            let (resp, path) = dereference_at(dflt, &components.responses, "responses", path)?;
+            walk_response(&resp, path, type_index, extensions, diagnostics)
         })
         .transpose()?;
 
@@ -524,14 +1698,16 @@ fn walk_response(
     resp: &openapiv3::Response,
     path: ApiPath,
     type_index: &mut TypeLookup,
+    extensions: &Extensions,
+    diagnostics: &mut Diagnostics,
 ) -> Result<Response> {
     if !resp.headers.is_empty() {
-        todo!("response headers not supported")
+        diagnostics.unsupported(&path.clone().push_location("headers"), "Response headers");
     }
     if !resp.links.is_empty() {
-        todo!("response links not supported")
+        diagnostics.unsupported(&path.clone().push_location("links"), "Response links");
     }
-    let type_path = walk_contents(&resp.content, path, type_index)?;
+    let type_path = walk_contents(&resp.content, path, type_index, extensions, diagnostics)?;
     Ok(Response {
         type_path,
         description: resp.description.clone(),
@@ -548,6 +1724,8 @@ fn build_type_recursive(
     ref_or_schema: &ReferenceOr<Schema>,
     path: ApiPath,
     type_index: &mut TypeLookup,
+    extensions: &Extensions,
+    diagnostics: &mut Diagnostics,
 ) -> Result<ReferenceOr<Type>> {
     let schema = match ref_or_schema {
         ReferenceOr::Reference { reference } => {
@@ -559,25 +1737,29 @@ fn build_type_recursive(
     };
     let meta = schema.schema_data.clone();
 
-    if let Some(_) = meta.default {
-        todo!("Default values not supported (location: '{}')", path)
-    }
-
-    if let Some(_) = meta.discriminator {
-        todo!("Discriminator values not supported (location: '{}')", path)
+    // If the schema uses anything we can't generate code for, note it down
+    // and fall back to treating it as arbitrary json
+    let unsupported = unsupported_constructs(schema);
+    if !unsupported.is_empty() {
+        for construct in unsupported {
+            diagnostics.unsupported(&path, construct);
+        }
+        return Ok(ReferenceOr::Item(TypeInner::Any.with_meta(meta.into())));
     }
 
     let ty = match &schema.schema_kind {
         SchemaKind::Type(ty) => ty,
         SchemaKind::Any(obj) => {
-            if let Some(_) = obj.additional_properties() {
-                todo!("Additional properties not supported (location: '{}')", path)
+            // openapiv3 can only parse an enum whose values all fit its type
+            if extensions.get_enum(&path).is_some() {
+                diagnostics.unsupported(&path, "Mixed-type enums");
+                return Ok(ReferenceOr::Item(TypeInner::Any.with_meta(meta.into())));
             }
-
-            let inner = if obj.properties.is_empty() {
+            let inner = if obj.properties.is_empty() && obj.additional_properties.is_none() {
                 TypeInner::Any
             } else {
-                TypeInner::Struct(Struct::from_objlike_recursive(obj, path, type_index)?)
+                Struct::from_objlike_recursive(obj, path, type_index, extensions, diagnostics)?
+                    .into_type()
             };
             return Ok(ReferenceOr::Item(inner.with_meta(meta.into())));
         }
@@ -586,10 +1768,36 @@ fn build_type_recursive(
                 .iter()
                 .enumerate()
                 .map(|(ix, schema)| {
-                    let path = path.clone().push(format!("AllOf_{}", ix));
-                    // Note that we do NOT automatically add the sub-types to
-                    // the registry as they may not be needed
-                    build_type_recursive(schema, path, type_index)
+                    let path = path
+                        .clone()
+                        .push_name(format!("AllOf_{}", ix))
+                        .push_location("allOf")
+                        .push_location(ix.to_string());
+                    let typ = build_type_recursive(
+                        schema,
+                        path.clone(),
+                        type_index,
+                        extensions,
+                        diagnostics,
+                    )?;
+                    // Note that we do NOT automatically add objects to the registry
+                    // as their properties are merged. Other parts may be aliased or
+                    // flattened, so they need a name
+                    if let ReferenceOr::Item(Type {
+                        typ:
+                            TypeInner::Primitive(_)
+                            | TypeInner::StringEnum(_)
+                            | TypeInner::ValueEnum(..)
+                            | TypeInner::Array(_)
+                            | TypeInner::Map(_)
+                            | TypeInner::OneOf(_)
+                            | TypeInner::AnyOf(_),
+                        ..
+                    }) = &typ
+                    {
+                        type_index.insert(TypePath::from(path), typ.clone());
+                    }
+                    Ok(typ)
                 })
                 .collect::<Result<Vec<_>>>()?;
             // It's an 'allOf', so at some point we need to costruct a new type by
@@ -600,12 +1808,26 @@ fn build_type_recursive(
             ));
         }
         SchemaKind::AnyOf { any_of: schemas } | SchemaKind::OneOf { one_of: schemas } => {
+            let keyword = match &schema.schema_kind {
+                SchemaKind::AnyOf { .. } => "anyOf",
+                _ => "oneOf",
+            };
             let oneof_types = schemas
                 .iter()
                 .enumerate()
                 .map(|(ix, schema)| {
-                    let path = path.clone().push(format!("OneOf_{}", ix));
-                    let innerty = build_type_recursive(schema, path.clone(), type_index)?;
+                    let path = path
+                        .clone()
+                        .push_name(format!("OneOf_{}", ix))
+                        .push_location(keyword)
+                        .push_location(ix.to_string());
+                    let innerty = build_type_recursive(
+                        schema,
+                        path.clone(),
+                        type_index,
+                        extensions,
+                        diagnostics,
+                    )?;
                     let type_path = TypePath::from(path);
                     assert!(type_index
                         .insert(type_path.clone(), innerty.clone())
@@ -613,38 +1835,152 @@ fn build_type_recursive(
                     Ok(type_path)
                 })
                 .collect::<Result<Vec<_>>>()?;
-            return Ok(ReferenceOr::Item(
-                TypeInner::OneOf(oneof_types).with_meta(meta.into()),
-            ));
+            let names = variant_names(&path.clone().push_location(keyword), schemas, extensions)?;
+            let discriminator = match &schema.schema_data.discriminator {
+                Some(discriminator) => Some(walk_discriminator(discriminator, schemas)?),
+                None => None,
+            };
+            let variants = names.into_iter().zip(oneof_types).collect();
+            // With a discriminator, only one alternative can match, even for anyOf
+            let typ = match (&schema.schema_kind, discriminator) {
+                (SchemaKind::AnyOf { .. }, None) => TypeInner::AnyOf(variants),
+                (_, discriminator) => TypeInner::OneOf(OneOf {
+                    variants,
+                    discriminator,
+                }),
+            };
+            return Ok(ReferenceOr::Item(typ.with_meta(meta.into())));
         }
     };
+    let mut constraints = Constraints::default();
     let typ = match ty {
         // TODO make enums from string
-        // TODO fail on other validation
         // handle the primitives in a straightforward way
         ApiType::String(strty) => {
-            if !strty.format.is_empty() {
-                todo!("String formats not supported (location: '{}')", path)
-            }
-
-            if let Some(_) = strty.pattern {
-                todo!("String patterns not supported (location: '{}')", path)
+            if let Some(pattern) = &strty.pattern {
+                if let Err(e) = Regex::new(pattern) {
+                    invalid!(
+                        "Invalid pattern '{}': {} (location: '{}')",
+                        pattern,
+                        e,
+                        path
+                    )
+                }
             }
-
+            constraints = Constraints {
+                min_count: strty.min_length,
+                max_count: strty.max_length,
+                pattern: strty.pattern.clone(),
+                ..constraints
+            };
             if !strty.enumeration.is_empty() {
                 TypeInner::StringEnum(strty.enumeration.clone())
+            } else if let Some(format) = format_name(&strty.format) {
+                TypeInner::Primitive(Primitive::from_string_format(&format, &path, diagnostics))
             } else {
                 TypeInner::Primitive(Primitive::String)
             }
         }
-        ApiType::Number(_) => TypeInner::Primitive(Primitive::F64),
-        ApiType::Integer(_) => TypeInner::Primitive(Primitive::I64),
-        ApiType::Boolean {} => TypeInner::Primitive(Primitive::Bool),
+        ApiType::Number(numty) => {
+            if let Some(multiple_of) = numty.multiple_of {
+                if multiple_of <= 0.0 || multiple_of.is_nan() {
+                    invalid!(
+                        "Invalid multipleOf {}, it must be greater than 0 (location: '{}')",
+                        multiple_of,
+                        path
+                    )
+                }
+            }
+            constraints = Constraints {
+                minimum: numty
+                    .minimum
+                    .map(|min| (Number::Float(min), numty.exclusive_minimum)),
+                maximum: numty
+                    .maximum
+                    .map(|max| (Number::Float(max), numty.exclusive_maximum)),
+                multiple_of: numty.multiple_of.map(Number::Float),
+                ..constraints
+            };
+            if !numty.enumeration.is_empty() {
+                let values = numty.enumeration.iter().map(|&val| val.into()).collect();
+                TypeInner::ValueEnum(Primitive::F64, values)
+            } else {
+                TypeInner::Primitive(Primitive::from_number_format(
+                    format_name(&numty.format).as_deref(),
+                    &path,
+                    diagnostics,
+                ))
+            }
+        }
+        ApiType::Integer(intty) => {
+            if let Some(multiple_of) = intty.multiple_of {
+                if multiple_of <= 0 {
+                    invalid!(
+                        "Invalid multipleOf {}, it must be greater than 0 (location: '{}')",
+                        multiple_of,
+                        path
+                    )
+                }
+            }
+            constraints = Constraints {
+                minimum: intty
+                    .minimum
+                    .map(|min| (Number::Int(min), intty.exclusive_minimum)),
+                maximum: intty
+                    .maximum
+                    .map(|max| (Number::Int(max), intty.exclusive_maximum)),
+                multiple_of: intty.multiple_of.map(Number::Int),
+                ..constraints
+            };
+            let unsigned =
+                extensions::as_bool(RUST_UNSIGNED, extensions.get(&path, RUST_UNSIGNED))?
+                    .unwrap_or(false);
+            if unsigned && !matches!(intty.minimum, Some(min) if min >= 0) {
+                invalid!(
+                    "Extension '{}' requires a 'minimum' of 0 or more (location: '{}')",
+                    RUST_UNSIGNED,
+                    path
+                )
+            }
+            if !intty.enumeration.is_empty() {
+                let values = intty.enumeration.iter().map(|&val| val.into()).collect();
+                TypeInner::ValueEnum(Primitive::I64, values)
+            } else {
+                TypeInner::Primitive(Primitive::from_integer_format(
+                    format_name(&intty.format).as_deref(),
+                    unsigned,
+                    &path,
+                    diagnostics,
+                ))
+            }
+        }
+        ApiType::Boolean {} => match extensions.get_enum(&path) {
+            None => TypeInner::Primitive(Primitive::Bool),
+            Some(values) if values.iter().all(serde_yaml::Value::is_bool) => {
+                let values = values
+                    .iter()
+                    .filter_map(serde_yaml::Value::as_bool)
+                    .map(serde_json::Value::from)
+                    .collect();
+                TypeInner::ValueEnum(Primitive::Bool, values)
+            }
+            Some(_) => {
+                diagnostics.unsupported(&path, "Mixed-type enums");
+                TypeInner::Any
+            }
+        },
         ApiType::Array(arr) => {
+            constraints = Constraints {
+                min_count: arr.min_items,
+                max_count: arr.max_items,
+                unique_items: arr.unique_items,
+                ..constraints
+            };
             // build the inner-type
             let items = arr.items.clone().unbox();
-            let path = path.clone().push("array");
-            let innerty = build_type_recursive(&items, path.clone(), type_index)?;
+            let path = path.clone().push_name("array").push_location("items");
+            let innerty =
+                build_type_recursive(&items, path.clone(), type_index, extensions, diagnostics)?;
             // add inner type to the registry
             assert!(type_index
                 .insert(TypePath::from(path), innerty.clone())
@@ -652,32 +1988,387 @@ fn build_type_recursive(
             TypeInner::Array(Box::new(innerty))
         }
         ApiType::Object(obj) => {
-            if let Some(_) = obj.additional_properties() {
-                todo!("Additional properties not supported")
+            // Only checked for maps, where the number of properties can vary much
+            constraints = Constraints {
+                min_count: obj.min_properties,
+                max_count: obj.max_properties,
+                ..constraints
+            };
+            Struct::from_objlike_recursive(obj, path.clone(), type_index, extensions, diagnostics)?
+                .into_type()
+        }
+    };
+    if let Some(default) = &meta.default {
+        check_default(&typ, default, meta.nullable, &path)?;
+    }
+    Ok(ReferenceOr::Item(
+        typ.with_meta(meta.into()).with_constraints(constraints),
+    ))
+}
+
+/// Name the variants of a oneOf/anyOf enum, whose alternatives are found at `path`.
+/// A name can be given with `x-rust-variant`, otherwise it is taken from the
+/// referenced schema, the `title`, or the type.
+/// Clashing names are numbered in order, e.g. `String`, `String2`
+fn variant_names(
+    path: &ApiPath,
+    schemas: &[ReferenceOr<Schema>],
+    extensions: &Extensions,
+) -> Result<Vec<Ident>> {
+    let overrides = schemas
+        .iter()
+        .enumerate()
+        .map(|(ix, schema)| {
+            let path = path.clone().push_location(ix.to_string());
+            let found = extensions.get_alternative(&path, schema, RUST_VARIANT);
+            extensions::as_str(RUST_VARIANT, found)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut taken = Set::new();
+    for name in overrides.iter().flatten() {
+        if !taken.insert(name.to_string()) {
+            invalid!(
+                "Duplicate variant name '{}' given by '{}'",
+                name,
+                RUST_VARIANT
+            )
+        }
+    }
+    schemas
+        .iter()
+        .zip(overrides)
+        .map(|(schema, name)| {
+            let name = match name {
+                Some(name) => name.to_string(),
+                None => {
+                    let base = default_variant_name(schema);
+                    let mut name = base.clone();
+                    let mut count = 1;
+                    while taken.contains(&name) {
+                        count += 1;
+                        name = format!("{}{}", base, count);
+                    }
+                    taken.insert(name.clone());
+                    name
+                }
+            };
+            name.parse()
+        })
+        .collect()
+}
+
+fn default_variant_name(schema: &ReferenceOr<Schema>) -> String {
+    let schema = match schema {
+        ReferenceOr::Reference { reference } => {
+            return component_name(reference)
+                .unwrap_or(reference)
+                .to_camel_case()
+        }
+        ReferenceOr::Item(schema) => schema,
+    };
+    if let Some(title) = &schema.schema_data.title {
+        return title.to_camel_case();
+    }
+    let name = match &schema.schema_kind {
+        SchemaKind::Type(ApiType::String(_)) => Primitive::String.to_string(),
+        SchemaKind::Type(ApiType::Number(_)) => Primitive::F64.to_string(),
+        SchemaKind::Type(ApiType::Integer(_)) => Primitive::I64.to_string(),
+        SchemaKind::Type(ApiType::Boolean {}) => Primitive::Bool.to_string(),
+        SchemaKind::Type(ApiType::Array(_)) => "Array".into(),
+        SchemaKind::Type(ApiType::Object(_)) => "Object".into(),
+        SchemaKind::OneOf { .. } => "OneOf".into(),
+        SchemaKind::AllOf { .. } => "AllOf".into(),
+        SchemaKind::AnyOf { .. } => "AnyOf".into(),
+        SchemaKind::Any(_) => "Any".into(),
+    };
+    name.to_camel_case()
+}
+
+/// Work out which values of the discriminator property select each variant.
+/// By default a variant is selected by the name of its schema
+fn walk_discriminator(
+    discriminator: &openapiv3::Discriminator,
+    schemas: &[ReferenceOr<Schema>],
+) -> Result<Discriminator> {
+    let mut variants = Vec::new();
+    for schema in schemas {
+        let reference = match schema {
+            ReferenceOr::Reference { reference } => reference,
+            ReferenceOr::Item(_) => invalid!(
+                "Discriminator '{}' requires every variant to be a reference to a schema",
+                discriminator.property_name
+            ),
+        };
+        let type_path = TypePath::from_reference(reference)?;
+        let mut values: Vec<String> = discriminator
+            .mapping
+            .iter()
+            .filter(|(_, target)| mapping_reference(target) == *reference)
+            .map(|(value, _)| value.clone())
+            .collect();
+        if values.is_empty() {
+            values.push(component_name(reference).unwrap().to_string());
+        }
+        variants.push((type_path, values));
+    }
+    for (value, target) in &discriminator.mapping {
+        let target = mapping_reference(target);
+        if !schemas.iter().any(|schema| match schema {
+            ReferenceOr::Reference { reference } => *reference == target,
+            ReferenceOr::Item(_) => false,
+        }) {
+            invalid!(
+                "Discriminator value '{}' maps to '{}', which is not one of the variants",
+                value,
+                target
+            )
+        }
+    }
+    Ok(Discriminator {
+        property: discriminator.property_name.clone(),
+        variants,
+    })
+}
+
+/// Discriminator mappings may be given as a reference or as a bare schema name
+fn mapping_reference(target: &str) -> String {
+    if target.starts_with('#') {
+        target.to_string()
+    } else {
+        format!("#/components/schemas/{}", target)
+    }
+}
+
+/// Serde consumes the tag of an internally-tagged enum before deserializing
+/// the variant, so each variant gets its own struct without the discriminator
+/// property, named after the enum and the variant. The referenced schemas are
+/// left alone, so they still carry the property when used on their own
+fn apply_discriminators(type_index: &mut TypeLookup) -> Result<()> {
+    let discriminated: Vec<(TypePath, OneOf)> = type_index
+        .iter()
+        .filter_map(|(path, typ)| match typ {
+            ReferenceOr::Item(Type {
+                typ:
+                    TypeInner::OneOf(
+                        oneof @ OneOf {
+                            discriminator: Some(_),
+                            ..
+                        },
+                    ),
+                ..
+            }) => Some((path.clone(), oneof.clone())),
+            _ => None,
+        })
+        .collect();
+    for (path, mut oneof) in discriminated {
+        let discriminator = oneof.discriminator.as_ref().unwrap();
+        let property = &discriminator.property;
+        for ((name, var_path), (type_path, _)) in
+            oneof.variants.iter_mut().zip(&discriminator.variants)
+        {
+            let typ = type_index
+                .get(type_path)
+                .ok_or_else(|| Error::BadReference(ApiPath::from(type_path.clone()).to_string()))?;
+            let (typ, mut strukt) = match typ {
+                ReferenceOr::Item(
+                    typ @ Type {
+                        typ: TypeInner::Struct(strukt),
+                        ..
+                    },
+                ) => (typ, strukt.clone()),
+                ReferenceOr::Item(
+                    typ @ Type {
+                        typ: TypeInner::AllOf(parts),
+                        ..
+                    },
+                ) => match combine_types(type_path, parts, type_index, false)? {
+                    Combined::Struct(strukt, _) => (typ, strukt),
+                    _ => invalid!(
+                        "Discriminated variant '{}' must be an object",
+                        type_path.canonicalize()
+                    ),
+                },
+                _ => invalid!(
+                    "Discriminated variant '{}' must be an object",
+                    type_path.canonicalize()
+                ),
+            };
+            let field = strukt.fields.iter().find_map(|(field, (meta, _))| {
+                let wire_name = meta.rename.as_deref().unwrap_or(field);
+                if wire_name == property {
+                    Some(field.clone())
+                } else {
+                    None
+                }
+            });
+            match field {
+                Some(field) => strukt.fields.shift_remove(&field),
+                None => invalid!(
+                    "Discriminator property '{}' is missing from variant '{}'",
+                    property,
+                    type_path.canonicalize()
+                ),
+            };
+            let variant = child(&path, &name.to_string());
+            if type_index
+                .keys()
+                .any(|other| other.canonicalize() == variant.canonicalize())
+            {
+                invalid!(
+                    "Cannot name the variant '{}' of '{}', as '{}' is already used",
+                    name,
+                    path.canonicalize(),
+                    variant.canonicalize()
+                )
             }
-            TypeInner::Struct(Struct::from_objlike_recursive(obj, path, type_index)?)
+            let variant_type = Type {
+                typ: TypeInner::Struct(strukt),
+                ..typ.clone()
+            };
+            // the alias to the referenced schema is replaced by the variant struct
+            type_index.remove(var_path);
+            type_index.insert(variant.clone(), ReferenceOr::Item(variant_type));
+            *var_path = variant;
+        }
+        if let Some(ReferenceOr::Item(typ)) = type_index.get_mut(&path) {
+            typ.typ = TypeInner::OneOf(oneof);
+        }
+    }
+    Ok(())
+}
+
+/// Check that a default value could be deserialized into its type.
+/// Only the 'shape' is checked, not the contents of arrays and structs
+fn check_default(
+    typ: &TypeInner,
+    default: &serde_json::Value,
+    nullable: bool,
+    path: &ApiPath,
+) -> Result<()> {
+    use serde_json::Value;
+    let ok = match (typ, default) {
+        (_, Value::Null) => nullable,
+        (TypeInner::Primitive(prim), Value::String(val)) => prim.fits_string(val),
+        (TypeInner::Primitive(prim), Value::Number(num)) => prim.fits_number(num),
+        (TypeInner::Primitive(Primitive::Bool), Value::Bool(_)) => true,
+        (TypeInner::StringEnum(variants), Value::String(val)) => variants.contains(val),
+        (TypeInner::ValueEnum(Primitive::F64, values), Value::Number(num)) => {
+            values.iter().any(|val| val.as_f64() == num.as_f64())
         }
+        (TypeInner::ValueEnum(_, values), val) => values.contains(val),
+        (TypeInner::Array(_), Value::Array(_)) => true,
+        (TypeInner::Struct(_), Value::Object(_)) => true,
+        (TypeInner::Map(_), Value::Object(_)) => true,
+        (TypeInner::Boxed(_), _) => true,
+        _ => false,
+    };
+    if ok {
+        Ok(())
+    } else {
+        invalid!(
+            "Default value {} does not fit type (location: '{}')",
+            default,
+            path
+        )
+    }
+}
+
+/// Whether a `YYYY-MM-DD` string is a real date
+fn is_date(date: &str) -> bool {
+    let parts: Vec<u32> = date
+        .split('-')
+        .filter_map(|part| part.parse().ok())
+        .collect();
+    let (year, month, day) = match parts[..] {
+        [year, month, day] => (year, month, day),
+        _ => return false,
+    };
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
     };
-    Ok(ReferenceOr::Item(typ.with_meta(meta.into())))
+    (1..=days).contains(&day)
+}
+
+/// List the constructs used (directly) by a schema which we cannot generate code for
+fn unsupported_constructs(schema: &Schema) -> Vec<String> {
+    let mut found = Vec::new();
+    let data = &schema.schema_data;
+    // Only discriminated oneOf/anyOf is supported, not inheritance via allOf
+    let composite = matches!(
+        schema.schema_kind,
+        SchemaKind::OneOf { .. } | SchemaKind::AnyOf { .. }
+    );
+    if data.discriminator.is_some() && !composite {
+        found.push("Discriminators".into());
+    }
+    found
+}
+
+/// The name of a `format`, as it was written in the spec
+fn format_name<T: serde::Serialize>(format: &VariantOrUnknownOrEmpty<T>) -> Option<String> {
+    match format {
+        VariantOrUnknownOrEmpty::Item(item) => serde_json::to_value(item)
+            .ok()
+            .and_then(|val| val.as_str().map(String::from)),
+        VariantOrUnknownOrEmpty::Unknown(name) => Some(name.clone()),
+        VariantOrUnknownOrEmpty::Empty => None,
+    }
 }
 
 /// Generate code that defines a `struct` or `type` alias for each object found
 /// in the OpenAPI definition
-pub(crate) fn generate_rust_types(types: &TypeLookup) -> Result<TokenStream> {
+pub(crate) fn generate_rust_types(
+    types: &TypeLookup,
+    options: &Options,
+    diagnostics: &mut Diagnostics,
+) -> Result<TokenStream> {
     let mut tokens = TokenStream::new();
+    let mut errors = Vec::new();
     for (typepath, typ) in types {
-        let def = generate_rust_type(typepath, typ, types)?;
-        tokens.extend(def);
+        match generate_rust_type(typepath, typ, types, options) {
+            Ok(def) => tokens.extend(def),
+            Err(Error::Unsupported { path, construct }) if diagnostics.lenient => {
+                // fall back to 'any' json
+                let name = typepath.canonicalize();
+                tokens.extend(quote! { type #name = JsonValue; });
+                diagnostics.unsupported(&path, construct);
+            }
+            Err(e) => errors.push(e),
+        }
     }
+    Error::collect(errors)?;
     Ok(tokens)
 }
 
+/// Whether a field is represented by an `Option`
+fn is_optional(
+    meta: &FieldMetadata,
+    field_type_path: &TypePath,
+    lookup: &TypeLookup,
+) -> Result<bool> {
+    if meta.flatten {
+        return Ok(false);
+    }
+    let ref_or = lookup
+        .get(field_type_path)
+        .ok_or_else(|| Error::BadReference(field_type_path.canonicalize().to_string()))?;
+    let field_type = lookup_type_recursive(ref_or, lookup)?;
+    // A field with a default value is never missing, so never needs an Option
+    Ok(!(field_type.meta.nullable || meta.required || field_type.meta.default.is_some()))
+}
+
 /// Generate code that defines a `struct` or `type` alias for each object found
 /// in the OpenAPI definition
 fn generate_rust_type(
     type_path: &TypePath,
     typ: &ReferenceOr<Type>,
     lookup: &TypeLookup,
+    options: &Options,
 ) -> Result<TokenStream> {
     debug!("generate: {}", ApiPath::from(type_path.clone()));
     let name = type_path.canonicalize();
@@ -701,25 +2392,106 @@ fn generate_rust_type(
                     }
                 }
                 T::AllOf(parts) => {
-                    let strukt = combine_types(parts, lookup)?;
-                    let typ =
-                        ReferenceOr::Item(TypeInner::Struct(strukt).with_meta(typ.meta.clone()));
-                    // Defer to struct impl
-                    generate_rust_type(type_path, &typ, lookup)?
+                    match combine_types(type_path, parts, lookup, options.flatten_all_of)? {
+                        Combined::Struct(strukt, parents) => {
+                            let conversions = if typ.meta.nullable {
+                                None
+                            } else {
+                                Some(generate_conversions(&name, &strukt, &parents, lookup)?)
+                            };
+                            let typ = ReferenceOr::Item(
+                                TypeInner::Struct(strukt).with_meta(typ.meta.clone()),
+                            );
+                            // Defer to struct impl
+                            let def = generate_rust_type(type_path, &typ, lookup, options)?;
+                            quote! { #def #conversions }
+                        }
+                        Combined::Alias(target) => {
+                            let descr = typ.meta.description();
+                            let target_nullable = lookup_type_recursive(&lookup[&target], lookup)?
+                                .meta
+                                .nullable;
+                            let target = target.canonicalize();
+                            if typ.meta.nullable && !target_nullable {
+                                quote! {
+                                    #descr
+                                    type #name = Option<#target>;
+                                }
+                            } else {
+                                quote! {
+                                    #descr
+                                    type #name = #target;
+                                }
+                            }
+                        }
+                        Combined::Any => {
+                            let descr = typ.meta.description();
+                            quote! {
+                                #descr
+                                type #name = JsonValue;
+                            }
+                        }
+                    }
+                }
+                T::OneOf(OneOf {
+                    variants,
+                    discriminator: None,
+                }) => {
+                    let variants: Vec<_> = variants
+                        .iter()
+                        .map(|(name, var)| Variant::new(name.clone()).type_path(Some(var.clone())))
+                        .collect();
+                    let tagging = if options.strict_one_of {
+                        Tagging::Strict
+                    } else {
+                        Tagging::Untagged
+                    };
+                    let def = generate_enum_def(&name, &typ.meta, &variants, None, tagging);
+                    let validate_impl = generate_one_of_validation(&name, &variants, lookup)?;
+                    quote! { #def #validate_impl }
                 }
-                T::OneOf(variants) => {
+                T::AnyOf(variants) => {
+                    let def = generate_any_of_def(&name, &typ.meta, variants);
+                    let mut validations = Vec::new();
+                    for (var, var_path) in variants {
+                        let field = Ident::from_property(var);
+                        let checks = generate_validation(
+                            var_path,
+                            quote! { value },
+                            quote! { pointer },
+                            lookup,
+                        )?;
+                        validations.extend(checks.map(|checks| {
+                            quote! {
+                                if let Some(value) = &self.#field {
+                                    #checks
+                                }
+                            }
+                        }));
+                    }
+                    let validate_impl = generate_validate_impl(&name, quote! { #(#validations)* });
+                    quote! { #def #validate_impl }
+                }
+                T::OneOf(OneOf {
+                    variants,
+                    discriminator: Some(discriminator),
+                }) => {
                     let variants: Vec<_> = variants
                         .iter()
-                        .enumerate()
-                        .map(|(ix, var)| {
-                            Variant::new(format!("V{}", ix + 1).parse().unwrap())
+                        .zip(&discriminator.variants)
+                        .map(|((name, var), (_, values))| {
+                            Variant::new(name.clone())
                                 .type_path(Some(var.clone()))
+                                .rename(values[0].clone())
+                                .aliases(values[1..].to_vec())
                         })
                         .collect();
-                    generate_enum_def(&name, &typ.meta, &variants, None, true)
+                    let tagging = Tagging::Internal(discriminator.property.clone());
+                    let def = generate_enum_def(&name, &typ.meta, &variants, None, tagging);
+                    let validate_impl = generate_one_of_validation(&name, &variants, lookup)?;
+                    quote! { #def #validate_impl }
                 }
-                T::Primitive(p) => {
-                    let id = crate::ident(p);
+                T::Primitive(id) => {
                     let descr = typ.meta.description();
                     let ty = if typ.meta.nullable {
                         quote! {
@@ -736,15 +2508,15 @@ fn generate_rust_type(
                     }
                 }
                 T::StringEnum(variants) => {
-                    let variants: Vec<_> = variants
-                        .iter()
-                        .map(|var| {
-                            let var =
-                                Variant::new(var.to_camel_case().parse()?).rename(var.clone());
-                            Ok(var)
-                        })
-                        .collect::<Result<_>>()?;
-                    generate_enum_def(&name, &typ.meta, &variants, None, false)
+                    let variants: Vec<_> = enum_variant_names(variants)
+                        .into_iter()
+                        .zip(variants)
+                        .map(|(var_name, var)| Variant::new(var_name).rename(var.clone()))
+                        .collect();
+                    generate_enum_def(&name, &typ.meta, &variants, None, Tagging::External)
+                }
+                T::ValueEnum(prim, values) => {
+                    generate_value_enum_def(&name, &typ.meta, prim, values)
                 }
                 T::Array(_) => {
                     let path = ApiPath::from(type_path.clone());
@@ -764,9 +2536,31 @@ fn generate_rust_type(
                         }
                     }
                 }
+                T::Map(values) => {
+                    let values = values.canonicalize();
+                    let descr = typ.meta.description();
+                    let map = quote! { std::collections::HashMap<String, #values> };
+                    if typ.meta.nullable {
+                        quote! {
+                            #descr
+                            type #name = Option<#map>;
+                        }
+                    } else {
+                        quote! {
+                            #descr
+                            type #name = #map;
+                        }
+                    }
+                }
                 T::Struct(strukt) => {
                     generate_struct_def(strukt, &name, type_path, &typ.meta, lookup)?
                 }
+                T::Boxed(target) => {
+                    let target = target.canonicalize();
+                    quote! {
+                        type #name = Box<#target>;
+                    }
+                }
             }
         }
     };
@@ -783,10 +2577,13 @@ fn generate_struct_def(
     let fieldnames: Vec<_> = strukt.fields.iter().map(|(field, _)| field).collect();
     let visibility = meta.visibility;
     let descr = meta.description();
+    let mut field_attrs = Vec::new();
+    let mut default_fns = Vec::new();
+    let mut validations = Vec::new();
     let fields: Vec<TokenStream> = strukt
         .fields
         .iter()
-        .map(|(_field, (meta, field_type_path))| {
+        .map(|(field, (meta, field_type_path))| {
             // Tricky bit. The field may be 'not required', from POV of the struct
             // but also the type itself may be nullable. This is supposed to represent
             // how in javascript an object key may be 'missing', or it may be 'null'
@@ -798,14 +2595,84 @@ fn generate_struct_def(
 
             let ref_or = lookup.get(&field_type_path).unwrap(); // this lookup should not fail
             let field_type = lookup_type_recursive(ref_or, lookup)?; // this one can
-            let required = meta.required;
-            let nullable = field_type.meta.nullable;
             let field_type_name = field_type_path.canonicalize();
-            let def = if nullable || (required && !nullable) {
+            let optional = is_optional(meta, field_type_path, lookup)?;
+            let def = if optional {
+                quote! {Option<#field_type_name>}
+            } else {
                 quote! {#field_type_name}
+            };
+
+            let wire_name = meta.rename.clone().unwrap_or_else(|| field.to_string());
+            let pointer = if meta.flatten {
+                // the fields are found directly in the object
+                quote! { pointer }
             } else {
-                quote! {Option<#field_type_name>}
+                quote! { &hsr::validate::pointer(pointer, #wire_name) }
+            };
+            if optional {
+                let checks =
+                    generate_validation(field_type_path, quote! { value }, pointer, lookup)?;
+                validations.extend(checks.map(|checks| {
+                    quote! {
+                        if let Some(value) = &self.#field {
+                            #checks
+                        }
+                    }
+                }));
+            } else {
+                let value = quote! { &self.#field };
+                validations.extend(generate_validation(
+                    field_type_path,
+                    value,
+                    pointer,
+                    lookup,
+                )?);
+            }
+
+            let rename = meta
+                .rename
+                .as_ref()
+                .map(|rename| quote! { #[serde(rename = #rename)] });
+            let default = field_type.meta.default.as_ref().map(|json| {
+                let default_fn = ident(format!(
+                    "default_{}_{}",
+                    name.to_snake_case(),
+                    field.to_snake_case()
+                ));
+                let default_fn_name = default_fn.to_string();
+                default_fns.push((
+                    field,
+                    default_fn.clone(),
+                    quote! {
+                        fn #default_fn() -> #def {
+                            hsr::serde_json::from_str(#json).unwrap()
+                        }
+                    },
+                ));
+                quote! { #[serde(default = #default_fn_name)] }
+            });
+            let flatten = if meta.flatten {
+                Some(quote! { #[serde(flatten)] })
+            } else {
+                None
+            };
+            // Decimals are sent as JSON numbers without going through an f64
+            let with = match field_type.typ {
+                TypeInner::Primitive(Primitive::Decimal) if optional => Some(quote! {
+                    #[serde(default, with = "hsr::rust_decimal::serde::arbitrary_precision_option")]
+                }),
+                TypeInner::Primitive(Primitive::Decimal) if field_type.meta.nullable => {
+                    Some(quote! {
+                        #[serde(with = "hsr::rust_decimal::serde::arbitrary_precision_option")]
+                    })
+                }
+                TypeInner::Primitive(Primitive::Decimal) => Some(quote! {
+                    #[serde(with = "hsr::rust_decimal::serde::arbitrary_precision")]
+                }),
+                _ => None,
             };
+            field_attrs.push(quote! { #rename #default #flatten #with });
             Ok(def)
         })
         .collect::<Result<_>>()?;
@@ -816,55 +2683,120 @@ fn generate_struct_def(
     // rename the type to the 'title', and also perhaps make it an 'nullable'
     // which amounts to creating an inner type and then aliasing to Option<Inner>
     // So now we handle these various cases
-    let tokens = match (&meta.title, meta.nullable) {
-        (None, false) => {
-            quote! {
-                #descr
-                #derives
-                #visibility struct #name {
-                    #(pub #fieldnames: #fields),*
-                }
-            }
-        }
+    let (struct_name, alias) = match (&meta.title, meta.nullable) {
+        (None, false) => (name.to_string().parse::<Ident>()?, None),
         (None, true) => {
             let new_path = TypePath::from(ApiPath::from(type_path.clone()).push("opt"));
             let new_name = new_path.canonicalize();
-            quote! {
-                #descr
-                #derives
-                #visibility struct #new_name {
-                    #(pub #fieldnames: #fields),*
-                }
-                #visibility type #name = Option<#new_name>;
-            }
+            (
+                new_name.to_string().parse()?,
+                Some(quote! {
+                    #visibility type #name = Option<#new_name>;
+                }),
+            )
         }
         (Some(title), false) => {
             let new_name = title.parse::<Ident>()?;
-            quote! {
-                #descr
-                #derives
-                #visibility struct #new_name {
-                    #(pub #fieldnames: #fields),*
-                }
+            let alias = quote! {
                 // This alias is not visible because we prefer to use new_name
                 type #name = #new_name;
-            }
+            };
+            (new_name, Some(alias))
         }
         (Some(title), true) => {
             let new_name = title.parse::<Ident>()?;
-            quote! {
-                #descr
-                #derives
-                #visibility struct #new_name {
-                    #(pub #fieldnames: #fields),*
-                }
+            let alias = quote! {
                 #visibility type #name = Option<#new_name>;
+            };
+            (new_name, Some(alias))
+        }
+    };
+
+    // If every field has a default, so does the struct
+    let default_impl = if !default_fns.is_empty() && default_fns.len() == fieldnames.len() {
+        let extra_default = match &strukt.additional {
+            Additional::Keep(_) => {
+                let extra_field = ident(EXTRA_FIELD);
+                Some(quote! { #extra_field: Default::default() })
+            }
+            _ => None,
+        };
+        let fields = default_fns.iter().map(|(field, _, _)| field);
+        let default_fns = default_fns.iter().map(|(_, default_fn, _)| default_fn);
+        Some(quote! {
+            impl Default for #struct_name {
+                fn default() -> Self {
+                    Self {
+                        #(#fields: #default_fns(),)*
+                        #extra_default
+                    }
+                }
             }
+        })
+    } else {
+        None
+    };
+    let default_fns = default_fns.iter().map(|(_, _, def)| def);
+
+    let extra_field = ident(EXTRA_FIELD);
+    let (deny, extra) = match &strukt.additional {
+        Additional::Ignore => (None, None),
+        Additional::Deny => (Some(quote! { #[serde(deny_unknown_fields)] }), None),
+        Additional::Keep(values) => {
+            let checks = generate_validation(
+                values,
+                quote! { item },
+                quote! { &hsr::validate::pointer(pointer, key) },
+                lookup,
+            )?;
+            validations.extend(checks.map(|checks| {
+                quote! {
+                    for (key, item) in &self.#extra_field {
+                        #checks
+                    }
+                }
+            }));
+            let values = values.canonicalize();
+            let extra = quote! {
+                /// Properties which are not listed in the schema
+                #[serde(flatten)]
+                pub #extra_field: std::collections::HashMap<String, #values>,
+            };
+            (None, Some(extra))
         }
     };
+
+    let validate_impl = generate_validate_impl(&struct_name, quote! { #(#validations)* });
+
+    let tokens = quote! {
+        #descr
+        #derives
+        #deny
+        #visibility struct #struct_name {
+            #(#field_attrs pub #fieldnames: #fields,)*
+            #extra
+        }
+        #alias
+        #(#default_fns)*
+        #default_impl
+        #validate_impl
+    };
     Ok(tokens)
 }
 
+/// How serde represents the variant of an enum
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Tagging {
+    /// The serde default, e.g. '{"Variant": {..}}' or '"Variant"'
+    External,
+    /// Nothing, the first variant which fits is taken
+    Untagged,
+    /// Nothing, and exactly one variant must fit
+    Strict,
+    /// A property of the variant object itself
+    Internal(String),
+}
+
 /// TODO If there are multiple different error types, construct an
 /// enum to hold them all. If there is only one or none, don't bother.
 pub(crate) fn generate_enum_def(
@@ -872,7 +2804,7 @@ pub(crate) fn generate_enum_def(
     meta: &TypeMetadata,
     variants: &[Variant],
     dflt: Option<&Variant>,
-    untagged: bool,
+    tagging: Tagging,
 ) -> TokenStream {
     if variants.is_empty() && dflt.is_none() {
         // Should not be able to get here (?)
@@ -881,10 +2813,15 @@ pub(crate) fn generate_enum_def(
 
     // should serde do untagged serialization?
     // (The answer should be 'no', unless it is a OneOf/AnyOf type)
-    let serde_tag = if untagged {
-        Some(quote! {#[serde(untagged)]})
-    } else {
-        None
+    let serde_tag = match &tagging {
+        Tagging::External => None,
+        Tagging::Untagged => Some(quote! {#[serde(untagged)]}),
+        Tagging::Strict => Some(quote! {#[serde(untagged, try_from = "JsonValue")]}),
+        Tagging::Internal(property) => Some(quote! {#[serde(tag = #property)]}),
+    };
+    let strict_impl = match tagging {
+        Tagging::Strict => Some(generate_strict_try_from(name, variants)),
+        _ => None,
     };
 
     // Special-case the default variant
@@ -915,33 +2852,734 @@ pub(crate) fn generate_enum_def(
             #(#variants,)*
             #default
         }
+        #strict_impl
     }
 }
 
-fn combine_types(parts: &[ReferenceOr<Type>], lookup: &TypeLookup) -> Result<Struct> {
-    // We do the combination in a simplistic way: assume parent types are structs,
-    // and add all the fields into a new struct. Reject duplicates
-    let mut base = Map::new();
-    for part in parts.iter() {
-        let typ = lookup_type_recursive(part, lookup)?;
-        match &typ.typ {
-            TypeInner::Struct(strukt) => {
-                for (field, required) in &strukt.fields {
-                    if let Some(_) = base.insert(field, required) {
-                        // duplicate field
-                        invalid!("Duplicate field '{}'", field);
-                    }
-                }
-            }
-            _ => todo!("Non-struct allOf combinations are not supported"),
+/// Name the fields of a struct after its properties. Where several properties
+/// convert to the same name, a property which is already that name keeps it,
+/// and the others are numbered in the order they are declared.
+/// Reserved names are numbered too, even when they match their property
+fn field_names(properties: &[&str], reserved: &[&str]) -> Result<Vec<Ident>> {
+    let candidates: Vec<Ident> = properties
+        .iter()
+        .map(|property| Ident::from_property(property))
+        .collect();
+    let keeps_name = |candidate: &Ident, property: &str| {
+        candidate.as_str() == property && !reserved.contains(&property)
+    };
+    let mut taken: BTreeSet<String> = candidates
+        .iter()
+        .zip(properties)
+        .filter(|(candidate, property)| keeps_name(candidate, property))
+        .map(|(candidate, _)| candidate.to_string())
+        .chain(reserved.iter().map(|name| name.to_string()))
+        .collect();
+    let mut names = Vec::new();
+    for (candidate, property) in candidates.into_iter().zip(properties) {
+        if keeps_name(&candidate, property) {
+            names.push(candidate);
+            continue;
+        }
+        let mut name = candidate.to_string();
+        let mut count = 2;
+        while taken.contains(&name) {
+            name = format!("{}_{}", candidate, count);
+            count += 1;
         }
+        taken.insert(name.clone());
+        names.push(name.parse()?);
     }
-    Ok(Struct {
-        fields: base
-            .into_iter()
-            .map(|(n, m)| (n.clone(), m.clone()))
-            .collect(),
-    })
+    Ok(names)
+}
+
+/// Name the variants of an enum after its values. Values which don't make a valid
+/// name (including `Self`) are named after their position, and clashing names are numbered
+fn enum_variant_names(values: &[impl AsRef<str>]) -> Vec<Ident> {
+    let mut names: Vec<Ident> = Vec::new();
+    for (ix, value) in values.iter().enumerate() {
+        let mut name = value.as_ref().to_camel_case();
+        if name.starts_with(|c: char| c.is_ascii_digit()) {
+            name = format!("Value{}", name).to_camel_case();
+        }
+        let invalid = !name.chars().all(|c| c.is_ascii_alphanumeric()) || name.is_empty();
+        if invalid || UNRAW_KEYWORDS.contains(&name.as_str()) {
+            name = format!("Value{}", ix);
+        }
+        let mut candidate = name.clone();
+        let mut count = 1;
+        while names
+            .iter()
+            .any(|existing| existing.to_string() == candidate)
+        {
+            count += 1;
+            candidate = format!("{}{}", name, count);
+        }
+        names.push(candidate.parse().expect("variant names are alphanumeric"));
+    }
+    names
+}
+
+/// An integer, number or boolean enum is (de)serialized as its value,
+/// via conversions to and from the primitive
+fn generate_value_enum_def(
+    name: &TypeName,
+    meta: &TypeMetadata,
+    prim: &Primitive,
+    values: &[serde_json::Value],
+) -> TokenStream {
+    let labels: Vec<String> = values
+        .iter()
+        .map(|val| match val {
+            serde_json::Value::Number(num) => {
+                let label = num.to_string().replace('.', "Point");
+                match label.strip_prefix('-') {
+                    Some(label) => format!("ValueMinus{}", label),
+                    None => format!("Value{}", label),
+                }
+            }
+            other => other.to_string(),
+        })
+        .collect();
+    let names = enum_variant_names(&labels);
+    let literals: Vec<_> = values
+        .iter()
+        .map(|val| match (prim, val) {
+            (Primitive::F64, val) => {
+                let lit = proc_macro2::Literal::f64_suffixed(val.as_f64().unwrap());
+                quote! { #lit }
+            }
+            (_, serde_json::Value::Number(num)) => {
+                let lit = proc_macro2::Literal::i64_suffixed(num.as_i64().unwrap());
+                quote! { #lit }
+            }
+            (_, val) => {
+                let val = val.as_bool().unwrap();
+                quote! { #val }
+            }
+        })
+        .collect();
+    let derives = get_derive_tokens();
+    let visibility = meta.visibility;
+    let descr = meta.description();
+    let repr = prim.to_string();
+    let repr_ty = quote! { #prim };
+    let invalid = format!("invalid value {{}} for enum '{}'", name);
+    quote! {
+        #descr
+        #derives
+        #[derive(Copy, Eq, Hash)]
+        #[serde(try_from = #repr, into = #repr)]
+        #visibility enum #name {
+            #(#names,)*
+        }
+
+        impl std::convert::TryFrom<#repr_ty> for #name {
+            type Error = String;
+            fn try_from(value: #repr_ty) -> Result<Self, String> {
+                #(
+                    if value == #literals {
+                        return Ok(#name::#names);
+                    }
+                )*
+                Err(format!(#invalid, value))
+            }
+        }
+
+        impl From<#name> for #repr_ty {
+            fn from(value: #name) -> #repr_ty {
+                match value {
+                    #(#name::#names => #literals,)*
+                }
+            }
+        }
+    }
+}
+
+/// Deserialize a oneOf by trying every variant, and fail unless exactly one fits
+fn generate_strict_try_from(name: &TypeName, variants: &[Variant]) -> TokenStream {
+    let names = variants.iter().map(|var| &var.name);
+    let no_match = format!("data did not match any variant of oneOf '{}'", name);
+    let many_matches = format!(
+        "data matched {{}} variants of oneOf '{}', expected exactly one",
+        name
+    );
+    quote! {
+        impl std::convert::TryFrom<JsonValue> for #name {
+            type Error = String;
+            fn try_from(value: JsonValue) -> Result<Self, String> {
+                let mut matched = Vec::new();
+                #(
+                    if let Ok(var) = hsr::serde_json::from_value(value.clone()) {
+                        matched.push(#name::#names(var));
+                    }
+                )*
+                match matched.len() {
+                    1 => Ok(matched.remove(0)),
+                    0 => Err(#no_match.into()),
+                    n => Err(format!(#many_matches, n)),
+                }
+            }
+        }
+    }
+}
+
+/// An anyOf becomes a struct with an optional field for each alternative.
+/// Every alternative which fits the data is filled in
+fn generate_any_of_def(
+    name: &TypeName,
+    meta: &TypeMetadata,
+    variants: &[(Ident, TypePath)],
+) -> TokenStream {
+    let fields: Vec<_> = variants
+        .iter()
+        .map(|(var, _)| Ident::from_property(var))
+        .collect();
+    let types = variants.iter().map(|(_, path)| path.canonicalize());
+    let derives = get_derive_tokens();
+    let visibility = meta.visibility;
+    let descr = meta.description();
+    let no_match = format!("data did not match any schema of anyOf '{}'", name);
+    quote! {
+        #descr
+        #derives
+        #[derive(Default)]
+        #[serde(try_from = "JsonValue", into = "JsonValue")]
+        #visibility struct #name {
+            #(pub #fields: Option<#types>),*
+        }
+
+        impl std::convert::TryFrom<JsonValue> for #name {
+            type Error = String;
+            fn try_from(value: JsonValue) -> Result<Self, String> {
+                let any_of = Self {
+                    #(#fields: hsr::serde_json::from_value(value.clone()).ok()),*
+                };
+                if #(any_of.#fields.is_none())&&* {
+                    return Err(#no_match.into());
+                }
+                Ok(any_of)
+            }
+        }
+
+        impl From<#name> for JsonValue {
+            fn from(any_of: #name) -> JsonValue {
+                let values = vec![#(
+                    any_of.#fields.map(|val| hsr::serde_json::to_value(val).unwrap())
+                ),*];
+                hsr::merge_json(values.into_iter().flatten())
+            }
+        }
+    }
+}
+
+/// Generate the statements which validate a value of the type at `type_path`,
+/// pushing any violations to `violations`. `value` must be a reference to the value,
+/// and `pointer` its JSON pointer. Returns `None` if there is nothing to check.
+///
+/// Generated structs and enums implement `hsr::Validate`, but the constraints of
+/// other types (which are aliases, e.g. of `String`) are checked here directly
+pub(crate) fn generate_validation(
+    type_path: &TypePath,
+    value: TokenStream,
+    pointer: TokenStream,
+    lookup: &TypeLookup,
+) -> Result<Option<TokenStream>> {
+    let typ = match lookup.get(type_path) {
+        Some(ReferenceOr::Reference { reference }) => {
+            let target = TypePath::from_reference(reference)?;
+            return generate_validation(&target, value, pointer, lookup);
+        }
+        Some(ReferenceOr::Item(typ)) => typ,
+        None => return Err(Error::BadReference(type_path.canonicalize().to_string())),
+    };
+    use TypeInner as T;
+    let checks = match &typ.typ {
+        T::Boxed(target) => {
+            return generate_validation(target, quote! { &**(#value) }, pointer, lookup)
+        }
+        T::AllOf(parts) => match combine_types(type_path, parts, lookup, false)? {
+            Combined::Struct(..) => quote! {
+                hsr::Validate::validate_at(value, pointer, violations);
+            },
+            Combined::Alias(target) if typ.meta.nullable => {
+                match generate_validation(&target, quote! { value }, quote! { pointer }, lookup)? {
+                    Some(checks) => checks,
+                    None => return Ok(None),
+                }
+            }
+            Combined::Alias(target) => return generate_validation(&target, value, pointer, lookup),
+            Combined::Any => return Ok(None),
+        },
+        T::Struct(_) | T::OneOf(_) | T::AnyOf(_) => quote! {
+            hsr::Validate::validate_at(value, pointer, violations);
+        },
+        T::StringEnum(_) | T::ValueEnum(..) | T::Any => return Ok(None),
+        T::Primitive(prim) => typ.constraints.generate_primitive_checks(prim),
+        T::Array(_) => {
+            let mut checks = typ
+                .constraints
+                .generate_count_checks(quote! { value.iter() }, "items");
+            if typ.constraints.unique_items {
+                checks.extend(quote! {
+                    hsr::validate::check_unique(value, pointer, violations);
+                });
+            }
+            let items = TypePath::from(ApiPath::from(type_path.clone()).push("array"));
+            let item_checks = generate_validation(
+                &items,
+                quote! { item },
+                quote! { &hsr::validate::pointer(pointer, ix) },
+                lookup,
+            )?;
+            if let Some(item_checks) = item_checks {
+                checks.extend(quote! {
+                    for (ix, item) in value.iter().enumerate() {
+                        #item_checks
+                    }
+                });
+            }
+            checks
+        }
+        T::Map(values) => {
+            let mut checks = typ
+                .constraints
+                .generate_count_checks(quote! { value.iter() }, "properties");
+            let value_checks = generate_validation(
+                values,
+                quote! { item },
+                quote! { &hsr::validate::pointer(pointer, key) },
+                lookup,
+            )?;
+            if let Some(value_checks) = value_checks {
+                checks.extend(quote! {
+                    for (key, item) in value.iter() {
+                        #value_checks
+                    }
+                });
+            }
+            checks
+        }
+    };
+    if checks.is_empty() {
+        return Ok(None);
+    }
+    let checks = if typ.meta.nullable {
+        quote! {
+            if let Some(value) = #value {
+                let pointer: &str = #pointer;
+                #checks
+            }
+        }
+    } else {
+        quote! {
+            {
+                let value = #value;
+                let pointer: &str = #pointer;
+                #checks
+            }
+        }
+    };
+    Ok(Some(checks))
+}
+
+/// Generate the `hsr::Validate` implementation of a oneOf, which validates the variant
+fn generate_one_of_validation(
+    name: &TypeName,
+    variants: &[Variant],
+    lookup: &TypeLookup,
+) -> Result<TokenStream> {
+    let mut arms = Vec::new();
+    for variant in variants {
+        let var_name = &variant.name;
+        let var_path = variant
+            .type_path
+            .as_ref()
+            .expect("oneOf variants have a type");
+        let checks = generate_validation(var_path, quote! { value }, quote! { pointer }, lookup)?;
+        arms.push(quote! {
+            #name::#var_name(value) => { #checks }
+        });
+    }
+    Ok(generate_validate_impl(
+        name,
+        quote! {
+            match self {
+                #(#arms)*
+            }
+        },
+    ))
+}
+
+/// Generate the `hsr::Validate` implementation of a generated struct or enum,
+/// given the statements which validate `self`
+fn generate_validate_impl(name: &impl quote::ToTokens, checks: TokenStream) -> TokenStream {
+    quote! {
+        impl hsr::Validate for #name {
+            #[allow(unused_variables)]
+            fn validate_at(&self, pointer: &str, violations: &mut Vec<hsr::Violation>) {
+                #checks
+            }
+        }
+    }
+}
+
+/// The result of combining the parts of an allOf
+enum Combined {
+    // an object, and the object schemas (referred to by the allOf) it can be converted into
+    Struct(Struct, Vec<Parent>),
+    // the parts are not objects, so the allOf refines the type at the path
+    Alias(TypePath),
+    // no part constrains the type
+    Any,
+}
+
+struct Parent {
+    path: TypePath,
+    strukt: Struct,
+    // the field which holds the parent, if it is flattened
+    field: Option<Ident>,
+}
+
+fn combine_types(
+    type_path: &TypePath,
+    parts: &[ReferenceOr<Type>],
+    lookup: &TypeLookup,
+    flatten: bool,
+) -> Result<Combined> {
+    let mut combiner = Combiner {
+        lookup,
+        flatten,
+        fields: Map::new(),
+        additional: Additional::Ignore,
+        parents: Vec::new(),
+        objects: false,
+        others: Vec::new(),
+    };
+    combiner.add_parts(type_path, parts)?;
+    combiner.finish(type_path)
+}
+
+/// Combines the parts of an allOf (and of any allOf nested inside it) into one type.
+/// Objects are merged into a struct. A property may be declared by more than one part
+/// as long as the types agree (or one refines the other, e.g. a string enum refines
+/// a string). oneOf and anyOf parts become flattened fields of the struct
+struct Combiner<'a> {
+    lookup: &'a TypeLookup,
+    flatten: bool,
+    fields: Map<Ident, (FieldMetadata, TypePath)>,
+    // additional properties are kept if any part keeps them
+    additional: Additional,
+    parents: Vec<Parent>,
+    // whether any part is an object
+    objects: bool,
+    // the parts which are not objects
+    others: Vec<TypePath>,
+}
+
+impl<'a> Combiner<'a> {
+    fn add_parts(&mut self, type_path: &TypePath, parts: &[ReferenceOr<Type>]) -> Result<()> {
+        for (ix, part) in parts.iter().enumerate() {
+            let typ = lookup_type_recursive(part, self.lookup)?;
+            // the parent relationship only exists for named (referenced) objects
+            let (part_path, named) = match part {
+                ReferenceOr::Reference { reference } => (
+                    resolve_reference(reference, self.lookup)?,
+                    !typ.meta.nullable,
+                ),
+                ReferenceOr::Item(_) => (child(type_path, &format!("AllOf_{}", ix)), false),
+            };
+            match &typ.typ {
+                // constrains nothing, e.g. a part which only adds a description
+                TypeInner::Any => {}
+                TypeInner::Struct(strukt) if named => self.add_parent(&part_path, strukt)?,
+                TypeInner::Struct(strukt) => {
+                    self.objects = true;
+                    self.add_fields(type_path, strukt)?;
+                }
+                TypeInner::AllOf(parts) if named => {
+                    match combine_types(&part_path, parts, self.lookup, self.flatten)? {
+                        Combined::Struct(strukt, _) => self.add_parent(&part_path, &strukt)?,
+                        Combined::Alias(path) => self.others.push(path),
+                        Combined::Any => {}
+                    }
+                }
+                TypeInner::AllOf(parts) => self.add_parts(&part_path, parts)?,
+                _ => self.others.push(part_path),
+            }
+        }
+        Ok(())
+    }
+
+    fn add_parent(&mut self, path: &TypePath, strukt: &Struct) -> Result<()> {
+        self.objects = true;
+        if self.parents.iter().any(|parent| &parent.path == path) {
+            return Ok(());
+        }
+        let field = if self.flatten {
+            if strukt.additional == Additional::Deny {
+                return Err(Error::Unsupported {
+                    path: path.clone().into(),
+                    construct: "Flattening an object which denies additional properties".into(),
+                });
+            }
+            let field = self.add_flattened(path)?;
+            Some(field)
+        } else {
+            self.add_fields(path, strukt)?;
+            None
+        };
+        self.parents.push(Parent {
+            path: path.clone(),
+            strukt: strukt.clone(),
+            field,
+        });
+        Ok(())
+    }
+
+    fn add_fields(&mut self, type_path: &TypePath, strukt: &Struct) -> Result<()> {
+        for (field, (meta, path)) in &strukt.fields {
+            self.add_field(type_path, field, meta, path)?;
+        }
+        if let (Additional::Ignore, Additional::Keep(_)) = (&self.additional, &strukt.additional) {
+            self.additional = strukt.additional.clone();
+        }
+        Ok(())
+    }
+
+    fn add_field(
+        &mut self,
+        type_path: &TypePath,
+        field: &Ident,
+        meta: &FieldMetadata,
+        path: &TypePath,
+    ) -> Result<()> {
+        let (existing_meta, existing_path) = match self.fields.get_mut(field) {
+            Some(existing) => existing,
+            None => {
+                self.fields
+                    .insert(field.clone(), (meta.clone(), path.clone()));
+                return Ok(());
+            }
+        };
+        if existing_path != path {
+            let existing = lookup_type_recursive(&self.lookup[&*existing_path], self.lookup)?;
+            let new = lookup_type_recursive(&self.lookup[path], self.lookup)?;
+            if refines(&new.typ, &existing.typ) && existing.typ != new.typ {
+                *existing_path = path.clone();
+            } else if !refines(&existing.typ, &new.typ) {
+                invalid!(
+                    "Property '{}' has incompatible types in the parts of allOf (location: '{}')",
+                    field,
+                    ApiPath::from(type_path.clone())
+                )
+            }
+        }
+        existing_meta.required |= meta.required;
+        existing_meta.read_only |= meta.read_only;
+        existing_meta.write_only |= meta.write_only;
+        Ok(())
+    }
+
+    /// Add a flattened field holding the type at `path`, named after it
+    fn add_flattened(&mut self, path: &TypePath) -> Result<Ident> {
+        let last = path.0.last().expect("type paths are not empty");
+        let field: Ident = last.to_snake_case().parse()?;
+        if self.fields.contains_key(&field) {
+            invalid!(
+                "Cannot name the flattened field for '{}', as '{}' is already used",
+                path.canonicalize(),
+                field
+            )
+        }
+        self.fields
+            .insert(field.clone(), (FieldMetadata::flattened(), path.clone()));
+        Ok(field)
+    }
+
+    fn finish(mut self, type_path: &TypePath) -> Result<Combined> {
+        if !self.objects {
+            let mut others = self.others.into_iter();
+            let mut alias = match others.next() {
+                Some(path) => path,
+                None => return Ok(Combined::Any),
+            };
+            // all the parts must agree, and we take the most refined
+            for other in others {
+                let current = lookup_type_recursive(&self.lookup[&alias], self.lookup)?;
+                let typ = lookup_type_recursive(&self.lookup[&other], self.lookup)?;
+                if refines(&typ.typ, &current.typ) {
+                    alias = other;
+                } else if !refines(&current.typ, &typ.typ) {
+                    return Err(Error::Unsupported {
+                        path: type_path.clone().into(),
+                        construct: "allOf combining incompatible schemas".into(),
+                    });
+                }
+            }
+            return Ok(Combined::Alias(alias));
+        }
+        for other in std::mem::take(&mut self.others) {
+            let typ = lookup_type_recursive(&self.lookup[&other], self.lookup)?;
+            match typ.typ {
+                TypeInner::OneOf(_) | TypeInner::AnyOf(_) => {
+                    self.add_flattened(&other)?;
+                }
+                _ => {
+                    return Err(Error::Unsupported {
+                        path: type_path.clone().into(),
+                        construct: "allOf combining objects and non-object schemas".into(),
+                    })
+                }
+            }
+        }
+        self.check_flattened(type_path)?;
+        Ok(Combined::Struct(
+            Struct {
+                fields: self.fields,
+                additional: self.additional,
+            },
+            self.parents,
+        ))
+    }
+
+    /// A property can't be refined when its object is flattened, as it would be
+    /// serialized twice
+    fn check_flattened(&self, type_path: &TypePath) -> Result<()> {
+        let mut wire_names = Vec::new();
+        self.wire_names(&self.fields, &mut wire_names)?;
+        let mut seen = Set::new();
+        for wire_name in wire_names {
+            if !seen.insert(wire_name.clone()) {
+                invalid!(
+                    "Property '{}' is declared by more than one part of allOf, \
+                     so they cannot be flattened (location: '{}')",
+                    wire_name,
+                    ApiPath::from(type_path.clone())
+                )
+            }
+        }
+        Ok(())
+    }
+
+    /// The names of the properties of an object, including those of flattened objects
+    fn wire_names(
+        &self,
+        fields: &Map<Ident, (FieldMetadata, TypePath)>,
+        names: &mut Vec<String>,
+    ) -> Result<()> {
+        for (field, (meta, path)) in fields {
+            if !meta.flatten {
+                names.push(meta.rename.clone().unwrap_or_else(|| field.to_string()));
+                continue;
+            }
+            match &lookup_type_recursive(&self.lookup[path], self.lookup)?.typ {
+                TypeInner::Struct(strukt) => self.wire_names(&strukt.fields, names)?,
+                TypeInner::AllOf(parts) => {
+                    if let Combined::Struct(strukt, _) =
+                        combine_types(path, parts, self.lookup, self.flatten)?
+                    {
+                        self.wire_names(&strukt.fields, names)?
+                    }
+                }
+                // the properties of a oneOf or anyOf depend on the variant
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether a value of type `new` is always a valid value of type `old`
+fn refines(new: &TypeInner, old: &TypeInner) -> bool {
+    match (new, old) {
+        (_, TypeInner::Any) => true,
+        (TypeInner::StringEnum(_), TypeInner::Primitive(Primitive::String)) => true,
+        (TypeInner::ValueEnum(prim, _), TypeInner::Primitive(old)) => prim == old,
+        (new, old) => new == old,
+    }
+}
+
+/// The path of the type a reference refers to, perhaps through other references
+fn resolve_reference(reference: &str, lookup: &TypeLookup) -> Result<TypePath> {
+    let mut path = TypePath::from_reference(reference)?;
+    // cycles of references have already been rejected
+    while let Some(ReferenceOr::Reference { reference }) = lookup.get(&path) {
+        path = TypePath::from_reference(reference)?;
+    }
+    Ok(path)
+}
+
+/// Generate `From<Child> for Parent` for each object an allOf refers to, and
+/// `AsRef<Parent>` when it is flattened. When the properties are copied instead,
+/// the conversion is only possible if the child did not refine any of them
+fn generate_conversions(
+    name: &TypeName,
+    strukt: &Struct,
+    parents: &[Parent],
+    lookup: &TypeLookup,
+) -> Result<TokenStream> {
+    let mut tokens = TokenStream::new();
+    'parents: for parent in parents {
+        let parent_name = parent.path.canonicalize();
+        if let Some(field) = &parent.field {
+            tokens.extend(quote! {
+                impl From<#name> for #parent_name {
+                    fn from(child: #name) -> Self {
+                        child.#field
+                    }
+                }
+
+                impl AsRef<#parent_name> for #name {
+                    fn as_ref(&self) -> &#parent_name {
+                        &self.#field
+                    }
+                }
+            });
+            continue;
+        }
+        let mut inits = Vec::new();
+        for (field, (parent_meta, parent_path)) in &parent.strukt.fields {
+            let (meta, path) = &strukt.fields[field];
+            if path != parent_path {
+                continue 'parents;
+            }
+            let init = match (
+                is_optional(parent_meta, parent_path, lookup)?,
+                is_optional(meta, path, lookup)?,
+            ) {
+                (true, false) => quote! { #field: Some(child.#field) },
+                (false, true) => continue 'parents,
+                _ => quote! { #field: child.#field },
+            };
+            inits.push(init);
+        }
+        match (&parent.strukt.additional, &strukt.additional) {
+            (Additional::Keep(parent_values), Additional::Keep(values))
+                if parent_values == values =>
+            {
+                let extra_field = ident(EXTRA_FIELD);
+                inits.push(quote! { #extra_field: child.#extra_field });
+            }
+            (Additional::Keep(_), _) => continue,
+            _ => {}
+        }
+        let child = if inits.is_empty() {
+            quote! { _ }
+        } else {
+            quote! { child }
+        };
+        tokens.extend(quote! {
+            impl From<#name> for #parent_name {
+                fn from(#child: #name) -> Self {
+                    Self {
+                        #(#inits,)*
+                    }
+                }
+            }
+        });
+    }
+    Ok(tokens)
 }
 
 #[cfg(test)]
@@ -949,16 +3587,1434 @@ mod tests {
     use super::*;
     use std::fs;
 
+    #[test]
+    fn test_collect_unsupported() {
+        let yaml = r#"
+openapi: "3.0.0"
+info:
+  version: 1.0.0
+  title: Unsupported
+paths:
+  /pets/{id}:
+    get:
+      operationId: get_pet
+      parameters:
+        - name: id
+          in: path
+          required: true
+          schema:
+            type: string
+        - name: filter
+          in: query
+          content:
+            application/json:
+              schema:
+                type: object
+      responses:
+        '200':
+          description: A pet
+          content:
+            text/plain:
+              schema:
+                type: string
+          links:
+            owner:
+              description: The owner of the pet
+              parameters:
+                petId: $response.body#/id
+"#;
+        let api: OpenAPI = serde_yaml::from_str(yaml).unwrap();
+        let errs = match walk_api(&api, &Extensions::default(), &mut Diagnostics::default()) {
+            Err(Error::Multiple(errs)) => errs,
+            other => panic!("Expected multiple errors, got {:?}", other),
+        };
+        let found: Vec<_> = errs
+            .iter()
+            .map(|e| match e {
+                Error::Unsupported { path, .. } => path.to_string(),
+                other => panic!("Expected unsupported error, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                "paths./pets/{id}.get.responses.200.links",
+                "paths./pets/{id}.get.parameters.filter",
+                "paths./pets/{id}.get.responses.200.content.text/plain",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_query_explode() {
+        let yaml = r#"
+openapi: "3.0.0"
+info:
+  version: 1.0.0
+  title: Explode
+paths:
+  /a:
+    get:
+      operationId: get_a
+      parameters:
+        - name: ids
+          in: query
+          explode: false
+          schema:
+            type: array
+            items:
+              type: string
+      responses:
+        '200':
+          description: Ok
+  /b:
+    get:
+      operationId: get_b
+      parameters:
+        - name: ids
+          in: query
+          schema:
+            type: array
+            items:
+              type: string
+      responses:
+        '200':
+          description: Ok
+"#;
+        let root: serde_yaml::Value = serde_yaml::from_str(yaml).unwrap();
+        let api: OpenAPI = serde_yaml::from_value(root.clone()).unwrap();
+        let extensions = Extensions::gather(&root);
+        let (types, _) = walk_api(&api, &extensions, &mut Diagnostics::default()).unwrap();
+        // the same parameter is exploded in one operation but not the other
+        let query = |path: &str, opid: &str| {
+            let path = ApiPath::default()
+                .push("paths")
+                .push(path)
+                .push("get")
+                .push(opid)
+                .push("query");
+            match &types[&TypePath::from(path)] {
+                ReferenceOr::Item(Type {
+                    typ: TypeInner::Struct(strukt),
+                    ..
+                }) => strukt.fields[&"ids".parse::<Ident>().unwrap()].0.query,
+                other => panic!("Expected struct, got {:?}", other),
+            }
+        };
+        let form = openapiv3::QueryStyle::Form;
+        assert_eq!(
+            query("/a", "get_a"),
+            Some(QueryParam::new(&form, false, false))
+        );
+        assert_eq!(
+            query("/b", "get_b"),
+            Some(QueryParam::new(&form, true, false))
+        );
+    }
+
+    #[test]
+    fn test_path_level_params() {
+        let yaml = r#"
+openapi: "3.0.0"
+info:
+  version: 1.0.0
+  title: Path level
+paths:
+  /pets/{id}:
+    parameters:
+      - name: id
+        in: path
+        required: true
+        schema:
+          type: string
+      - name: limit
+        in: query
+        schema:
+          type: integer
+      - name: X-Trace
+        in: header
+        schema:
+          type: string
+    get:
+      operationId: get_pet
+      parameters:
+        - name: limit
+          in: query
+          required: true
+          schema:
+            type: integer
+        - name: x-trace
+          in: header
+          required: true
+          schema:
+            type: string
+      responses:
+        '200':
+          description: A pet
+    delete:
+      operationId: delete_pet
+      responses:
+        '200':
+          description: Deleted
+"#;
+        let api: OpenAPI = serde_yaml::from_str(yaml).unwrap();
+        let (types, routes) =
+            walk_api(&api, &Extensions::default(), &mut Diagnostics::default()).unwrap();
+        assert_eq!(routes["/pets/{id}"].len(), 2);
+
+        let query_type = |method: &str, opid: &str| {
+            let path = ApiPath::default()
+                .push("paths")
+                .push("/pets/{id}")
+                .push(method)
+                .push(opid)
+                .push("query");
+            match &types[&TypePath::from(path)] {
+                ReferenceOr::Item(Type {
+                    typ: TypeInner::Struct(strukt),
+                    ..
+                }) => strukt.fields[&"limit".parse::<Ident>().unwrap()].0.required,
+                other => panic!("Expected struct, got {:?}", other),
+            }
+        };
+        // the operation-level declaration wins
+        assert!(query_type("get", "get_pet"));
+        assert!(!query_type("delete", "delete_pet"));
+        // even when a header name differs in case
+        let path = ApiPath::default()
+            .push("paths")
+            .push("/pets/{id}")
+            .push("get")
+            .push("get_pet")
+            .push("header");
+        match &types[&TypePath::from(path)] {
+            ReferenceOr::Item(Type {
+                typ: TypeInner::Struct(strukt),
+                ..
+            }) => {
+                assert_eq!(strukt.fields.len(), 1);
+                assert!(
+                    strukt.fields[&"x_trace".parse::<Ident>().unwrap()]
+                        .0
+                        .required
+                );
+            }
+            other => panic!("Expected struct, got {:?}", other),
+        }
+        for (method, opid) in &[("get", "get_pet"), ("delete", "delete_pet")] {
+            let path = ApiPath::default()
+                .push("paths")
+                .push("/pets/{id}")
+                .push(*method)
+                .push(*opid)
+                .push("path");
+            assert!(types.contains_key(&TypePath::from(path)));
+        }
+
+        // a name used in another location is suffixed, keeping its name on the wire
+        let yaml = yaml.replace(
+            "        - name: limit\n          in: query\n          required: true",
+            "        - name: id\n          in: query\n          required: true",
+        );
+        let api: OpenAPI = serde_yaml::from_str(&yaml).unwrap();
+        let (types, _routes) =
+            walk_api(&api, &Extensions::default(), &mut Diagnostics::default()).unwrap();
+        let fields = |location: &str| {
+            let path = ApiPath::default()
+                .push("paths")
+                .push("/pets/{id}")
+                .push("get")
+                .push("get_pet")
+                .push(location);
+            match &types[&TypePath::from(path)] {
+                ReferenceOr::Item(Type {
+                    typ: TypeInner::Struct(strukt),
+                    ..
+                }) => strukt.fields.clone(),
+                other => panic!("Expected struct, got {:?}", other),
+            }
+        };
+        assert!(fields("path").contains_key(&"id".parse::<Ident>().unwrap()));
+        let (meta, _) = &fields("query")[&"id_query".parse::<Ident>().unwrap()];
+        assert_eq!(meta.rename.as_deref(), Some("id"));
+
+        // but a name repeated in the same location is still a duplicate
+        let yaml = yaml.replace("- name: x-trace", "- name: X-TRACE\n          in: header\n          schema:\n            type: string\n        - name: x-trace");
+        let api: OpenAPI = serde_yaml::from_str(&yaml).unwrap();
+        match walk_api(&api, &Extensions::default(), &mut Diagnostics::default()) {
+            Err(Error::Validation(msg)) => assert_eq!(msg, "Duplicated parameter 'x-trace'"),
+            other => panic!("Expected validation error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_header_params() {
+        let yaml = r#"
+openapi: "3.0.0"
+info:
+  version: 1.0.0
+  title: Headers
+paths:
+  /pets:
+    get:
+      operationId: get_pets
+      parameters:
+        - name: X-Tenant-ID
+          in: header
+          required: true
+          schema:
+            type: string
+        - name: Authorization
+          in: header
+          schema:
+            type: string
+      responses:
+        '200':
+          description: Pets
+"#;
+        let api: OpenAPI = serde_yaml::from_str(yaml).unwrap();
+        let (types, _routes) =
+            walk_api(&api, &Extensions::default(), &mut Diagnostics::default()).unwrap();
+        let path = ApiPath::default()
+            .push("paths")
+            .push("/pets")
+            .push("get")
+            .push("get_pets")
+            .push("header");
+        match &types[&TypePath::from(path)] {
+            ReferenceOr::Item(Type {
+                typ: TypeInner::Struct(strukt),
+                ..
+            }) => {
+                // 'Authorization' is ignored, as required by the spec
+                assert_eq!(strukt.fields.len(), 1);
+                let (name, (meta, _)) = strukt.fields.get_index(0).unwrap();
+                assert_eq!(name.to_string(), "x_tenant_id");
+                assert_eq!(meta.rename.as_deref(), Some("x-tenant-id"));
+                assert!(meta.required);
+            }
+            other => panic!("Expected struct, got {:?}", other),
+        }
+
+        // arrays can't be sent in a single header
+        let yaml = yaml.replace("type: string\n        - name: Authorization", "type: array\n            items:\n              type: string\n        - name: Authorization");
+        let api: OpenAPI = serde_yaml::from_str(&yaml).unwrap();
+        match walk_api(&api, &Extensions::default(), &mut Diagnostics::default()) {
+            Err(Error::Unsupported { path, construct }) => {
+                assert_eq!(path.to_string(), "paths./pets.get.parameters.X-Tenant-ID");
+                assert_eq!(construct, "Non-primitive header parameters");
+            }
+            other => panic!("Expected unsupported error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cookie_params() {
+        let yaml = r#"
+openapi: "3.0.0"
+info:
+  version: 1.0.0
+  title: Cookies
+paths:
+  /pets:
+    get:
+      operationId: get_pets
+      parameters:
+        - name: session-id
+          in: cookie
+          required: true
+          schema:
+            type: string
+        - name: tags
+          in: cookie
+          schema:
+            type: array
+            items:
+              type: string
+      responses:
+        '200':
+          description: Pets
+"#;
+        let api: OpenAPI = serde_yaml::from_str(yaml).unwrap();
+        match walk_api(&api, &Extensions::default(), &mut Diagnostics::default()) {
+            Err(Error::Unsupported { path, construct }) => {
+                assert_eq!(path.to_string(), "paths./pets.get.parameters.tags");
+                assert_eq!(construct, "Non-primitive cookie parameters");
+            }
+            other => panic!("Expected unsupported error, got {:?}", other),
+        }
+
+        // a string cookie is fine, and is renamed to a valid identifier
+        let yaml = yaml.replace(
+            "type: array\n            items:\n              type: string",
+            "type: string",
+        );
+        let api: OpenAPI = serde_yaml::from_str(&yaml).unwrap();
+        let (types, _routes) =
+            walk_api(&api, &Extensions::default(), &mut Diagnostics::default()).unwrap();
+        let path = ApiPath::default()
+            .push("paths")
+            .push("/pets")
+            .push("get")
+            .push("get_pets")
+            .push("cookie");
+        match &types[&TypePath::from(path)] {
+            ReferenceOr::Item(Type {
+                typ: TypeInner::Struct(strukt),
+                ..
+            }) => {
+                let (name, (meta, _)) = strukt.fields.get_index(0).unwrap();
+                assert_eq!(name.to_string(), "session_id");
+                assert_eq!(meta.rename.as_deref(), Some("session-id"));
+            }
+            other => panic!("Expected struct, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_discriminator() {
+        let yaml = r#"
+openapi: "3.0.0"
+info:
+  version: 1.0.0
+  title: Discriminator
+paths: {}
+components:
+  schemas:
+    Pet:
+      oneOf:
+        - $ref: '#/components/schemas/Cat'
+        - $ref: '#/components/schemas/Dog'
+      discriminator:
+        propertyName: petType
+        mapping:
+          cat: Cat
+          kitty: '#/components/schemas/Cat'
+    Cat:
+      properties:
+        petType:
+          type: string
+        name:
+          type: string
+    Dog:
+      properties:
+        petType:
+          type: string
+"#;
+        let walk = |yaml: &str| {
+            let api: OpenAPI = serde_yaml::from_str(yaml).unwrap();
+            walk_api(&api, &Extensions::default(), &mut Diagnostics::default())
+        };
+        let (types, _routes) = walk(yaml).unwrap();
+        let cat = TypePath::from_reference("#/components/schemas/Cat").unwrap();
+        let dog = TypePath::from_reference("#/components/schemas/Dog").unwrap();
+        match &types[&TypePath::from_reference("#/components/schemas/Pet").unwrap()] {
+            ReferenceOr::Item(Type {
+                typ:
+                    TypeInner::OneOf(OneOf {
+                        discriminator: Some(discriminator),
+                        ..
+                    }),
+                ..
+            }) => assert_eq!(
+                discriminator,
+                &Discriminator {
+                    property: "petType".into(),
+                    variants: vec![
+                        (cat.clone(), vec!["cat".into(), "kitty".into()]),
+                        (dog, vec!["Dog".into()]),
+                    ]
+                }
+            ),
+            other => panic!("Expected discriminated oneOf, got {:?}", other),
+        }
+        let fields = |path: &TypePath| match &types[path] {
+            ReferenceOr::Item(Type {
+                typ: TypeInner::Struct(strukt),
+                ..
+            }) => strukt
+                .fields
+                .keys()
+                .map(|f| f.to_string())
+                .collect::<Vec<_>>(),
+            other => panic!("Expected struct, got {:?}", other),
+        };
+        // the tag is removed from the variant structs, but not from the schemas
+        let pet_cat = TypePath::from(
+            ApiPath::default()
+                .push("components")
+                .push("schemas")
+                .push("Pet")
+                .push("Cat"),
+        );
+        assert_eq!(pet_cat.canonicalize().to_string(), "PetCat");
+        assert_eq!(fields(&pet_cat), vec!["name"]);
+        assert_eq!(fields(&cat), vec!["pet_type", "name"]);
+
+        let bad_yaml = format!("{}    PetCat:\n      type: string\n", yaml);
+        match walk(&bad_yaml) {
+            Err(Error::Validation(msg)) => assert_eq!(
+                msg,
+                "Cannot name the variant 'Cat' of 'Pet', as 'PetCat' is already used"
+            ),
+            other => panic!("Expected validation error, got {:?}", other.map(|_| ())),
+        }
+
+        let bad_yaml = yaml.replace("kitty: '#/components/schemas/Cat'", "cow: Cow");
+        match walk(&bad_yaml) {
+            Err(Error::Validation(msg)) => assert_eq!(
+                msg,
+                "Discriminator value 'cow' maps to '#/components/schemas/Cow', \
+                 which is not one of the variants"
+            ),
+            other => panic!("Expected validation error, got {:?}", other.map(|_| ())),
+        }
+
+        let bad_yaml = yaml.replace("- $ref: '#/components/schemas/Dog'", "- type: object");
+        match walk(&bad_yaml) {
+            Err(Error::Validation(msg)) => assert_eq!(
+                msg,
+                "Discriminator 'petType' requires every variant to be a reference to a schema"
+            ),
+            other => panic!("Expected validation error, got {:?}", other.map(|_| ())),
+        }
+
+        let bad_yaml = yaml.replace(
+            "Dog:\n      properties:\n        petType",
+            "Dog:\n      properties:\n        age",
+        );
+        match walk(&bad_yaml) {
+            Err(Error::Validation(msg)) => assert_eq!(
+                msg,
+                "Discriminator property 'petType' is missing from variant 'Dog'"
+            ),
+            other => panic!("Expected validation error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_variant_names() {
+        let yaml = r#"
+oneOf:
+  - $ref: '#/components/schemas/Pet'
+  - type: string
+  - title: pet
+    type: object
+  - type: string
+    x-rust-variant: String3
+  - type: string
+  - type: array
+    items:
+      type: string
+"#;
+        let names = |yaml: &str| {
+            let raw: serde_yaml::Value = serde_yaml::from_str(yaml).unwrap();
+            let schema: Schema = serde_yaml::from_value(raw.clone()).unwrap();
+            let alternatives = match &schema.schema_kind {
+                SchemaKind::OneOf { one_of } => one_of.clone(),
+                _ => unreachable!(),
+            };
+            let path = ApiPath::default().push_location("oneOf");
+            variant_names(&path, &alternatives, &Extensions::gather(&raw)).map(|names| {
+                names
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(
+            names(yaml).unwrap(),
+            vec!["Pet", "String", "Pet2", "String3", "String2", "Array"]
+        );
+
+        match names(&yaml.replace("title: pet", "x-rust-variant: String3")) {
+            Err(Error::Validation(msg)) => assert_eq!(
+                msg,
+                "Duplicate variant name 'String3' given by 'x-rust-variant'"
+            ),
+            other => panic!("Expected validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_any_of_and_strict_one_of() {
+        let yaml = r#"
+openapi: "3.0.0"
+info:
+  version: 1.0.0
+  title: Composites
+paths: {}
+components:
+  schemas:
+    Either:
+      oneOf:
+        - type: string
+        - type: integer
+    Both:
+      anyOf:
+        - type: string
+        - type: integer
+        - type: boolean
+          title: type
+"#;
+        let api: OpenAPI = serde_yaml::from_str(yaml).unwrap();
+        let (types, _) =
+            walk_api(&api, &Extensions::default(), &mut Diagnostics::default()).unwrap();
+        let both = TypePath::from_reference("#/components/schemas/Both").unwrap();
+        match &types[&both] {
+            ReferenceOr::Item(Type {
+                typ: TypeInner::AnyOf(variants),
+                ..
+            }) => {
+                let names: Vec<_> = variants.iter().map(|(name, _)| name.to_string()).collect();
+                assert_eq!(names, vec!["String", "I64", "Type"]);
+            }
+            other => panic!("Expected anyOf, got {:?}", other),
+        }
+
+        let generate = |options: &Options| {
+            generate_rust_types(&types, options, &mut Diagnostics::default())
+                .unwrap()
+                .to_string()
+        };
+        let lax = generate(&Options::default());
+        assert!(lax.contains("pub struct Both"));
+        // field names are made safe, as a variant may be named after a keyword
+        assert!(lax.contains("pub r#type : Option < BothOneOf2 >"));
+        assert!(!lax.contains("expected exactly one"));
+        let strict = generate(&Options::default().strict_one_of(true));
+        assert!(strict.contains(r#"try_from = "JsonValue""#));
+        assert!(strict.contains("data matched {} variants of oneOf 'Either', expected exactly one"));
+    }
+
+    #[test]
+    fn test_additional_properties() {
+        let yaml = r#"
+openapi: "3.0.0"
+info:
+  version: 1.0.0
+  title: Additional
+paths: {}
+components:
+  schemas:
+    Dict:
+      additionalProperties:
+        type: integer
+    Extras:
+      properties:
+        name:
+          type: string
+      additionalProperties: {}
+    Closed:
+      properties:
+        name:
+          type: string
+      additionalProperties: false
+"#;
+        let walk = |yaml: &str| {
+            let api: OpenAPI = serde_yaml::from_str(yaml).unwrap();
+            walk_api(&api, &Extensions::default(), &mut Diagnostics::default())
+        };
+        let (types, _) = walk(yaml).unwrap();
+        let get = |name: &str| {
+            let path = TypePath::from_reference(&format!("#/components/schemas/{}", name));
+            match &types[&path.unwrap()] {
+                ReferenceOr::Item(typ) => typ.typ.clone(),
+                other => panic!("Expected item, got {:?}", other),
+            }
+        };
+        let dict_values = TypePath::from(
+            ApiPath::default()
+                .push("components")
+                .push("schemas")
+                .push("Dict")
+                .push("additional"),
+        );
+        assert_eq!(get("Dict"), TypeInner::Map(dict_values));
+        match get("Extras") {
+            TypeInner::Struct(Struct {
+                additional: Additional::Keep(values),
+                ..
+            }) => assert_eq!(values.canonicalize().to_string(), "ExtrasAdditional"),
+            other => panic!("Expected struct, got {:?}", other),
+        }
+        match get("Closed") {
+            TypeInner::Struct(strukt) => assert_eq!(strukt.additional, Additional::Deny),
+            other => panic!("Expected struct, got {:?}", other),
+        }
+
+        // properties named like the additional properties are renamed
+        let clash = yaml.replace(
+            "        name:\n          type: string\n      additionalProperties: {}",
+            "        extra:\n          type: string\n        additional:\n          type: object\n      \
+             additionalProperties: {}",
+        );
+        let (types, _) = walk(&clash).unwrap();
+        let extras = TypePath::from_reference("#/components/schemas/Extras").unwrap();
+        match &types[&extras] {
+            ReferenceOr::Item(Type {
+                typ: TypeInner::Struct(strukt),
+                ..
+            }) => {
+                let fields: Vec<_> = strukt
+                    .fields
+                    .iter()
+                    .map(|(field, (meta, path))| {
+                        (
+                            field.to_string(),
+                            meta.rename.clone(),
+                            path.canonicalize().to_string(),
+                        )
+                    })
+                    .collect();
+                assert_eq!(
+                    fields,
+                    vec![
+                        (
+                            "extra_2".into(),
+                            Some("extra".into()),
+                            "ExtrasExtra2".into()
+                        ),
+                        (
+                            "additional_2".into(),
+                            Some("additional".into()),
+                            "ExtrasAdditional2".into()
+                        ),
+                    ]
+                );
+                match &strukt.additional {
+                    Additional::Keep(values) => {
+                        assert_eq!(values.canonicalize().to_string(), "ExtrasAdditional")
+                    }
+                    other => panic!("Expected additional properties, got {:?}", other),
+                }
+            }
+            other => panic!("Expected struct, got {:?}", other),
+        }
+        // but keep their names when there are none
+        let (types, _) = walk(&clash.replace("additionalProperties: {}", "")).unwrap();
+        match &types[&extras] {
+            ReferenceOr::Item(Type {
+                typ: TypeInner::Struct(strukt),
+                ..
+            }) => {
+                let fields: Vec<_> = strukt.fields.keys().map(|f| f.to_string()).collect();
+                assert_eq!(fields, vec!["extra", "additional"]);
+            }
+            other => panic!("Expected struct, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_string_formats() {
+        let yaml = r#"
+openapi: "3.0.0"
+info:
+  version: 1.0.0
+  title: Formats
+paths: {}
+components:
+  schemas:
+    Formats:
+      properties:
+        created:
+          type: string
+          format: date-time
+        address:
+          type: string
+          format: ipv4
+        email:
+          type: string
+          format: email
+        colour:
+          type: string
+          format: colour
+"#;
+        let api: OpenAPI = serde_yaml::from_str(yaml).unwrap();
+        // Unknown formats are not an error, even when not lenient
+        let mut diagnostics = Diagnostics::default();
+        let (types, _) = walk_api(&api, &Extensions::default(), &mut diagnostics).unwrap();
+        let get = |field: &str| {
+            let path = ApiPath::default()
+                .push("components")
+                .push("schemas")
+                .push("Formats")
+                .push(field);
+            match &types[&TypePath::from(path)] {
+                ReferenceOr::Item(typ) => typ.typ.clone(),
+                other => panic!("Expected item, got {:?}", other),
+            }
+        };
+        let created = if cfg!(feature = "chrono") {
+            Primitive::DateTime
+        } else {
+            Primitive::String
+        };
+        assert_eq!(get("created"), TypeInner::Primitive(created));
+        assert_eq!(get("address"), TypeInner::Primitive(Primitive::Ipv4));
+        assert_eq!(get("email"), TypeInner::Primitive(Primitive::String));
+        assert_eq!(get("colour"), TypeInner::Primitive(Primitive::String));
+
+        let degraded: Vec<_> = diagnostics
+            .into_report()
+            .degraded
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        let mut expect = vec![];
+        if !cfg!(feature = "chrono") {
+            expect.push(
+                "Format 'date-time' without feature 'chrono' not supported \
+                 (location: 'components.schemas.Formats.properties.created')",
+            );
+        }
+        expect.push(
+            "String format 'colour' not supported (location: 'components.schemas.Formats.properties.colour')",
+        );
+        assert_eq!(degraded, expect);
+    }
+
+    #[test]
+    fn test_number_formats() {
+        let yaml = r#"
+openapi: "3.0.0"
+info:
+  version: 1.0.0
+  title: Numbers
+paths: {}
+components:
+  schemas:
+    Numbers:
+      properties:
+        small:
+          type: integer
+          format: int32
+        count:
+          type: integer
+          minimum: 0
+          x-rust-unsigned: true
+        ratio:
+          type: number
+          format: float
+        price:
+          type: number
+          format: decimal
+"#;
+        let walk = |yaml: &str, diagnostics: &mut Diagnostics| {
+            let root: serde_yaml::Value = serde_yaml::from_str(yaml).unwrap();
+            let api: OpenAPI = serde_yaml::from_value(root.clone()).unwrap();
+            walk_api(&api, &Extensions::gather(&root), diagnostics)
+        };
+        let (types, _) = walk(yaml, &mut Diagnostics::default()).unwrap();
+        let get = |field: &str| {
+            let path = ApiPath::default()
+                .push("components")
+                .push("schemas")
+                .push("Numbers")
+                .push(field);
+            match &types[&TypePath::from(path)] {
+                ReferenceOr::Item(typ) => typ.typ.clone(),
+                other => panic!("Expected item, got {:?}", other),
+            }
+        };
+        let price = if cfg!(feature = "decimal") {
+            Primitive::Decimal
+        } else {
+            Primitive::F64
+        };
+        assert_eq!(get("small"), TypeInner::Primitive(Primitive::I32));
+        assert_eq!(get("count"), TypeInner::Primitive(Primitive::U64));
+        assert_eq!(get("ratio"), TypeInner::Primitive(Primitive::F32));
+        assert_eq!(get("price"), TypeInner::Primitive(price));
+        if cfg!(feature = "decimal") {
+            // an optional decimal field keeps its precision, and may be missing
+            let code =
+                generate_rust_types(&types, &Options::default(), &mut Diagnostics::default())
+                    .unwrap()
+                    .to_string();
+            assert!(code.contains(
+                r#"# [serde (default , with = "hsr::rust_decimal::serde::arbitrary_precision_option")] pub price"#
+            ));
+        }
+
+        let negative = yaml.replace("minimum: 0", "minimum: -1");
+        match walk(&negative, &mut Diagnostics::default()) {
+            Err(Error::Validation(msg)) => assert_eq!(
+                msg,
+                "Extension 'x-rust-unsigned' requires a 'minimum' of 0 or more \
+                 (location: 'components.schemas.Numbers.properties.count')"
+            ),
+            other => panic!("Expected validation error, got {:?}", other.map(|_| ())),
+        }
+
+        // an identical schema elsewhere without the extension is unaffected
+        let identical = format!(
+            "{}        other:\n          type: integer\n          minimum: 0\n",
+            yaml
+        );
+        let (types, _) = walk(&identical, &mut Diagnostics::default()).unwrap();
+        let other = TypePath::from(
+            ApiPath::default()
+                .push("components")
+                .push("schemas")
+                .push("Numbers")
+                .push("other"),
+        );
+        match &types[&other] {
+            ReferenceOr::Item(typ) => assert_eq!(typ.typ, TypeInner::Primitive(Primitive::I64)),
+            other => panic!("Expected item, got {:?}", other),
+        }
+
+        let unknown = yaml.replace("format: int32", "format: int8");
+        let mut diagnostics = Diagnostics::default();
+        walk(&unknown, &mut diagnostics).unwrap();
+        assert!(diagnostics.into_report().degraded[0]
+            .to_string()
+            .starts_with("Integer format 'int8' not supported"));
+    }
+
+    #[test]
+    fn test_constraints() {
+        let yaml = r#"
+openapi: 3.0.0
+info:
+  version: 1.0.0
+  title: Constraints
+paths: {}
+components:
+  schemas:
+    Pet:
+      required: [name]
+      properties:
+        name:
+          type: string
+          minLength: 1
+          pattern: '^[a-z]+$'
+        tags:
+          type: array
+          maxItems: 3
+          uniqueItems: true
+          items:
+            type: string
+        age:
+          type: integer
+          minimum: 0
+          exclusiveMinimum: true
+          multipleOf: 2
+        price:
+          type: number
+          format: float
+          maximum: 100
+          multipleOf: 0.01
+"#;
+        let api: OpenAPI = serde_yaml::from_str(yaml).unwrap();
+        let (types, _) =
+            walk_api(&api, &Extensions::default(), &mut Diagnostics::default()).unwrap();
+        let get = |field: &str| {
+            let path = ApiPath::default()
+                .push("components")
+                .push("schemas")
+                .push("Pet")
+                .push(field);
+            match &types[&TypePath::from(path)] {
+                ReferenceOr::Item(typ) => typ.constraints.clone(),
+                other => panic!("Expected item, got {:?}", other),
+            }
+        };
+        let name = get("name");
+        assert_eq!(name.min_count, Some(1));
+        assert_eq!(name.pattern.as_deref(), Some("^[a-z]+$"));
+        let tags = get("tags");
+        assert_eq!(tags.max_count, Some(3));
+        assert!(tags.unique_items);
+        let age = get("age");
+        assert_eq!(age.minimum, Some((Number::Int(0), true)));
+        assert_eq!(age.multiple_of, Some(Number::Int(2)));
+
+        // the struct checks its fields, using their names on the wire
+        let code = generate_rust_types(&types, &Options::default(), &mut Diagnostics::default())
+            .unwrap()
+            .to_string();
+        assert!(code.contains("impl hsr :: Validate for Pet"));
+        assert!(code.contains(r#"hsr :: validate :: pointer (pointer , "name")"#));
+        assert!(code.contains("hsr :: validate :: check_unique"));
+        assert!(code.contains("if let Some (value) = & self . age"));
+        // f32 values are compared as f32
+        assert!(code.contains("check_maximum (* value , 100f32 , false"));
+        assert!(code.contains("check_f32_multiple_of (* value , 0.01f32"));
+
+        let bad = yaml.replace("'^[a-z]+$'", "'[a-z'");
+        let api: OpenAPI = serde_yaml::from_str(&bad).unwrap();
+        match walk_api(&api, &Extensions::default(), &mut Diagnostics::default()) {
+            Err(Error::Validation(msg)) => {
+                assert!(msg.starts_with("Invalid pattern '[a-z'"), "{}", msg)
+            }
+            other => panic!("Expected validation error, got {:?}", other.map(|_| ())),
+        }
+
+        // a multipleOf of 0 would divide by zero
+        for bad in &[
+            yaml.replace("multipleOf: 2", "multipleOf: 0"),
+            yaml.replace("multipleOf: 0.01", "multipleOf: -0.01"),
+        ] {
+            let api: OpenAPI = serde_yaml::from_str(bad).unwrap();
+            match walk_api(&api, &Extensions::default(), &mut Diagnostics::default()) {
+                Err(Error::Validation(msg)) => {
+                    assert!(msg.starts_with("Invalid multipleOf"), "{}", msg)
+                }
+                other => panic!("Expected validation error, got {:?}", other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn test_value_enums() {
+        let yaml = r#"
+openapi: 3.0.0
+info:
+  version: 1.0.0
+  title: Enums
+paths: {}
+components:
+  schemas:
+    Enums:
+      properties:
+        sizes:
+          type: integer
+          enum: [1, 2, -5]
+        ratios:
+          type: number
+          enum: [0.5, 1.0]
+        flags:
+          type: boolean
+          description: Only ever true
+          enum: [true]
+        plain:
+          type: boolean
+"#;
+        let walk = |yaml: &str, diagnostics: &mut Diagnostics| {
+            let root: serde_yaml::Value = serde_yaml::from_str(yaml).unwrap();
+            let api: OpenAPI = serde_yaml::from_value(root.clone()).unwrap();
+            walk_api(&api, &Extensions::gather(&root), diagnostics)
+        };
+        let (types, _) = walk(yaml, &mut Diagnostics::default()).unwrap();
+        let get = |field: &str| {
+            let path = ApiPath::default()
+                .push("components")
+                .push("schemas")
+                .push("Enums")
+                .push(field);
+            match &types[&TypePath::from(path)] {
+                ReferenceOr::Item(typ) => typ.typ.clone(),
+                other => panic!("Expected item, got {:?}", other),
+            }
+        };
+        use serde_json::json;
+        assert_eq!(
+            get("sizes"),
+            TypeInner::ValueEnum(Primitive::I64, vec![json!(1), json!(2), json!(-5)])
+        );
+        assert_eq!(
+            get("ratios"),
+            TypeInner::ValueEnum(Primitive::F64, vec![json!(0.5), json!(1.0)])
+        );
+        assert_eq!(
+            get("flags"),
+            TypeInner::ValueEnum(Primitive::Bool, vec![json!(true)])
+        );
+        assert_eq!(get("plain"), TypeInner::Primitive(Primitive::Bool));
+
+        let code = generate_rust_types(&types, &Options::default(), &mut Diagnostics::default())
+            .unwrap()
+            .to_string();
+        assert!(code.contains("{ Value1 , Value2 , ValueMinus5 , }"));
+        assert!(code.contains("{ Value0Point5 , Value1Point0 , }"));
+        assert!(code.contains("{ True , }"));
+        assert!(code.contains("impl std :: convert :: TryFrom < i64 > for"));
+
+        // the values of a mixed-type enum have no Rust type in common
+        let mixed = yaml.replace("enum: [1, 2, -5]", "enum: [1, two]");
+        let mut diagnostics = Diagnostics::new(true);
+        walk(&mixed, &mut diagnostics).unwrap();
+        let degraded: Vec<_> = diagnostics
+            .into_report()
+            .degraded
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            degraded,
+            vec!["Mixed-type enums not supported (location: 'components.schemas.Enums.properties.sizes')"]
+        );
+    }
+
+    #[test]
+    fn test_enum_variant_names() {
+        let names = enum_variant_names(&["red", "dark-red", "dark_red", "1st", "", "+", "self"]);
+        let names: Vec<_> = names.iter().map(|name| name.to_string()).collect();
+        assert_eq!(
+            names,
+            vec!["Red", "DarkRed", "DarkRed2", "Value1st", "Value4", "Value5", "Value6"]
+        );
+    }
+
+    #[test]
+    fn test_field_names() {
+        let names = field_names(
+            &[
+                "myName",
+                "my_name",
+                "first-name",
+                "@type",
+                "type",
+                "2fa",
+                "self",
+                "",
+            ],
+            &[],
+        )
+        .unwrap();
+        let names: Vec<_> = names.iter().map(|name| name.to_string()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "my_name_2",
+                "my_name",
+                "first_name",
+                "type_2",
+                "type",
+                "field_2fa",
+                "self_",
+                "field"
+            ]
+        );
+        let keyword: Ident = "type".parse().unwrap();
+        assert_eq!(quote! { #keyword }.to_string(), "r#type");
+    }
+
+    #[test]
+    fn test_read_write_only() {
+        let yaml = r#"
+openapi: 3.0.0
+info:
+  version: 1.0.0
+  title: Pets
+paths:
+  /pets:
+    post:
+      operationId: add_pet
+      requestBody:
+        content:
+          application/json:
+            schema:
+              $ref: '#/components/schemas/Pet'
+      responses:
+        '200':
+          description: The new pet
+          content:
+            application/json:
+              schema:
+                $ref: '#/components/schemas/Pet'
+components:
+  schemas:
+    Pet:
+      properties:
+        id:
+          type: integer
+          readOnly: true
+        name:
+          type: string
+        password:
+          type: string
+          writeOnly: true
+    Owner:
+      title: PetOwner
+      properties:
+        pets:
+          type: array
+          items:
+            $ref: '#/components/schemas/Pet'
+    Plain:
+      properties:
+        name:
+          type: string
+"#;
+        let api: OpenAPI = serde_yaml::from_str(yaml).unwrap();
+        let (types, routes) =
+            walk_api(&api, &Extensions::default(), &mut Diagnostics::default()).unwrap();
+        let fields = |name: &str| {
+            let path = TypePath::from(
+                ApiPath::default()
+                    .push("components")
+                    .push("schemas")
+                    .push(name),
+            );
+            match &types[&path] {
+                ReferenceOr::Item(Type {
+                    typ: TypeInner::Struct(strukt),
+                    ..
+                }) => strukt
+                    .fields
+                    .iter()
+                    .map(|(field, (_, path))| format!("{}: {}", field, path.canonicalize()))
+                    .collect::<Vec<_>>(),
+                other => panic!("Expected struct, got {:?}", other),
+            }
+        };
+        assert_eq!(fields("Pet"), vec!["id: PetId", "name: PetName"]);
+        assert_eq!(
+            fields("PetInput"),
+            vec!["name: PetName", "password: PetPassword"]
+        );
+        assert_eq!(fields("OwnerInput"), vec!["pets: OwnerPetsInput"]);
+        let plain = TypePath::from(
+            ApiPath::default()
+                .push("components")
+                .push("schemas")
+                .push("PlainInput"),
+        );
+        assert!(!types.contains_key(&plain));
+
+        // requests send the input variant, responses return the original
+        let route = &routes["/pets"][0];
+        let code = route.generate_api_signature().to_string();
+        assert!(code.contains("payload : PetInput"), "{}", code);
+
+        let code = generate_rust_types(&types, &Options::default(), &mut Diagnostics::default())
+            .unwrap()
+            .to_string();
+        assert!(code.contains("pub struct PetOwnerInput"));
+        assert!(code.contains("type OwnerPetsInput = Vec < OwnerPetsInputArray >"));
+
+        let taken = format!("{}    PetInput:\n      type: string\n", yaml);
+        let api: OpenAPI = serde_yaml::from_str(&taken).unwrap();
+        match walk_api(&api, &Extensions::default(), &mut Diagnostics::default()) {
+            Err(Error::Validation(msg)) => {
+                assert!(msg.contains("as 'PetInput' is already used"), "{}", msg)
+            }
+            other => panic!("Expected validation error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_box_cycles() {
+        let yaml = r#"
+openapi: 3.0.0
+info:
+  version: 1.0.0
+  title: Trees
+paths: {}
+components:
+  schemas:
+    TreeNode:
+      properties:
+        children:
+          type: array
+          items:
+            $ref: '#/components/schemas/TreeNode'
+        parent:
+          $ref: '#/components/schemas/TreeNode'
+    Expr:
+      oneOf:
+        - type: integer
+        - $ref: '#/components/schemas/Sum'
+    Sum:
+      properties:
+        left:
+          $ref: '#/components/schemas/Expr'
+        right:
+          $ref: '#/components/schemas/Expr'
+"#;
+        let api: OpenAPI = serde_yaml::from_str(yaml).unwrap();
+        let (types, _) =
+            walk_api(&api, &Extensions::default(), &mut Diagnostics::default()).unwrap();
+        let boxed: Vec<_> = types
+            .iter()
+            .filter(|(_, typ)| {
+                matches!(
+                    typ,
+                    ReferenceOr::Item(Type {
+                        typ: TypeInner::Boxed(_),
+                        ..
+                    })
+                )
+            })
+            .map(|(path, _)| path.canonicalize().to_string())
+            .collect();
+        assert_eq!(boxed, vec!["SumLeft", "SumRight", "TreeNodeParent"]);
+        let code = generate_rust_types(&types, &Options::default(), &mut Diagnostics::default())
+            .unwrap()
+            .to_string();
+        assert!(code.contains("type TreeNodeParent = Box < TreeNode >"));
+
+        // a chain of references which never reaches a type is an error
+        let cyclic = format!(
+            "{}    A:\n      $ref: '#/components/schemas/B'\n    B:\n      $ref: '#/components/schemas/A'\n",
+            yaml
+        );
+        let api: OpenAPI = serde_yaml::from_str(&cyclic).unwrap();
+        match walk_api(&api, &Extensions::default(), &mut Diagnostics::default()) {
+            Err(Error::CyclicReference(chain)) => assert_eq!(
+                chain,
+                "#/components/schemas/B -> #/components/schemas/A -> #/components/schemas/B"
+            ),
+            other => panic!("Expected cyclic reference, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_check_default() {
+        let path = ApiPath::default().push("x");
+        let string = TypeInner::Primitive(Primitive::String);
+        let int = TypeInner::Primitive(Primitive::I64);
+        let colours = TypeInner::StringEnum(vec!["red".into(), "blue".into()]);
+        assert!(check_default(&string, &serde_json::json!("a"), false, &path).is_ok());
+        assert!(check_default(&int, &serde_json::json!(1), false, &path).is_ok());
+        assert!(check_default(&int, &serde_json::json!(1.5), false, &path).is_err());
+        assert!(check_default(&int, &serde_json::json!(null), false, &path).is_err());
+        assert!(check_default(&int, &serde_json::json!(null), true, &path).is_ok());
+        let small = TypeInner::Primitive(Primitive::U32);
+        assert!(check_default(&small, &serde_json::json!(1), false, &path).is_ok());
+        assert!(check_default(&small, &serde_json::json!(-1), false, &path).is_err());
+        assert!(check_default(&small, &serde_json::json!(1u64 << 32), false, &path).is_err());
+        assert!(check_default(&colours, &serde_json::json!("red"), false, &path).is_ok());
+        match check_default(&colours, &serde_json::json!("green"), false, &path) {
+            Err(Error::Validation(msg)) => assert_eq!(
+                msg,
+                "Default value \"green\" does not fit type (location: 'x')"
+            ),
+            other => panic!("Expected validation error, got {:?}", other),
+        }
+
+        // formatted strings must fit their format
+        let check = |prim: Primitive, val: &str| {
+            let typ = TypeInner::Primitive(prim);
+            check_default(&typ, &serde_json::json!(val), false, &path).is_ok()
+        };
+        assert!(check(Primitive::DateTime, "2020-02-29T12:00:00.5+01:00"));
+        assert!(!check(Primitive::DateTime, "2021-02-29T12:00:00Z"));
+        assert!(!check(Primitive::DateTime, "yesterday"));
+        assert!(check(Primitive::Date, "2020-12-31"));
+        assert!(!check(Primitive::Date, "2020-13-01"));
+        assert!(check(
+            Primitive::Uuid,
+            "67e55044-10b1-426f-9247-bb680e5fe0c8"
+        ));
+        assert!(!check(Primitive::Uuid, "67e55044"));
+        assert!(check(Primitive::Url, "https://example.com/home"));
+        assert!(!check(Primitive::Url, "/home"));
+        assert!(check(Primitive::Bytes, "aGVsbG8="));
+        assert!(!check(Primitive::Bytes, "hello!"));
+        assert!(check(Primitive::Ipv4, "127.0.0.1"));
+        assert!(!check(Primitive::Ipv6, "127.0.0.1"));
+    }
+
+    #[test]
+    fn test_lenient_mode() {
+        let yaml = r#"
+openapi: "3.0.0"
+info:
+  version: 1.0.0
+  title: Lenient
+paths:
+  /pets:
+    get:
+      operationId: get_pets
+      parameters:
+        - name: X-Trace
+          in: header
+          content:
+            application/json:
+              schema:
+                type: object
+          schema:
+            type: string
+      responses:
+        '200':
+          description: Pets
+    post:
+      operationId: add_pet
+      requestBody:
+        content:
+          application/json:
+            schema:
+              $ref: '#/components/schemas/Pet'
+      responses:
+        '200':
+          description: Added
+          links:
+            self:
+              parameters:
+                petId: $response.body#/id
+components:
+  schemas:
+    Pet:
+      type: object
+      properties:
+        tag:
+          type: object
+          discriminator:
+            propertyName: kind
+"#;
+        let api: OpenAPI = serde_yaml::from_str(yaml).unwrap();
+        assert!(walk_api(&api, &Extensions::default(), &mut Diagnostics::default()).is_err());
+
+        let mut diagnostics = Diagnostics::new(true);
+        let (types, routes) = walk_api(&api, &Extensions::default(), &mut diagnostics).unwrap();
+        generate_rust_types(&types, &Options::default(), &mut diagnostics).unwrap();
+        let report = diagnostics.into_report();
+
+        // 'get_pets' is left out entirely, 'add_pet' survives
+        let opids: Vec<_> = routes["/pets"]
+            .iter()
+            .map(|route| route.operation_id().to_string())
+            .collect();
+        assert_eq!(opids, vec!["add_pet"]);
+        assert!(types
+            .keys()
+            .all(|path| !path
+                .starts_with(&ApiPath::default().push("paths").push("/pets").push("get"))));
+
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].0, "paths./pets.get");
+        let degraded: Vec<_> = report.degraded.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            degraded,
+            vec![
+                "Discriminators not supported (location: 'components.schemas.Pet.properties.tag')",
+                "Response links not supported (location: 'paths./pets.post.responses.200.links')",
+            ]
+        );
+        // the degraded field is still there, just untyped
+        let pet_tag = TypePath::from(
+            ApiPath::default()
+                .push("components")
+                .push("schemas")
+                .push("Pet")
+                .push("tag"),
+        );
+        match &types[&pet_tag] {
+            ReferenceOr::Item(Type {
+                typ: TypeInner::Any,
+                ..
+            }) => {}
+            other => panic!("Expected 'any' type, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_gather_types() {
         let yaml = "../examples/petstore-expanded/petstore-expanded.yaml";
         // let yaml = "../examples/petstore/petstore.yaml";
         let yaml = fs::read_to_string(yaml).unwrap();
         let api: OpenAPI = serde_yaml::from_str(&yaml).unwrap();
-        let (types, _routes) = walk_api(&api).unwrap();
+        let mut diagnostics = Diagnostics::default();
+        let (types, _routes) = walk_api(&api, &Extensions::default(), &mut diagnostics).unwrap();
 
         #[allow(unused_mut)]
-        let mut code = generate_rust_types(&types).unwrap().to_string();
+        let mut code = generate_rust_types(&types, &Options::default(), &mut diagnostics)
+            .unwrap()
+            .to_string();
 
         #[cfg(feature = "rustfmt")]
         {
@@ -967,4 +5023,122 @@ mod tests {
         println!("{}", code);
         panic!()
     }
+
+    #[test]
+    fn test_all_of() {
+        let yaml = r#"
+openapi: 3.0.0
+info:
+  version: 1.0.0
+  title: AllOf
+paths: {}
+components:
+  schemas:
+    Animal:
+      required: [name]
+      properties:
+        name:
+          type: string
+        status:
+          type: string
+    Pet:
+      allOf:
+        - $ref: '#/components/schemas/Animal'
+        - properties:
+            owner:
+              type: string
+    Id:
+      nullable: true
+      allOf:
+        - type: string
+        - description: Only annotates
+    Shape:
+      allOf:
+        - $ref: '#/components/schemas/Animal'
+        - oneOf:
+            - required: [radius]
+              properties:
+                radius:
+                  type: number
+            - required: [side]
+              properties:
+                side:
+                  type: number
+"#;
+        let generate = |yaml: &str, options: &Options| {
+            let api: OpenAPI = serde_yaml::from_str(yaml).unwrap();
+            let (types, _) =
+                walk_api(&api, &Extensions::default(), &mut Diagnostics::default()).unwrap();
+            generate_rust_types(&types, options, &mut Diagnostics::default())
+                .map(|code| code.to_string())
+        };
+        let code = generate(yaml, &Options::default()).unwrap();
+        assert!(code.contains("impl From < Pet > for Animal"), "{}", code);
+        assert!(code.contains("name : child . name , status : child . status"));
+        assert!(code.contains("type Id = Option < IdAllOf0 >"));
+        assert!(code.contains("# [serde (flatten)] pub all_of_1 : ShapeAllOf1"));
+        assert!(code.contains("pub enum ShapeAllOf1"));
+
+        let flattened = generate(yaml, &Options::default().flatten_all_of(true)).unwrap();
+        assert!(flattened.contains("# [serde (flatten)] pub animal : Animal"));
+        assert!(flattened.contains("impl AsRef < Animal > for Pet"));
+
+        // a nested allOf may refine properties, though the child can't then be
+        // converted into its parent
+        let refined = format!(
+            "{}{}",
+            yaml,
+            r#"    Dog:
+      allOf:
+        - $ref: '#/components/schemas/Pet'
+        - allOf:
+            - required: [status]
+              properties:
+                status:
+                  type: string
+                  enum: [good, bad]
+            - properties:
+                name:
+                  type: string
+"#
+        );
+        let code = generate(&refined, &Options::default()).unwrap();
+        assert!(
+            code.contains("pub status : DogAllOf1AllOf0Status"),
+            "{}",
+            code
+        );
+        assert!(code.contains("pub name : AnimalName"));
+        assert!(!code.contains("impl From < Dog > for Pet"));
+        match generate(&refined, &Options::default().flatten_all_of(true)) {
+            Err(Error::Validation(msg)) => assert!(
+                msg.contains("Property 'status' is declared by more than one part"),
+                "{}",
+                msg
+            ),
+            other => panic!("Expected validation error, got {:?}", other),
+        }
+
+        let clash = format!(
+            "{}{}",
+            yaml,
+            r#"    Clash:
+      allOf:
+        - $ref: '#/components/schemas/Animal'
+        - properties:
+            name:
+              type: integer
+"#
+        );
+        match generate(&clash, &Options::default()) {
+            Err(Error::Validation(msg)) => {
+                assert!(
+                    msg.contains("Property 'name' has incompatible types"),
+                    "{}",
+                    msg
+                )
+            }
+            other => panic!("Expected validation error, got {:?}", other),
+        }
+    }
 }
@@ -18,13 +18,41 @@ pub use serde_json;
 pub use serde_urlencoded;
 pub use url;
 
+#[cfg(feature = "chrono")]
+pub use chrono;
+#[cfg(feature = "decimal")]
+pub use rust_decimal;
+#[cfg(feature = "uuid")]
+pub use uuid;
+
 pub use openssl;
 
 pub use url::Url;
 
+mod security;
+pub use security::{
+    authenticate, authenticate_optional, select_credentials, ApiKeyLocation, AuthError, AuthScheme,
+    Authenticator, Credential, SchemeRequirement, SecurityRequirement,
+};
+
+mod query;
+pub use query::{decode_query, encode_query, QueryParam, QueryParams, QueryStyle, QueryStyles};
+
+pub mod validate;
+pub use validate::{Validate, Violation, Violations};
+
 // We re-export this type as it is used in all the trait functions
 use actix_http::http::StatusCode;
-use actix_web::{Error as ActixError, HttpResponse};
+use actix_web::{
+    dev::{Payload, ResourceDef},
+    Error as ActixError, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+};
+use futures::future::{ready, Ready};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::de::DeserializeOwned;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
 
 /// Associate an http status code with a type. Defaults to 501 Internal Server Error
 pub trait HasStatusCode {
@@ -43,6 +71,224 @@ pub enum ClientError {
     BadStatus(StatusCode),
     #[error("Actix error: {}", _0)]
     Actix(#[from] ActixError),
+    /// The request was not sent, as it breaks constraints of the spec
+    #[error("Invalid request: {}", _0)]
+    Invalid(#[from] Violations),
+    /// The request was not sent, as its URL could not be built
+    #[error("Bad URL: {}", _0)]
+    Url(#[from] url::ParseError),
+    /// The request was not sent, as its query parameters could not be serialized
+    #[error("Bad query parameters: {}", _0)]
+    Query(#[from] serde_json::Error),
+    /// The request was not sent, as its header parameters could not be serialized
+    #[error("Bad header parameters: {}", _0)]
+    Header(serde_urlencoded::ser::Error),
+    /// The request was not sent, as its cookie parameters could not be serialized
+    #[error("Bad cookie parameters: {}", _0)]
+    Cookie(serde_urlencoded::ser::Error),
+}
+
+/// The characters which are left alone in a path parameter: the 'unreserved' set of RFC 3986
+const PATH_PARAM: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Percent-encode the value of a path parameter, so it can only fill its place in the path.
+/// Note that a whole segment of `.` or `..` is still removed when the URL is normalized
+pub fn encode_path_param(value: &str) -> String {
+    utf8_percent_encode(value, PATH_PARAM).to_string()
+}
+
+/// Combine json values into one. Objects are merged, with keys from earlier values
+/// taking precedence. Otherwise the first value is taken. If there are no values,
+/// the result is `null`.
+///
+/// Used to serialize anyOf types, where several alternatives may be present
+pub fn merge_json(values: impl IntoIterator<Item = serde_json::Value>) -> serde_json::Value {
+    use serde_json::Value;
+    values
+        .into_iter()
+        .fold(None, |merged, value| match (merged, value) {
+            (None, value) => Some(value),
+            (Some(Value::Object(mut merged)), Value::Object(value)) => {
+                for (key, val) in value {
+                    merged.entry(key).or_insert(val);
+                }
+                Some(Value::Object(merged))
+            }
+            (Some(merged), _) => Some(merged),
+        })
+        .unwrap_or(Value::Null)
+}
+
+/// Bytes which are base64-encoded when (de)serialized. Used for strings with format 'byte'
+#[cfg(feature = "byte")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Base64(pub Vec<u8>);
+
+#[cfg(feature = "byte")]
+impl From<Vec<u8>> for Base64 {
+    fn from(bytes: Vec<u8>) -> Self {
+        Base64(bytes)
+    }
+}
+
+#[cfg(feature = "byte")]
+impl From<Base64> for Vec<u8> {
+    fn from(bytes: Base64) -> Self {
+        bytes.0
+    }
+}
+
+#[cfg(feature = "byte")]
+impl AsRef<[u8]> for Base64 {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(feature = "byte")]
+impl serde::Serialize for Base64 {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode(&self.0))
+    }
+}
+
+#[cfg(feature = "byte")]
+impl<'de> serde::Deserialize<'de> for Base64 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        base64::decode(&encoded)
+            .map(Base64)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Extract typed headers from a request.
+///
+/// Each field of `T` is matched against the lowercased header names, so the field
+/// names (or serde renames) of `T` must be lowercase. Values are parsed as if
+/// they were query parameters. If the headers do not fit `T` (for example, a
+/// required header is missing) the request is rejected with 400 Bad Request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Headers<T>(pub T);
+
+impl<T> Headers<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: DeserializeOwned> FromRequest for Headers<T> {
+    type Error = ActixError;
+    type Future = Ready<Result<Self, ActixError>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let pairs = req.headers().iter().filter_map(|(name, value)| {
+            // Non-visible-ASCII values are skipped, as though they were missing
+            value.to_str().ok().map(|value| (name.as_str(), value))
+        });
+        ready(from_pairs(pairs).map(Headers))
+    }
+}
+
+/// Extract typed cookies from a request.
+///
+/// Each field of `T` is matched against a cookie name. As with `Headers`,
+/// values are parsed as if they were query parameters, and the request is
+/// rejected with 400 Bad Request if the cookies do not fit `T`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cookies<T>(pub T);
+
+impl<T> Cookies<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: DeserializeOwned> FromRequest for Cookies<T> {
+    type Error = ActixError;
+    type Future = Ready<Result<Self, ActixError>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let cookies = match req.cookies() {
+            Ok(cookies) => cookies,
+            Err(e) => return ready(Err(actix_web::error::ErrorBadRequest(e))),
+        };
+        let pairs = cookies.iter().map(|cookie| (cookie.name(), cookie.value()));
+        ready(from_pairs(pairs).map(Cookies))
+    }
+}
+
+/// Extract typed path parameters from a request.
+///
+/// Actix matches routes against a partly decoded path, in which `%2F` and `%2B`
+/// are left encoded but `%25` is decoded, so the original values can't always be
+/// recovered from `match_info`. Instead the raw path is matched against the
+/// route's pattern (from `PathPattern`) and each value is fully percent-decoded.
+/// As with `Headers`, the request is rejected with 400 Bad Request if the values
+/// do not fit `T`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathParams<T>(pub T);
+
+impl<T> PathParams<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+/// The route pattern which a set of path parameters is matched by
+pub trait PathPattern {
+    const PATTERN: &'static str;
+}
+
+impl<T: DeserializeOwned + PathPattern> FromRequest for PathParams<T> {
+    type Error = ActixError;
+    type Future = Ready<Result<Self, ActixError>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        thread_local! {
+            static RESOURCES: RefCell<HashMap<&'static str, ResourceDef>> =
+                RefCell::new(HashMap::new());
+        }
+        let mut path = actix_web::dev::Path::new(req.uri().path().to_string());
+        let matched = RESOURCES.with(|resources| {
+            resources
+                .borrow_mut()
+                .entry(T::PATTERN)
+                .or_insert_with(|| ResourceDef::new(T::PATTERN))
+                .match_path(&mut path)
+        });
+        // e.g. when mounted in a scope, fall back to the values actix found
+        let raw: Vec<(&str, &str)> = if matched {
+            path.iter().collect()
+        } else {
+            req.match_info().iter().collect()
+        };
+        let mut pairs = Vec::new();
+        for (name, value) in raw {
+            match percent_decode_str(value).decode_utf8() {
+                Ok(value) => pairs.push((name, value)),
+                Err(e) => return ready(Err(actix_web::error::ErrorBadRequest(e))),
+            }
+        }
+        let pairs = pairs.iter().map(|(name, value)| (*name, value.as_ref()));
+        ready(from_pairs(pairs).map(PathParams))
+    }
+}
+
+/// Deserialize name-value pairs by way of a urlencoded string
+fn from_pairs<'a, T: DeserializeOwned>(
+    pairs: impl Iterator<Item = (&'a str, &'a str)>,
+) -> Result<T, ActixError> {
+    let mut encoded = url::form_urlencoded::Serializer::new(String::new());
+    encoded.extend_pairs(pairs);
+    serde_urlencoded::from_str(&encoded.finish()).map_err(actix_web::error::ErrorBadRequest)
 }
 
 pub fn configure_spec(
@@ -73,3 +319,51 @@ impl Config {
         Self { host, ssl: None }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_encode_path_param() {
+        assert_eq!(encode_path_param("a-b.c_d~e"), "a-b.c_d~e");
+        assert_eq!(encode_path_param("a/b?c#d"), "a%2Fb%3Fc%23d");
+        assert_eq!(encode_path_param("100% a+b"), "100%25%20a%2Bb");
+        assert_eq!(encode_path_param("Zoë 🦀"), "Zo%C3%AB%20%F0%9F%A6%80");
+        assert_eq!(encode_path_param(""), "");
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Params {
+        name: String,
+        id: i64,
+    }
+
+    impl PathPattern for Params {
+        const PATTERN: &'static str = "/files/{name}.json/{id}";
+    }
+
+    fn extract(uri: &str) -> Result<Params, ActixError> {
+        let req = TestRequest::with_uri(uri).to_http_request();
+        PathParams::<Params>::from_request(&req, &mut Payload::None)
+            .into_inner()
+            .map(PathParams::into_inner)
+    }
+
+    #[test]
+    fn test_path_params() {
+        for name in &[
+            "a/b", "what?", "#1", "100%", "%2F", "%41", "a+b", "Zoë", "🦀",
+        ] {
+            let uri = format!("/files/{}.json/3", encode_path_param(name));
+            let expect = Params {
+                name: name.to_string(),
+                id: 3,
+            };
+            assert_eq!(extract(&uri).unwrap(), expect);
+        }
+        assert!(extract("/files/a.json/x").is_err());
+        assert!(extract("/files/%FF.json/3").is_err());
+    }
+}
@@ -0,0 +1,586 @@
+//! Query parameters, serialized according to their `style` and `explode`
+
+use actix_web::{dev::Payload, Error as ActixError, FromRequest, HttpRequest};
+use futures::future::{ready, Ready};
+use percent_encoding::percent_decode_str;
+use serde::de::{
+    self,
+    value::{Error, MapDeserializer, SeqDeserializer},
+    DeserializeOwned, Error as _, IntoDeserializer, Visitor,
+};
+use serde::ser::{self, Serialize};
+use serde_json::Value;
+
+/// How a query parameter is serialized (its `style` and `explode`).
+///
+/// The delimited styles only differ from `form` when not exploded, so
+/// when exploded they are `Form { explode: true }`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryStyle {
+    /// Exploded: `tags=a&tags=b`, or an object's entries as `key=value`.
+    /// Otherwise: `tags=a,b`, or an object as `filter=key,value`
+    Form { explode: bool },
+    /// `tags=a%20b`
+    SpaceDelimited,
+    /// `tags=a|b`
+    PipeDelimited,
+    /// An object as `filter[key]=value`
+    DeepObject,
+}
+
+impl QueryStyle {
+    fn delimiter(self) -> Option<&'static str> {
+        match self {
+            QueryStyle::Form { explode: false } => Some(","),
+            QueryStyle::SpaceDelimited => Some("%20"),
+            QueryStyle::PipeDelimited => Some("|"),
+            QueryStyle::Form { explode: true } | QueryStyle::DeepObject => None,
+        }
+    }
+}
+
+/// The serialization of a query parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryParam {
+    /// The name of the parameter on the wire
+    pub name: &'static str,
+    pub style: QueryStyle,
+    /// Whether the parameter is an object. The entries of an exploded `form`
+    /// object are every pair which isn't another parameter
+    pub object: bool,
+}
+
+/// How each field of a set of query parameters is serialized.
+/// Fields which aren't listed are exploded `form` parameters
+pub trait QueryStyles {
+    const STYLES: &'static [QueryParam];
+}
+
+/// Extract typed query parameters from a request, according to `QueryStyles`.
+///
+/// Values are parsed as the fields of `T` require, as with `Headers`, and the
+/// request is rejected with 400 Bad Request if they do not fit `T`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryParams<T>(pub T);
+
+impl<T> QueryParams<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: DeserializeOwned + QueryStyles> FromRequest for QueryParams<T> {
+    type Error = ActixError;
+    type Future = Ready<Result<Self, ActixError>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let result = decode_query(req.query_string())
+            .map(QueryParams)
+            .map_err(actix_web::error::ErrorBadRequest);
+        ready(result)
+    }
+}
+
+/// Serialize query parameters into a query string, according to `QueryStyles`
+pub fn encode_query<T: Serialize + QueryStyles>(params: &T) -> Result<String, serde_json::Error> {
+    let fields = match serde_json::to_value(params)? {
+        Value::Object(fields) => fields,
+        _ => return Err(ser::Error::custom("Query parameters must be a struct")),
+    };
+    let mut pairs = Vec::new();
+    for (name, value) in &fields {
+        let style = T::STYLES
+            .iter()
+            .find(|param| param.name == name)
+            .map(|param| param.style)
+            .unwrap_or(QueryStyle::Form { explode: true });
+        let name = encode(name);
+        match value {
+            Value::Null => {}
+            Value::Array(items) => {
+                let items = items.iter().map(scalar).collect::<Result<Vec<_>, _>>()?;
+                match style.delimiter() {
+                    Some(delimiter) => pairs.push(format!("{}={}", name, items.join(delimiter))),
+                    None => pairs.extend(items.iter().map(|item| format!("{}={}", name, item))),
+                }
+            }
+            Value::Object(entries) => {
+                let entries = entries.iter().filter(|(_, value)| !value.is_null());
+                match style.delimiter() {
+                    Some(delimiter) => {
+                        let mut items = Vec::new();
+                        for (key, value) in entries {
+                            items.push(encode(key));
+                            items.push(scalar(value)?);
+                        }
+                        pairs.push(format!("{}={}", name, items.join(delimiter)))
+                    }
+                    None => {
+                        for (key, value) in entries {
+                            let key = match style {
+                                QueryStyle::DeepObject => format!("{}[{}]", name, encode(key)),
+                                _ => encode(key),
+                            };
+                            pairs.push(format!("{}={}", key, scalar(value)?))
+                        }
+                    }
+                }
+            }
+            value => pairs.push(format!("{}={}", name, scalar(value)?)),
+        }
+    }
+    Ok(pairs.join("&"))
+}
+
+/// Deserialize query parameters from a query string, according to `QueryStyles`
+pub fn decode_query<T: DeserializeOwned + QueryStyles>(query: &str) -> Result<T, Error> {
+    let mut pairs = Vec::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let mut parts = pair.splitn(2, '=');
+        let key = decode(parts.next().unwrap_or_default())?;
+        pairs.push((key, parts.next().unwrap_or_default()));
+    }
+    let deep_key = |key: &str, name: &str| {
+        if key.starts_with(name) && key[name.len()..].starts_with('[') && key.ends_with(']') {
+            Some(key[name.len() + 1..key.len() - 1].to_string())
+        } else {
+            None
+        }
+    };
+    let is_param = |key: &str| {
+        T::STYLES.iter().any(|param| {
+            param.name == key
+                || (param.style == QueryStyle::DeepObject && deep_key(key, param.name).is_some())
+        })
+    };
+
+    let mut fields = Vec::new();
+    for param in T::STYLES {
+        let mut values = pairs
+            .iter()
+            .filter(|(key, _)| key == param.name)
+            .map(|(_, value)| *value);
+        let node = match (param.style.delimiter(), param.style) {
+            (Some(delimiter), _) => match values.next() {
+                Some(raw) => Node::delimited(raw, delimiter, param.object)?,
+                None => continue,
+            },
+            (None, QueryStyle::DeepObject) if param.object => {
+                let mut entries = Vec::new();
+                for (key, value) in &pairs {
+                    if let Some(key) = deep_key(key, param.name) {
+                        entries.push((key, decode(value)?))
+                    }
+                }
+                Node::object(entries)
+            }
+            (None, _) if param.object => {
+                let mut entries = Vec::new();
+                for (key, value) in pairs.iter().filter(|(key, _)| !is_param(key)) {
+                    entries.push((key.clone(), decode(value)?))
+                }
+                Node::object(entries)
+            }
+            (None, _) => {
+                let items = values.map(decode).collect::<Result<Vec<_>, _>>()?;
+                Node {
+                    scalar: items.first().cloned(),
+                    items,
+                    entries: Vec::new(),
+                }
+            }
+        };
+        if node.scalar.is_some() || !node.entries.is_empty() {
+            fields.push((param.name, node))
+        }
+    }
+    // anything else is an exploded `form` parameter
+    for (key, value) in &pairs {
+        if !is_param(key) {
+            let value = decode(value)?;
+            match fields.iter_mut().find(|(name, _)| name == key) {
+                Some((_, node)) => node.items.push(value),
+                None => fields.push((key.as_str(), Node::scalar(value))),
+            }
+        }
+    }
+    T::deserialize(MapDeserializer::new(fields.into_iter()))
+}
+
+fn encode(value: &str) -> String {
+    url::form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+fn decode(value: &str) -> Result<String, Error> {
+    percent_decode_str(&value.replace('+', " "))
+        .decode_utf8()
+        .map(|value| value.into_owned())
+        .map_err(Error::custom)
+}
+
+fn scalar(value: &Value) -> Result<String, serde_json::Error> {
+    match value {
+        Value::String(value) => Ok(encode(value)),
+        Value::Number(value) => Ok(value.to_string()),
+        Value::Bool(value) => Ok(value.to_string()),
+        Value::Null => Ok(String::new()),
+        Value::Array(_) | Value::Object(_) => Err(ser::Error::custom(
+            "Nested arrays and objects cannot be sent in a query parameter",
+        )),
+    }
+}
+
+/// The value of a query parameter, which is parsed on demand as the target
+/// type requires (as `serde_urlencoded` does)
+#[derive(Debug, Default)]
+struct Node {
+    // the whole value, when it is a single value
+    scalar: Option<String>,
+    // the value as an array
+    items: Vec<String>,
+    // the value as an object
+    entries: Vec<(String, String)>,
+}
+
+impl Node {
+    fn scalar(value: String) -> Self {
+        Self {
+            scalar: Some(value.clone()),
+            items: vec![value],
+            entries: Vec::new(),
+        }
+    }
+
+    fn object(entries: Vec<(String, String)>) -> Self {
+        Self {
+            entries,
+            ..Self::default()
+        }
+    }
+
+    fn delimited(raw: &str, delimiter: &str, object: bool) -> Result<Self, Error> {
+        let items = if raw.is_empty() {
+            Vec::new()
+        } else {
+            raw.split(delimiter)
+                .map(decode)
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        let mut entries = Vec::new();
+        if object {
+            if items.len() % 2 != 0 {
+                return Err(Error::custom(
+                    "Expected alternating keys and values for an object",
+                ));
+            }
+            for pair in items.chunks(2) {
+                entries.push((pair[0].clone(), pair[1].clone()))
+            }
+        }
+        Ok(Self {
+            scalar: Some(decode(raw)?),
+            items,
+            entries,
+        })
+    }
+
+    fn into_scalar(self) -> Result<String, Error> {
+        self.scalar
+            .ok_or_else(|| Error::custom("Expected a single value"))
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Node {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! parse_scalar {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                match self.into_scalar()?.parse() {
+                    Ok(value) => visitor.$visit(value),
+                    Err(e) => Err(Error::custom(e)),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Node {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.scalar {
+            Some(scalar) => visitor.visit_string(scalar),
+            None => self.deserialize_map(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let items = self.items.into_iter().map(Node::scalar);
+        visitor.visit_seq(SeqDeserializer::new(items))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let entries = self
+            .entries
+            .into_iter()
+            .map(|(key, value)| (key, Node::scalar(value)));
+        visitor.visit_map(MapDeserializer::new(entries))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_enum(self.into_scalar()?.into_deserializer())
+    }
+
+    parse_scalar! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    serde::forward_to_deserialize_any! {
+        str string bytes byte_buf unit_struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_derive::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Filter {
+        status: String,
+        limit: Option<i64>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Params {
+        exploded: Option<Vec<String>>,
+        comma: Option<Vec<i64>>,
+        space: Option<Vec<String>>,
+        pipe: Option<Vec<bool>>,
+        deep: Option<Filter>,
+        pairs: Option<BTreeMap<String, String>>,
+        rest: Option<BTreeMap<String, i64>>,
+        plain: Option<f64>,
+    }
+
+    impl QueryStyles for Params {
+        const STYLES: &'static [QueryParam] = &[
+            QueryParam {
+                name: "exploded",
+                style: QueryStyle::Form { explode: true },
+                object: false,
+            },
+            QueryParam {
+                name: "comma",
+                style: QueryStyle::Form { explode: false },
+                object: false,
+            },
+            QueryParam {
+                name: "space",
+                style: QueryStyle::SpaceDelimited,
+                object: false,
+            },
+            QueryParam {
+                name: "pipe",
+                style: QueryStyle::PipeDelimited,
+                object: false,
+            },
+            QueryParam {
+                name: "deep",
+                style: QueryStyle::DeepObject,
+                object: true,
+            },
+            QueryParam {
+                name: "pairs",
+                style: QueryStyle::Form { explode: false },
+                object: true,
+            },
+            QueryParam {
+                name: "rest",
+                style: QueryStyle::Form { explode: true },
+                object: true,
+            },
+            QueryParam {
+                name: "plain",
+                style: QueryStyle::Form { explode: true },
+                object: false,
+            },
+        ];
+    }
+
+    fn empty() -> Params {
+        Params {
+            exploded: None,
+            comma: None,
+            space: None,
+            pipe: None,
+            deep: None,
+            pairs: None,
+            rest: None,
+            plain: None,
+        }
+    }
+
+    fn round_trip(params: Params, expect: &str) {
+        let encoded = encode_query(&params).unwrap();
+        assert_eq!(encoded, expect);
+        assert_eq!(decode_query::<Params>(&encoded).unwrap(), params);
+    }
+
+    #[test]
+    fn test_query_styles() {
+        round_trip(empty(), "");
+        round_trip(
+            Params {
+                exploded: Some(vec!["a".into(), "b c".into(), "d&e".into()]),
+                ..empty()
+            },
+            "exploded=a&exploded=b+c&exploded=d%26e",
+        );
+        round_trip(
+            Params {
+                comma: Some(vec![1, -2, 3]),
+                ..empty()
+            },
+            "comma=1,-2,3",
+        );
+        round_trip(
+            Params {
+                space: Some(vec!["a b".into(), "c,d".into()]),
+                ..empty()
+            },
+            "space=a+b%20c%2Cd",
+        );
+        round_trip(
+            Params {
+                pipe: Some(vec![true, false]),
+                ..empty()
+            },
+            "pipe=true|false",
+        );
+        round_trip(
+            Params {
+                deep: Some(Filter {
+                    status: "sold out".into(),
+                    limit: Some(5),
+                }),
+                ..empty()
+            },
+            "deep[limit]=5&deep[status]=sold+out",
+        );
+        round_trip(
+            Params {
+                deep: Some(Filter {
+                    status: "new".into(),
+                    limit: None,
+                }),
+                ..empty()
+            },
+            "deep[status]=new",
+        );
+        let pairs: BTreeMap<_, _> = vec![("R".into(), "1,0".into()), ("G".into(), "2".into())]
+            .into_iter()
+            .collect();
+        round_trip(
+            Params {
+                pairs: Some(pairs),
+                ..empty()
+            },
+            "pairs=G,2,R,1%2C0",
+        );
+        let rest: BTreeMap<_, _> = vec![("x".into(), 1), ("y".into(), 2)].into_iter().collect();
+        round_trip(
+            Params {
+                rest: Some(rest),
+                plain: Some(1.5),
+                ..empty()
+            },
+            "plain=1.5&x=1&y=2",
+        );
+    }
+
+    #[test]
+    fn test_decode_query() {
+        // empty delimited arrays are sent, but empty exploded arrays can't be
+        let params = decode_query::<Params>("comma=&exploded=").unwrap();
+        assert_eq!(params.comma, Some(vec![]));
+        assert_eq!(params.exploded, Some(vec!["".to_string()]));
+        // parameters may come in any order, and unknown ones are ignored
+        let params =
+            decode_query::<Params>("exploded=b&plain=2&exploded=a&deep[status]=x").unwrap();
+        assert_eq!(params.exploded, Some(vec!["b".into(), "a".into()]));
+        assert_eq!(params.plain, Some(2.0));
+        assert_eq!(params.deep.unwrap().status, "x");
+        assert!(decode_query::<Params>("comma=1,x").is_err());
+        assert!(decode_query::<Params>("pairs=a,b,c").is_err());
+        assert!(decode_query::<Params>("deep[limit]=1").is_err());
+        assert!(decode_query::<Params>("exploded=%FF").is_err());
+    }
+}
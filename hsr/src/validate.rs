@@ -0,0 +1,293 @@
+//! Checking the constraints of a schema which can't be expressed with Rust types
+//!
+//! The helpers here are called by generated code.
+
+use actix_http::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use regex::Regex;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Check the constraints of a schema, such as `maxLength` or `minimum`.
+///
+/// Implemented for every generated struct and enum. Constraints on other types
+/// (e.g. a `String` with a `pattern`) are checked by the type which contains them
+pub trait Validate {
+    /// Add a violation for each broken constraint. `pointer` points to `self`
+    /// within the whole value being validated. By default there is nothing to check
+    fn validate_at(&self, _pointer: &str, _violations: &mut Vec<Violation>) {}
+
+    /// Check every constraint, collecting all the violations
+    fn validate(&self) -> Result<(), Violations> {
+        let mut violations = Vec::new();
+        self.validate_at("", &mut violations);
+        Violations::check(violations)
+    }
+}
+
+// For types which fall back to arbitrary json
+impl Validate for serde_json::Value {}
+
+impl<T: Validate> Validate for Box<T> {
+    fn validate_at(&self, pointer: &str, violations: &mut Vec<Violation>) {
+        (**self).validate_at(pointer, violations)
+    }
+}
+
+/// A broken constraint.
+///
+/// The pointer is a JSON pointer into the request, taken as a document with
+/// `path`, `query`, `header`, `cookie` and `body` properties. For example
+/// `/body/pets/0/name` or `/query/limit`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Violation {
+    pub pointer: String,
+    pub message: String,
+}
+
+/// The constraints broken by a request.
+/// Returned as 400 Bad Request, with the violations as a json list
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violations(pub Vec<Violation>);
+
+impl Violations {
+    /// Fail if there are any violations
+    pub fn check(violations: Vec<Violation>) -> Result<(), Violations> {
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(Violations(violations))
+        }
+    }
+}
+
+impl fmt::Display for Violations {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let violations: Vec<_> = self
+            .0
+            .iter()
+            .map(|v| format!("'{}' {}", v.pointer, v.message))
+            .collect();
+        write!(f, "{}", violations.join(", "))
+    }
+}
+
+impl std::error::Error for Violations {}
+
+impl ResponseError for Violations {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::BadRequest().json(&self.0)
+    }
+}
+
+/// Extend a JSON pointer with one more reference token
+pub fn pointer(base: &str, token: impl fmt::Display) -> String {
+    let token = token.to_string().replace('~', "~0").replace('/', "~1");
+    format!("{}/{}", base, token)
+}
+
+fn violation(pointer: &str, message: String, violations: &mut Vec<Violation>) {
+    violations.push(Violation {
+        pointer: pointer.into(),
+        message,
+    })
+}
+
+/// Check the number of characters, items or properties of a value
+pub fn check_count(
+    count: usize,
+    min: Option<usize>,
+    max: Option<usize>,
+    noun: &str,
+    pointer: &str,
+    violations: &mut Vec<Violation>,
+) {
+    match (min, max) {
+        (Some(min), _) if count < min => violation(
+            pointer,
+            format!("must have at least {} {}", min, noun),
+            violations,
+        ),
+        (_, Some(max)) if count > max => violation(
+            pointer,
+            format!("must have at most {} {}", max, noun),
+            violations,
+        ),
+        _ => {}
+    }
+}
+
+/// Check that a string matches a pattern. Patterns are not anchored
+pub fn check_pattern(
+    value: &str,
+    pattern: &'static str,
+    pointer: &str,
+    violations: &mut Vec<Violation>,
+) {
+    thread_local! {
+        static PATTERNS: RefCell<HashMap<&'static str, Regex>> = RefCell::new(HashMap::new());
+    }
+    let matched = PATTERNS.with(|patterns| {
+        patterns
+            .borrow_mut()
+            .entry(pattern)
+            .or_insert_with(|| Regex::new(pattern).expect("pattern was checked by hsr-codegen"))
+            .is_match(value)
+    });
+    if !matched {
+        violation(
+            pointer,
+            format!("must match pattern '{}'", pattern),
+            violations,
+        )
+    }
+}
+
+/// Check a lower bound on a number
+pub fn check_minimum<T: PartialOrd + fmt::Display>(
+    value: T,
+    minimum: T,
+    exclusive: bool,
+    pointer: &str,
+    violations: &mut Vec<Violation>,
+) {
+    if exclusive && value <= minimum {
+        violation(
+            pointer,
+            format!("must be greater than {}", minimum),
+            violations,
+        )
+    } else if value < minimum {
+        violation(pointer, format!("must be at least {}", minimum), violations)
+    }
+}
+
+/// Check an upper bound on a number
+pub fn check_maximum<T: PartialOrd + fmt::Display>(
+    value: T,
+    maximum: T,
+    exclusive: bool,
+    pointer: &str,
+    violations: &mut Vec<Violation>,
+) {
+    if exclusive && value >= maximum {
+        violation(
+            pointer,
+            format!("must be less than {}", maximum),
+            violations,
+        )
+    } else if value > maximum {
+        violation(pointer, format!("must be at most {}", maximum), violations)
+    }
+}
+
+/// Check that an integer (or decimal) is a multiple of another
+pub fn check_multiple_of<T>(value: T, multiple: T, pointer: &str, violations: &mut Vec<Violation>)
+where
+    T: std::ops::Rem<Output = T> + PartialEq + Default + Copy + fmt::Display,
+{
+    if value % multiple != T::default() {
+        violation(
+            pointer,
+            format!("must be a multiple of {}", multiple),
+            violations,
+        )
+    }
+}
+
+/// Check that a float is a multiple of another, allowing for rounding errors
+pub fn check_float_multiple_of(
+    value: f64,
+    multiple: f64,
+    pointer: &str,
+    violations: &mut Vec<Violation>,
+) {
+    if !is_multiple(value, multiple, 1e-9) {
+        violation(
+            pointer,
+            format!("must be a multiple of {}", multiple),
+            violations,
+        )
+    }
+}
+
+/// Check that an `f32` is a multiple of another, allowing for its larger rounding errors
+pub fn check_f32_multiple_of(
+    value: f32,
+    multiple: f32,
+    pointer: &str,
+    violations: &mut Vec<Violation>,
+) {
+    let tolerance = 4.0 * f64::from(f32::EPSILON);
+    if !is_multiple(value.into(), multiple.into(), tolerance) {
+        violation(
+            pointer,
+            format!("must be a multiple of {}", multiple),
+            violations,
+        )
+    }
+}
+
+fn is_multiple(value: f64, multiple: f64, tolerance: f64) -> bool {
+    let quotient = value / multiple;
+    (quotient - quotient.round()).abs() <= tolerance * quotient.abs().max(1.0)
+}
+
+/// Check that the items of an array are all different
+pub fn check_unique<T: PartialEq>(items: &[T], pointer: &str, violations: &mut Vec<Violation>) {
+    let duplicate = items
+        .iter()
+        .enumerate()
+        .any(|(ix, item)| items[..ix].contains(item));
+    if duplicate {
+        violation(
+            pointer,
+            "must not contain duplicate items".into(),
+            violations,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checks() {
+        let mut violations = Vec::new();
+        check_count(2, Some(3), None, "characters", "/a", &mut violations);
+        check_count(2, None, Some(2), "items", "/b", &mut violations);
+        check_pattern("abc", "^[a-z]+$", "/c", &mut violations);
+        check_pattern("ab1", "^[a-z]+$", "/d", &mut violations);
+        check_minimum(0i128, 0, true, "/e", &mut violations);
+        check_maximum(1.5f64, 1.5, false, "/f", &mut violations);
+        check_multiple_of(10i128, 3, "/g", &mut violations);
+        check_float_multiple_of(0.3, 0.1, "/h", &mut violations);
+        check_unique(&[1, 2, 1], "/i", &mut violations);
+        check_f32_multiple_of(19.99, 0.01, "/j", &mut violations);
+        check_maximum(0.1f32, 0.1, false, "/k", &mut violations);
+        check_f32_multiple_of(0.015, 0.01, "/l", &mut violations);
+        let found: Vec<_> = violations
+            .iter()
+            .map(|v| format!("{} {}", v.pointer, v.message))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                "/a must have at least 3 characters",
+                "/d must match pattern '^[a-z]+$'",
+                "/e must be greater than 0",
+                "/g must be a multiple of 3",
+                "/i must not contain duplicate items",
+                "/l must be a multiple of 0.01",
+            ]
+        );
+        assert_eq!(pointer("/body", "a/b~c"), "/body/a~1b~0c");
+    }
+}
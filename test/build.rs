@@ -2,12 +2,13 @@ use hsr_codegen;
 use std::io::Write;
 
 fn main() {
-    let code = hsr_codegen::generate_from_yaml_file("test-spec.yaml").expect("Generation failure");
+    let spec = hsr_codegen::Spec::load("test-spec.yaml").expect("Failed to load spec");
+    let code = hsr_codegen::generate_from_spec(&spec).expect("Generation failure");
 
     let out_dir = std::env::var("OUT_DIR").unwrap();
     let dest_path = std::path::Path::new(&out_dir).join("api.rs");
     let mut f = std::fs::File::create(&dest_path).unwrap();
 
     write!(f, "{}", code).unwrap();
-    println!("cargo:rerun-if-changed=test-spec.yaml");
+    spec.rerun_if_changed();
 }
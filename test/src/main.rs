@@ -2,6 +2,43 @@ use test::api::{self, client, server, TestApi};
 
 struct Api;
 
+#[hsr::async_trait::async_trait(?Send)]
+impl hsr::Authenticator for Api {
+    type Principal = String;
+
+    async fn authenticate(
+        &self,
+        credentials: &[(&str, hsr::Credential)],
+    ) -> Result<String, hsr::AuthError> {
+        use hsr::Credential::*;
+        match credentials {
+            [("api_key", ApiKey(key))] if key == "secret" => Ok("api-key-user".into()),
+            [("basic", Basic { username, password })] => {
+                if password.as_deref() == Some("password") {
+                    Ok(username.clone())
+                } else {
+                    Err(hsr::AuthError::Forbidden("Bad password".into()))
+                }
+            }
+            [("query_key", ApiKey(key)), ("session", ApiKey(session))] => {
+                Ok(format!("{}-{}", key, session))
+            }
+            [("bearer", Bearer(token))] => Ok(format!("bearer-{}", token)),
+            [("oauth", Bearer(token))] => Ok(format!("oauth-{}", token)),
+            _ => Err(hsr::AuthError::Unauthorized("Bad credentials".into())),
+        }
+    }
+
+    fn has_scope(&self, principal: &String, scope: &str) -> bool {
+        match principal.as_str() {
+            "oauth-rw" => scope == "pets:read" || scope == "pets:write",
+            "oauth-r" => scope == "pets:read",
+            "oauth-admin" => scope == "admin",
+            _ => false,
+        }
+    }
+}
+
 #[hsr::async_trait::async_trait(?Send)]
 impl TestApi for Api {
     async fn get_status(&self) -> api::GetStatus {
@@ -14,15 +51,70 @@ impl TestApi for Api {
 
     async fn two_path_params(&self, name: String, age: i64) -> api::TwoPathParams {
         api::TwoPathParams::Ok(api::Hello {
-            myName: name,
+            my_name: name,
             my_age: Some(age),
         })
     }
 
+    async fn archived_file(&self, file_name: String, version: i64) -> api::ArchivedFile {
+        api::ArchivedFile::Ok(api::Hello {
+            my_name: file_name,
+            my_age: Some(version),
+        })
+    }
+
     async fn two_query_params(&self, my_name: String, my_age: Option<i64>) -> api::TwoQueryParams {
-        api::TwoQueryParams::Ok(api::Hello {
-            myName: my_name,
-            my_age,
+        api::TwoQueryParams::Ok(api::Hello { my_name, my_age })
+    }
+
+    async fn path_level_params(&self, my_name: String, my_age: i64) -> api::PathLevelParams {
+        api::PathLevelParams::Ok(api::Hello {
+            my_name,
+            my_age: Some(my_age),
+        })
+    }
+
+    async fn same_name(&self, id: String, id_query: i64, id_header: String) -> api::SameName {
+        api::SameName::Ok(api::Hello {
+            my_name: format!("{} {}", id, id_header),
+            my_age: Some(id_query),
+        })
+    }
+
+    async fn header_params(
+        &self,
+        x_user_name: String,
+        x_user_age: Option<i64>,
+    ) -> api::HeaderParams {
+        api::HeaderParams::Ok(api::Hello {
+            my_name: x_user_name,
+            my_age: x_user_age,
+        })
+    }
+
+    async fn cookie_params(&self, user_name: String, user_age: Option<i64>) -> api::CookieParams {
+        api::CookieParams::Ok(api::Hello {
+            my_name: user_name,
+            my_age: user_age,
+        })
+    }
+
+    async fn whoami(&self, principal: String) -> api::Whoami {
+        api::Whoami::Ok(principal)
+    }
+
+    async fn maybe_whoami(&self, principal: Option<String>) -> api::MaybeWhoami {
+        api::MaybeWhoami::Ok(principal)
+    }
+
+    async fn whoami_admin(&self, principal: String) -> api::WhoamiAdmin {
+        api::WhoamiAdmin::Ok(principal)
+    }
+
+    async fn with_defaults(&self, count: i64) -> api::WithDefaults {
+        api::WithDefaults::Ok(api::Defaults {
+            count,
+            ..Default::default()
         })
     }
 
@@ -51,15 +143,72 @@ impl TestApi for Api {
         })
     }
 
+    async fn hellos_by_name(&self) -> api::HellosByName {
+        let mut hellos = std::collections::HashMap::new();
+        hellos.insert("alex".to_string(), hello());
+        api::HellosByName::Ok(hellos)
+    }
+
+    async fn echo_pet(&self, pet: api::Pet) -> api::EchoPet {
+        api::EchoPet::Ok(pet)
+    }
+
+    async fn echo_formats(&self, formats: api::Formats) -> api::EchoFormats {
+        api::EchoFormats::Ok(formats)
+    }
+
+    async fn echo_numbers(&self, numbers: api::Numbers) -> api::EchoNumbers {
+        api::EchoNumbers::Ok(numbers)
+    }
+
+    async fn echo_constrained(
+        &self,
+        _code: String,
+        _limit: Option<i64>,
+        constrained: api::Constrained,
+    ) -> api::EchoConstrained {
+        api::EchoConstrained::Ok(constrained)
+    }
+
+    async fn create_account(&self, account: api::AccountInput) -> api::CreateAccount {
+        assert_eq!(account.password, "secret");
+        api::CreateAccount::Ok(api::Account {
+            id: 1,
+            name: account.name,
+            friends: None,
+        })
+    }
+
     async fn anything_goes(&self, one_of: api::OneOfTest) -> api::AnythingGoes {
         api::AnythingGoes::Ok(one_of)
     }
+
+    async fn query_styles(
+        &self,
+        tags: Vec<String>,
+        ids: Option<Vec<i64>>,
+        words: Option<Vec<String>>,
+        flags: Option<Vec<bool>>,
+        filter: Option<api::Filter>,
+        color: Option<api::Color>,
+        point: Option<api::Point>,
+    ) -> api::QueryStyles {
+        api::QueryStyles::Ok(api::StyledQuery {
+            tags,
+            ids,
+            words,
+            flags,
+            filter,
+            color,
+            point,
+        })
+    }
 }
 
 // Quickly generate some data
 fn hello() -> api::Hello {
     api::Hello {
-        myName: "Alex".into(),
+        my_name: "Alex".into(),
         my_age: Some(33),
     }
 }
@@ -125,6 +274,61 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         assert_eq!(echo, api::TwoPathParams::Ok(hello()));
     }
 
+    {
+        // path parameters are percent-encoded, so reserved characters arrive intact
+        for name in &[
+            "a/b",
+            "what?",
+            "#1",
+            "100%",
+            "two words",
+            "a+b",
+            "Zoë",
+            "🦀",
+            "%2F",
+            "a..b",
+        ] {
+            let echo = client.two_path_params(name.to_string(), 1).await?;
+            let expect = api::Hello {
+                my_name: name.to_string(),
+                my_age: Some(1),
+            };
+            assert_eq!(echo, api::TwoPathParams::Ok(expect), "{}", name);
+        }
+    }
+
+    {
+        // a URL which can't be built is an error, not a panic
+        let bad = client::Client::new("mailto:nobody".parse().unwrap());
+        match bad.two_path_params("Alex".into(), 1).await {
+            Err(hsr::ClientError::Url(_)) => {}
+            other => panic!("Expected URL error, got {:?}", other),
+        }
+    }
+
+    {
+        // parameters may share a segment with literal text
+        let echo = client.archived_file("my-report.v2".into(), 3).await?;
+        let expect = api::Hello {
+            my_name: "my-report.v2".into(),
+            my_age: Some(3),
+        };
+        assert_eq!(echo, api::ArchivedFile::Ok(expect));
+
+        let resp = hsr::awc::Client::new()
+            .get("http://127.0.0.1:8000/v1/files/report.json/3:archive/")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        let resp = hsr::awc::Client::new()
+            .get("http://127.0.0.1:8000/v1/files/report.json/3:archive")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 404);
+    }
+
     {
         let echo = client
             .two_query_params("Alex".to_string(), Some(33))
@@ -135,10 +339,163 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         assert_eq!(
             echo,
             api::TwoQueryParams::Ok(api::Hello {
-                myName: "Alex".into(),
+                my_name: "Alex".into(),
+                my_age: None
+            })
+        );
+    }
+
+    {
+        let echo = client.path_level_params("Alex".to_string(), 33).await?;
+        assert_eq!(echo, api::PathLevelParams::Ok(hello()));
+
+        // the arguments are suffixed with their location
+        let echo = client
+            .same_name("Al".to_string(), 33, "ex".to_string())
+            .await?;
+        assert_eq!(
+            echo,
+            api::SameName::Ok(api::Hello {
+                my_name: "Al ex".into(),
+                my_age: Some(33)
+            })
+        );
+    }
+
+    {
+        let echo = client.header_params("Alex".to_string(), Some(33)).await?;
+        assert_eq!(echo, api::HeaderParams::Ok(hello()));
+
+        // header names are case-insensitive
+        let mut resp = hsr::awc::Client::new()
+            .get("http://127.0.0.1:8000/headerParams")
+            .header("X-USER-NAME", "Alex")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        let body: api::Hello = resp.json().await.unwrap();
+        assert_eq!(body.my_age, None);
+
+        // missing required header
+        let resp = hsr::awc::Client::new()
+            .get("http://127.0.0.1:8000/headerParams")
+            .header("x-user-age", "33")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 400);
+    }
+
+    {
+        let echo = client.cookie_params("Alex".to_string(), Some(33)).await?;
+        assert_eq!(echo, api::CookieParams::Ok(hello()));
+
+        let echo = client.cookie_params("A; lex".to_string(), None).await?;
+        assert_eq!(
+            echo,
+            api::CookieParams::Ok(api::Hello {
+                my_name: "A; lex".into(),
                 my_age: None
             })
         );
+
+        // missing required cookie
+        let resp = hsr::awc::Client::new()
+            .get("http://127.0.0.1:8000/cookieParams")
+            .header("Cookie", "user_age=33")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 400);
+    }
+
+    {
+        let rtn = client.whoami().await;
+        assert!(matches!(rtn, Err(hsr::ClientError::BadStatus(s)) if s == 401));
+
+        let rtn = client.clone().with_api_key("secret").whoami().await?;
+        assert_eq!(rtn, api::Whoami::Ok("api-key-user".into()));
+        let rtn = client.clone().with_api_key("wrong").whoami().await;
+        assert!(matches!(rtn, Err(hsr::ClientError::BadStatus(s)) if s == 401));
+
+        let rtn = client
+            .clone()
+            .with_basic("alex", Some("password".into()))
+            .whoami()
+            .await?;
+        assert_eq!(rtn, api::Whoami::Ok("alex".into()));
+        let rtn = client.clone().with_basic("alex", None).whoami().await;
+        assert!(matches!(rtn, Err(hsr::ClientError::BadStatus(s)) if s == 403));
+
+        // both schemes of a requirement are needed
+        let rtn = client.clone().with_query_key("key").whoami().await;
+        assert!(matches!(rtn, Err(hsr::ClientError::BadStatus(s)) if s == 401));
+        let rtn = client
+            .clone()
+            .with_query_key("key")
+            .with_session("sesh")
+            .whoami()
+            .await?;
+        assert_eq!(rtn, api::Whoami::Ok("key-sesh".into()));
+
+        let rtn = client.maybe_whoami().await?;
+        assert_eq!(rtn, api::MaybeWhoami::Ok(None));
+        let rtn = client.clone().with_bearer("token").maybe_whoami().await?;
+        assert_eq!(rtn, api::MaybeWhoami::Ok(Some("bearer-token".into())));
+    }
+
+    {
+        assert_eq!(
+            api::security::scopes::WHOAMI_ADMIN,
+            &[&["pets:read", "pets:write"][..], &["admin"][..]]
+        );
+
+        // either set of scopes will do
+        let rtn = client.clone().with_oauth("rw").whoami_admin().await?;
+        assert_eq!(rtn, api::WhoamiAdmin::Ok("oauth-rw".into()));
+        let rtn = client.clone().with_oauth("admin").whoami_admin().await?;
+        assert_eq!(rtn, api::WhoamiAdmin::Ok("oauth-admin".into()));
+
+        let mut resp = hsr::awc::Client::new()
+            .get("http://127.0.0.1:8000/whoami/admin")
+            .bearer_auth("r")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 403);
+        let body = resp.body().await.unwrap();
+        assert_eq!(body, "Forbidden: Missing required scopes: pets:write");
+    }
+
+    {
+        let dflt: api::Defaults = serde_json::from_str("{}").unwrap();
+        assert_eq!(dflt, api::Defaults::default());
+        assert_eq!(
+            dflt,
+            api::Defaults {
+                colour: "red".into(),
+                count: 10,
+                tags: vec!["a".into(), "b".into()]
+            }
+        );
+
+        let rtn = client.with_defaults(3).await?;
+        assert_eq!(
+            rtn,
+            api::WithDefaults::Ok(api::Defaults {
+                count: 3,
+                ..dflt.clone()
+            })
+        );
+
+        let mut resp = hsr::awc::Client::new()
+            .get("http://127.0.0.1:8000/defaults")
+            .send()
+            .await
+            .unwrap();
+        let body: api::Defaults = resp.json().await.unwrap();
+        assert_eq!(body.count, 5);
     }
 
     {
@@ -178,13 +535,466 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         );
     }
 
+    {
+        let cat = api::Pet::Cat(api::PetCat {
+            name: "Tom".into(),
+            lives: Some(9),
+        });
+        let rtn = client.echo_pet(cat.clone()).await?;
+        assert_eq!(rtn, api::EchoPet::Ok(cat.clone()));
+        assert_eq!(
+            serde_json::to_value(&cat).unwrap(),
+            serde_json::json!({"petType": "cat", "name": "Tom", "lives": 9})
+        );
+
+        // mapped aliases are accepted
+        let kitten: api::Pet =
+            serde_json::from_str(r#"{"petType": "kitten", "name": "Tom", "lives": 9}"#).unwrap();
+        assert_eq!(kitten, cat);
+
+        // unmapped variants are named after their schema
+        let dog = api::Pet::Dog(api::PetDog {
+            name: "Rex".into(),
+            good: Some(true),
+        });
+        let rtn = client.echo_pet(dog.clone()).await?;
+        assert_eq!(rtn, api::EchoPet::Ok(dog.clone()));
+        assert_eq!(serde_json::to_value(&dog).unwrap()["petType"], "Dog");
+
+        // the schemas keep the tag when used on their own
+        let json = serde_json::json!({"petType": "cat", "name": "Tom", "lives": 9});
+        let standalone: api::Cat = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(standalone.pet_type, "cat");
+        assert_eq!(serde_json::to_value(&standalone).unwrap(), json);
+
+        // a wrong or missing tag is rejected
+        let err =
+            serde_json::from_str::<api::Pet>(r#"{"petType": "Cow", "name": "Daisy"}"#).unwrap_err();
+        assert!(err.to_string().contains("unknown variant `Cow`"), "{}", err);
+        let err = serde_json::from_str::<api::Pet>(r#"{"name": "Daisy"}"#).unwrap_err();
+        assert!(
+            err.to_string().contains("missing field `petType`"),
+            "{}",
+            err
+        );
+
+        let resp = hsr::awc::Client::new()
+            .post("http://127.0.0.1:8000/pets")
+            .send_json(&serde_json::json!({"petType": "Cow", "name": "Daisy"}))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 400);
+    }
+
+    {
+        use hsr::chrono::TimeZone;
+        let formats = api::Formats {
+            created: hsr::chrono::Utc.ymd(2020, 4, 1).and_hms(12, 30, 0),
+            birthday: Some(hsr::chrono::NaiveDate::from_ymd(1990, 1, 31)),
+            id: "67e55044-10b1-426f-9247-bb680e5fe0c8".parse().unwrap(),
+            homepage: "https://example.com/home".parse().unwrap(),
+            data: hsr::Base64(b"hello".to_vec()),
+            address: Some(std::net::Ipv4Addr::LOCALHOST),
+            email: None,
+        };
+        let rtn = client.echo_formats(formats.clone()).await?;
+        assert_eq!(rtn, api::EchoFormats::Ok(formats.clone()));
+        assert_eq!(
+            serde_json::to_value(&formats).unwrap(),
+            serde_json::json!({
+                "created": "2020-04-01T12:30:00Z",
+                "birthday": "1990-01-31",
+                "id": "67e55044-10b1-426f-9247-bb680e5fe0c8",
+                "homepage": "https://example.com/home",
+                "data": "aGVsbG8=",
+                "address": "127.0.0.1",
+                "email": null,
+            })
+        );
+
+        // malformed values are rejected
+        let resp = hsr::awc::Client::new()
+            .post("http://127.0.0.1:8000/formats")
+            .send_json(&serde_json::json!({
+                "created": "yesterday",
+                "id": "67e55044-10b1-426f-9247-bb680e5fe0c8",
+                "homepage": "https://example.com/home",
+                "data": "aGVsbG8=",
+            }))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 400);
+    }
+
+    {
+        let numbers = api::Numbers {
+            small: -5i32,
+            count: 7u32,
+            big: Some(1i64 << 40),
+            ratio: 0.5f32,
+            price: "12345678901234567890.0001".parse().unwrap(),
+            discount: Some("0.10000000000000000001".parse().unwrap()),
+        };
+        let rtn = client.echo_numbers(numbers.clone()).await?;
+        assert_eq!(rtn, api::EchoNumbers::Ok(numbers.clone()));
+        // decimals keep their precision
+        assert_eq!(
+            serde_json::to_string(&numbers).unwrap(),
+            r#"{"small":-5,"count":7,"big":1099511627776,"ratio":0.5,"price":12345678901234567890.0001,"discount":0.10000000000000000001}"#
+        );
+        let parsed: api::Numbers = serde_json::from_str(
+            r#"{"small":0,"count":0,"ratio":0,"price":12345678901234567890.0001}"#,
+        )
+        .unwrap();
+        assert_eq!(parsed.price.to_string(), "12345678901234567890.0001");
+        assert_eq!(parsed.discount, None);
+
+        // out of range values are rejected
+        for bad in &[
+            serde_json::json!({"small": 1u64 << 31, "count": 1, "ratio": 1, "price": 1}),
+            serde_json::json!({"small": 1, "count": -1, "ratio": 1, "price": 1}),
+        ] {
+            let resp = hsr::awc::Client::new()
+                .post("http://127.0.0.1:8000/numbers")
+                .send_json(bad)
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), 400);
+        }
+    }
+
+    {
+        let constrained = api::Constrained {
+            name: "Rex".into(),
+            tags: vec!["good".into(), "dog".into()],
+            score: Some(2.5),
+            ranks: None,
+        };
+        let rtn = client
+            .echo_constrained("ABC".into(), Some(10), constrained.clone())
+            .await?;
+        assert_eq!(rtn, api::EchoConstrained::Ok(constrained.clone()));
+        assert!(hsr::Validate::validate(&constrained).is_ok());
+
+        // the server rejects requests which break the constraints
+        let bad = serde_json::json!({
+            "name": "",
+            "tags": ["ok", "Bad", "ok"],
+            "score": 0.3,
+            "ranks": {"a": 1, "b": 2, "c": 11},
+        });
+        let mut resp = hsr::awc::Client::new()
+            .post("http://127.0.0.1:8000/constrained/abcd?limit=0")
+            .send_json(&bad)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 400);
+        let violations: Vec<hsr::Violation> = resp.json().await.unwrap();
+        let found: Vec<_> = violations
+            .iter()
+            .map(|v| format!("{} {}", v.pointer, v.message))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                "/path/code must match pattern '^[A-Z]{3}$'",
+                "/query/limit must be at least 1",
+                "/body/name must have at least 1 characters",
+                "/body/tags must not contain duplicate items",
+                "/body/tags/1 must match pattern '^[a-z]+$'",
+                "/body/score must be a multiple of 0.5",
+                "/body/ranks must have at most 2 properties",
+                "/body/ranks/c must be at most 10",
+            ]
+        );
+
+        // the client can refuse to send them
+        let bad: api::Constrained = serde_json::from_value(bad).unwrap();
+        let rtn = client
+            .clone()
+            .validate_requests(true)
+            .echo_constrained("ABC".into(), None, bad.clone())
+            .await;
+        assert!(matches!(rtn, Err(hsr::ClientError::Invalid(ref v)) if v.0.len() == 6));
+        // but it sends them by default
+        let rtn = client.echo_constrained("ABC".into(), None, bad).await;
+        assert!(matches!(rtn, Err(hsr::ClientError::BadStatus(s)) if s == 400));
+    }
+
+    {
+        // the id is only in the response, the password only in the request
+        let friend = api::AccountInput {
+            name: "Bob".into(),
+            password: "hunter2".into(),
+            friends: None,
+        };
+        let account = api::AccountInput {
+            name: "Alice".into(),
+            password: "secret".into(),
+            friends: Some(vec![friend]),
+        };
+        assert_eq!(
+            serde_json::to_value(&account).unwrap()["friends"][0],
+            serde_json::json!({"name": "Bob", "password": "hunter2", "friends": null})
+        );
+        let rtn = client.create_account(account).await?;
+        let created = api::Account {
+            id: 1,
+            name: "Alice".into(),
+            friends: None,
+        };
+        assert_eq!(rtn, api::CreateAccount::Ok(created.clone()));
+        assert!(serde_json::to_value(&created)
+            .unwrap()
+            .get("password")
+            .is_none());
+    }
+
+    {
+        let root = api::TreeNode {
+            value: 1,
+            children: None,
+            parent: None,
+        };
+        let node = api::TreeNode {
+            value: 2,
+            children: Some(vec![root.clone()]),
+            parent: Some(Box::new(root)),
+        };
+        let json = serde_json::json!({
+            "value": 2,
+            "children": [{"value": 1, "children": null, "parent": null}],
+            "parent": {"value": 1, "children": null, "parent": null},
+        });
+        assert_eq!(serde_json::to_value(&node).unwrap(), json);
+        assert_eq!(serde_json::from_value::<api::TreeNode>(json).unwrap(), node);
+
+        let json = serde_json::json!({"left": 1, "right": {"left": 2, "right": 3}});
+        let expr: api::Expr = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&expr).unwrap(), json);
+    }
+
+    {
+        let json = serde_json::json!({
+            "first-name": "Alex",
+            "@type": "person",
+            "type": "admin",
+            "2fa": true,
+            "userId": 1,
+            "user_id": 2,
+        });
+        let awkward: api::AwkwardNames = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(
+            awkward,
+            api::AwkwardNames {
+                first_name: "Alex".into(),
+                type_2: "person".into(),
+                r#type: "admin".into(),
+                field_2fa: true,
+                user_id_2: 1,
+                user_id: 2,
+            }
+        );
+        assert_eq!(serde_json::to_value(&awkward).unwrap(), json);
+    }
+
+    {
+        // the child of an allOf converts into its parent
+        let hello: api::Hello = all_of_test().into();
+        assert_eq!(hello.my_name, "Alex");
+
+        let json = serde_json::json!({
+            "myName": "Alex",
+            "my_age": null,
+            "height": 1.88,
+            "favourite_colour": "blue",
+        });
+        let refined: api::RefinedAllOf = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(refined.favourite_colour, "blue");
+        assert_eq!(serde_json::to_value(&refined).unwrap()["height"], 1.88);
+        let parent = api::AllOfTest::from(refined);
+        assert_eq!(parent.favourite_colour, Some("blue".into()));
+        let missing = serde_json::json!({"myName": "Alex", "height": 1.88});
+        assert!(serde_json::from_value::<api::RefinedAllOf>(missing).is_err());
+
+        let json = serde_json::json!({"myName": "Alex", "my_age": 33, "side": 2});
+        let shaped: api::ShapedHello = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(shaped.my_age, Some(33));
+        assert_eq!(serde_json::to_value(&shaped).unwrap(), json);
+        let hello: api::Hello = shaped.into();
+        assert_eq!(hello.my_age, Some(33));
+    }
+
+    {
+        use std::convert::TryFrom;
+        let json = serde_json::json!({"size": -5, "ratio": 1.5, "flag": true});
+        let enums: api::Enums = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(enums.size, api::EnumsSize::ValueMinus5);
+        assert_eq!(enums.ratio, api::EnumsRatio::Value1Point5);
+        assert_eq!(enums.flag, api::EnumsFlag::True);
+        assert_eq!(serde_json::to_value(&enums).unwrap(), json);
+
+        assert_eq!(api::EnumsSize::try_from(2), Ok(api::EnumsSize::Value2));
+        assert_eq!(i64::from(api::EnumsSize::Value1), 1);
+        assert!(api::EnumsSize::try_from(3).is_err());
+        for bad in &[
+            serde_json::json!({"size": 3, "ratio": 1.5, "flag": true}),
+            serde_json::json!({"size": 1, "ratio": 1.0, "flag": true}),
+            serde_json::json!({"size": 1, "ratio": 1.5, "flag": false}),
+        ] {
+            assert!(serde_json::from_value::<api::Enums>(bad.clone()).is_err());
+        }
+    }
+
     {
         // TODO I doubt this is being serialized properly. Need to send as 'untagged'
-        let payload = api::OneOfTest::V1(hello());
+        let payload = api::OneOfTest::Hello(hello());
         let body = client.anything_goes(payload.clone()).await?;
         assert_eq!(body, api::AnythingGoes::Ok(payload));
     }
 
+    {
+        let styled = api::StyledQuery {
+            tags: vec!["a b".into(), "c,d".into()],
+            ids: Some(vec![1, 2]),
+            words: Some(vec!["x".into(), "y|z".into()]),
+            flags: Some(vec![true, false]),
+            filter: Some(api::Filter {
+                status: "sold out".into(),
+                limit: Some(10),
+            }),
+            color: Some(api::Color { r: 100, g: 200 }),
+            point: Some(api::Point { x: 1.5, y: -2.0 }),
+        };
+        let api::StyledQuery {
+            tags,
+            ids,
+            words,
+            flags,
+            filter,
+            color,
+            point,
+        } = styled.clone();
+        let rtn = client
+            .query_styles(tags, ids, words, flags, filter, color, point)
+            .await?;
+        assert_eq!(rtn, api::QueryStyles::Ok(styled));
+
+        // empty delimited arrays are sent, and missing parameters are left out
+        let rtn = client
+            .query_styles(vec!["a".into()], Some(vec![]), None, None, None, None, None)
+            .await?;
+        let expect = api::StyledQuery {
+            tags: vec!["a".into()],
+            ids: Some(vec![]),
+            words: None,
+            flags: None,
+            filter: None,
+            color: None,
+            point: None,
+        };
+        assert_eq!(rtn, api::QueryStyles::Ok(expect));
+
+        // values in the format of the spec's examples are understood
+        let mut resp = hsr::awc::Client::new()
+            .get(
+                "http://127.0.0.1:8000/queryStyles?tags=blue&tags=black&ids=3,4\
+                 &words=blue%20black&flags=true|true&filter[status]=new\
+                 &color=r,100,g,200&x=1&y=2",
+            )
+            .send()
+            .await
+            .unwrap();
+        let body: api::StyledQuery = resp.json().await.unwrap();
+        let expect = api::StyledQuery {
+            tags: vec!["blue".into(), "black".into()],
+            ids: Some(vec![3, 4]),
+            words: Some(vec!["blue".into(), "black".into()]),
+            flags: Some(vec![true, true]),
+            filter: Some(api::Filter {
+                status: "new".into(),
+                limit: None,
+            }),
+            color: Some(api::Color { r: 100, g: 200 }),
+            point: Some(api::Point { x: 1.0, y: 2.0 }),
+        };
+        assert_eq!(body, expect);
+
+        for bad in &["", "?ids=1", "?tags=a&ids=1,x", "?tags=a&color=r,1,g"] {
+            let resp = hsr::awc::Client::new()
+                .get(format!("http://127.0.0.1:8000/queryStyles{}", bad))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), 400);
+        }
+    }
+
+    {
+        let rtn = client.hellos_by_name().await?;
+        let mut hellos = std::collections::HashMap::new();
+        hellos.insert("alex".to_string(), hello());
+        assert_eq!(rtn, api::HellosByName::Ok(hellos));
+
+        // additional properties are kept alongside the declared ones
+        let json = serde_json::json!({"name": "x", "colour": "red", "size": 3});
+        let props: api::HasAdditionalProps = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(props.name, "x");
+        assert_eq!(props.extra.len(), 2);
+        assert_eq!(props.extra["colour"], "red");
+        assert_eq!(serde_json::to_value(&props).unwrap(), json);
+
+        // or rejected
+        assert!(serde_json::from_value::<api::NoAdditionalProps>(json).is_err());
+        let ok = serde_json::json!({"name": "x"});
+        assert!(serde_json::from_value::<api::NoAdditionalProps>(ok).is_ok());
+    }
+
+    {
+        // every alternative which fits is filled in
+        let both: api::AnyOfTest =
+            serde_json::from_str(r#"{"myName": "Alex", "my_age": 33, "height": 1.88}"#).unwrap();
+        assert_eq!(
+            both,
+            api::AnyOfTest {
+                hello: Some(hello()),
+                measurements: Some(api::Measurements { height: 1.88 }),
+                string: None,
+            }
+        );
+        assert_eq!(
+            serde_json::to_value(&both).unwrap(),
+            serde_json::json!({"myName": "Alex", "my_age": 33, "height": 1.88})
+        );
+
+        let string: api::AnyOfTest = serde_json::from_str(r#""hello""#).unwrap();
+        assert_eq!(string.string.as_deref(), Some("hello"));
+        assert!(string.hello.is_none() && string.measurements.is_none());
+
+        let err = serde_json::from_str::<api::AnyOfTest>("[1, 2]").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("data did not match any schema of anyOf 'AnyOfTest'"));
+    }
+
+    {
+        // variants are named after their schema, title or type
+        let names = vec![
+            api::VariantNames::Hello(hello()),
+            api::VariantNames::Greeting(hello()),
+            api::VariantNames::String("a".into()),
+            api::VariantNames::String2("b".into()),
+            api::VariantNames::Count(1),
+            api::VariantNames::Bool(true),
+            api::VariantNames::Array(vec![1.5]),
+        ];
+        let json = serde_json::to_string(&names).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"myName":"Alex","my_age":33},{"myName":"Alex","my_age":33},"a","b",1,true,[1.5]]"#
+        );
+    }
+
     println!("Success");
 
     Ok(())